@@ -0,0 +1,121 @@
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+
+///Network settings for the demo, kept in a config file between runs.
+///
+/// ```text
+/// # freed-demo settings
+/// bind = 127.0.0.1:40000
+/// address = 127.0.0.1
+/// port = 40000
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Settings {
+    pub local: SocketAddr,
+    pub address: IpAddr,
+    pub port: u16,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            local: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 40000),
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 40000,
+        }
+    }
+}
+
+impl Settings {
+    ///Parses a config file, keeping the default for any setting it leaves out.
+    pub fn parse(text: &str) -> Result<Settings, String> {
+        let mut settings = Settings::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let error = |x: String| format!("line {}: {}", number + 1, x);
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| error(format!("expected key = value, found {}", line)))?;
+            let value = value.trim();
+            match key.trim() {
+                "bind" => settings.local = value.parse().map_err(|_| error(format!("{} is not an ADDRESS:PORT", value)))?,
+                "address" => settings.address = value.parse().map_err(|_| error(format!("{} is not an address", value)))?,
+                "port" => settings.port = value.parse().map_err(|_| error(format!("{} is not a port", value)))?,
+                x => return Err(error(format!("unknown setting {}", x))),
+            }
+        }
+        Ok(settings)
+    }
+
+    ///Reads the settings from `path`, or the defaults if it doesn't exist yet.
+    pub fn load(path: &Path) -> Result<Settings, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => Settings::parse(&text).map_err(|x| format!("{}: {}", path.display(), x)),
+            Err(x) if x.kind() == std::io::ErrorKind::NotFound => Ok(Settings::default()),
+            Err(x) => Err(format!("could not read {}: {}", path.display(), x)),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|x| format!("could not create {}: {}", parent.display(), x))?;
+        }
+        let text = format!("# freed-demo settings\nbind = {}\naddress = {}\nport = {}\n", self.local, self.address, self.port);
+        fs::write(path, text).map_err(|x| format!("could not write {}: {}", path.display(), x))
+    }
+}
+
+///Where the settings are kept when no `--config` is given: `freed-demo.conf` in the user's config directory.
+pub fn default_path() -> Option<PathBuf> {
+    let directory = match std::env::var_os("XDG_CONFIG_HOME").or_else(|| std::env::var_os("APPDATA")) {
+        Some(x) => PathBuf::from(x),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(directory.join("freed-demo.conf"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let text = "# freed-demo settings\n\nbind = [::1]:40001\n  address=192.168.0.20   # the unit\nport = 6301\n";
+        let settings = Settings::parse(text).unwrap();
+        assert_eq!(settings.local, "[::1]:40001".parse().unwrap());
+        assert_eq!(settings.address, "192.168.0.20".parse::<IpAddr>().unwrap());
+        assert_eq!(settings.port, 6301);
+
+        //settings left out keep their defaults
+        let settings = Settings::parse("port = 1").unwrap();
+        assert_eq!(settings, Settings { port: 1, ..Settings::default() });
+        assert_eq!(Settings::parse("# nothing set\n").unwrap(), Settings::default());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(Settings::parse("port = 1\ncolour = red"), Err("line 2: unknown setting colour".to_string()));
+        assert_eq!(Settings::parse("port 1"), Err("line 1: expected key = value, found port 1".to_string()));
+        assert_eq!(Settings::parse("port = 65536"), Err("line 1: 65536 is not a port".to_string()));
+        assert_eq!(Settings::parse("bind = 127.0.0.1"), Err("line 1: 127.0.0.1 is not an ADDRESS:PORT".to_string()));
+        assert_eq!(Settings::parse("address = localhost"), Err("line 1: localhost is not an address".to_string()));
+    }
+
+    #[test]
+    fn load_and_save() {
+        let directory = std::env::temp_dir().join(format!("freed-demo-config-{}", std::process::id()));
+        let path = directory.join("nested").join("freed-demo.conf");
+        assert_eq!(Settings::load(&path), Ok(Settings::default()));
+
+        let settings = Settings { address: "10.0.0.2".parse().unwrap(), port: 6301, ..Settings::default() };
+        settings.save(&path).unwrap();
+        assert_eq!(Settings::load(&path), Ok(settings));
+
+        fs::write(&path, "port = none\n").unwrap();
+        assert_eq!(Settings::load(&path), Err(format!("{}: line 1: none is not a port", path.display())));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,17 +1,25 @@
+mod config;
 mod payloadui;
+mod receive;
 
-use std::fmt::{Display, format};
-use std::net::{UdpSocket, IpAddr, Ipv4Addr, SocketAddr};
+use std::fmt::Display;
+use std::net::{IpAddr, SocketAddr};
 use std::ops::Not;
-use crossterm::terminal::{Clear, ClearType, ScrollUp, EnterAlternateScreen, LeaveAlternateScreen};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen};
 use crossterm::{self, event, execute};
-use crossterm::event::{read,Event,KeyCode, KeyEvent, MouseEvent};
-use freed::payloads::{PositionPollPayload, PollPayload, SystemStatusPayload, SystemControlPayload, TargetDataPayload, ImageDataPayload, EEPROMDataPayload, EEPROMDataRequestPayload, CameraCalibrationPayload, DiagnosticModePayload};
+use crossterm::event::{Event,KeyCode, KeyEvent};
+use freed::common::Serialise;
+use freed::payloads::{Message, Payloads, PositionPollPayload, PollPayload, SystemStatusPayload, SystemControlPayload, TargetDataPayload, ImageDataPayload, EEPROMDataPayload, EEPROMDataRequestPayload, CameraCalibrationPayload, DiagnosticModePayload};
 use payloadui::StructUI;
+use config::Settings;
+use receive::{Incoming, Link, RateMeter};
 use tui::Frame;
+use tui::style::{Color, Style};
 use tui::widgets::{Paragraph, Wrap};
-use tui::{backend::CrosstermBackend, widgets::{Widget, Block, Borders},layout::{Layout, Constraint, Direction}, Terminal};
-use freed;
+use tui::{backend::CrosstermBackend, widgets::{Block, Borders},layout::{Layout, Constraint, Direction}, Terminal};
 
 struct CleanUp(std::io::Stdout);
 impl Drop for CleanUp {
@@ -27,8 +35,11 @@ struct Status {
     payload_mode: PayloadModes,
     payload_index: Option<i32>,
     payload_history: [PayloadModes; 10],
-    address: IpAddr,
-    port: u16,
+    settings: Settings,
+    setting: Option<Setting>,
+    cameraid: u8,
+    stream_rate: f64,
+    sent_rate: f64,
 }
 
 impl Status {
@@ -61,14 +72,86 @@ impl Status {
         self.payload_index
     }
 
+    ///Applies the text typed for the highlighted field to the current payload.
+    pub fn apply_edit(&mut self, text: &str) -> Result<(), String> {
+        let index = self.payload_index.ok_or("No field is selected")?;
+        self.payload_mode = self.payload_mode.with_field(index as usize, text)?;
+        Ok(())
+    }
+
+    ///Steps the stream rate up or down through `STREAM_RATES`.
+    pub fn change_stream_rate(&mut self, faster: bool) {
+        let next = match faster {
+            true => STREAM_RATES.iter().find(|x| **x > self.stream_rate),
+            false => STREAM_RATES.iter().rev().find(|x| **x < self.stream_rate),
+        };
+        if let Some(rate) = next {
+            self.stream_rate = *rate;
+        }
+    }
+
+    ///Sends the current payload to the configured address and port.
+    pub fn send(&self, link: Option<&Link>) -> Result<(), String> {
+        let link = link.ok_or("Not bound to a local address")?;
+        let message = Message::new(Payloads::from(self.payload_mode), self.cameraid);
+        link.socket.send_to(&message.serialise(), SocketAddr::new(self.settings.address, self.settings.port)).map_err(|x| x.to_string())?;
+        Ok(())
+    }
+
+    ///Applies the text typed for a network setting, rebinding the socket if the local address changed. If the new
+    /// address can't be bound the old one is bound again.
+    pub fn apply_setting(&mut self, setting: Setting, text: &str, link: &mut Option<Link>) -> Result<(), String> {
+        match setting {
+            Setting::Address => self.settings.address = text.parse().map_err(|_| format!("{} is not an address", text))?,
+            Setting::Port => self.settings.port = text.parse().map_err(|_| format!("{} is not a port", text))?,
+            Setting::Bind => {
+                let local: SocketAddr = text.parse().map_err(|_| format!("{} is not an ADDRESS:PORT", text))?;
+                //the old socket has to be closed first in case the new address overlaps it
+                *link = None;
+                match Link::bind(local) {
+                    Ok(x) => *link = Some(x),
+                    Err(x) => {
+                        *link = Link::bind(self.settings.local).ok();
+                        return Err(format!("Could not bind {}: {}", local, x));
+                    }
+                }
+                self.settings.local = local;
+            }
+        }
+        Ok(())
+    }
+
+}
+
+///The network settings that can be edited in the header, selected in turn with F5.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Setting {
+    Bind,
+    Address,
+    Port,
 }
 
+impl Setting {
+    fn next(setting: Option<Setting>) -> Option<Setting> {
+        match setting {
+            None => Some(Setting::Bind),
+            Some(Setting::Bind) => Some(Setting::Address),
+            Some(Setting::Address) => Some(Setting::Port),
+            Some(Setting::Port) => None,
+        }
+    }
+}
+
+//Rates in Hz that F3 and F4 step through in stream mode, covering common video frame rates.
+const STREAM_RATES: [f64; 9] = [1.0, 5.0, 10.0, 24.0, 25.0, 30.0, 50.0, 60.0, 100.0];
+
 impl Default for Status {
     fn default() -> Self {
         let payloadhistory = PayloadModes::array();
 
         Status {operating_mode: OperatingModes::FreezeMode, payload_mode: PayloadModes::PositionPollPayload(PositionPollPayload::default()),
-        payload_index: None, payload_history: payloadhistory, address: IpAddr::V4(Ipv4Addr::LOCALHOST), port: 40000}
+        payload_index: None, payload_history: payloadhistory, settings: Settings::default(), setting: None, cameraid: 1,
+        stream_rate: 50.0, sent_rate: 0.0}
     }
 }
 
@@ -98,6 +181,7 @@ impl Display for OperatingModes {
     }
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Copy, Clone, Debug)]
 enum PayloadModes {
     PollPayload(freed::payloads::PollPayload),
@@ -143,6 +227,40 @@ impl PayloadModes {
 
 }
 
+impl From<Payloads> for PayloadModes {
+    fn from(payload: Payloads) -> Self {
+        match payload {
+            Payloads::PollPayload(x) => PayloadModes::PollPayload(x),
+            Payloads::PositionPollPayload(x) => PayloadModes::PositionPollPayload(x),
+            Payloads::SystemStatusPayload(x) => PayloadModes::SystemStatusPayload(x),
+            Payloads::SystemControlPayload(x) => PayloadModes::SystemControlPayload(x),
+            Payloads::TargetDataPayload(x) => PayloadModes::TargetDataPayload(x),
+            Payloads::ImageDataPayload(x) => PayloadModes::ImageDataPayload(x),
+            Payloads::EEPROMDataPayload(x) => PayloadModes::EEPROMDataPayload(x),
+            Payloads::EEPROMDataRequestPayload(x) => PayloadModes::EEPROMDataRequestPayload(x),
+            Payloads::CameraCalibrationPayload(x) => PayloadModes::CameraCalibrationPayload(x),
+            Payloads::DiagnosticModePayload(x) => PayloadModes::DiagnosticModePayload(x),
+        }
+    }
+}
+
+impl From<PayloadModes> for Payloads {
+    fn from(payload: PayloadModes) -> Self {
+        match payload {
+            PayloadModes::PollPayload(x) => Payloads::PollPayload(x),
+            PayloadModes::PositionPollPayload(x) => Payloads::PositionPollPayload(x),
+            PayloadModes::SystemStatusPayload(x) => Payloads::SystemStatusPayload(x),
+            PayloadModes::SystemControlPayload(x) => Payloads::SystemControlPayload(x),
+            PayloadModes::TargetDataPayload(x) => Payloads::TargetDataPayload(x),
+            PayloadModes::ImageDataPayload(x) => Payloads::ImageDataPayload(x),
+            PayloadModes::EEPROMDataPayload(x) => Payloads::EEPROMDataPayload(x),
+            PayloadModes::EEPROMDataRequestPayload(x) => Payloads::EEPROMDataRequestPayload(x),
+            PayloadModes::CameraCalibrationPayload(x) => Payloads::CameraCalibrationPayload(x),
+            PayloadModes::DiagnosticModePayload(x) => Payloads::DiagnosticModePayload(x),
+        }
+    }
+}
+
 impl Display for PayloadModes {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", match self {
@@ -178,6 +296,21 @@ impl StructUI for PayloadModes {
         }
     }
 
+    fn with_field(self, index: usize, text: &str) -> Result<Self, String> {
+        match self {
+            PayloadModes::CameraCalibrationPayload(a) => Ok(PayloadModes::CameraCalibrationPayload(a.with_field(index, text)?)),
+            PayloadModes::PollPayload(a) => Ok(PayloadModes::PollPayload(a.with_field(index, text)?)),
+            PayloadModes::PositionPollPayload(a) => Ok(PayloadModes::PositionPollPayload(a.with_field(index, text)?)),
+            PayloadModes::SystemStatusPayload(a) => Ok(PayloadModes::SystemStatusPayload(a.with_field(index, text)?)),
+            PayloadModes::SystemControlPayload(a) => Ok(PayloadModes::SystemControlPayload(a.with_field(index, text)?)),
+            PayloadModes::TargetDataPayload(a) => Ok(PayloadModes::TargetDataPayload(a.with_field(index, text)?)),
+            PayloadModes::ImageDataPayload(a) => Ok(PayloadModes::ImageDataPayload(a.with_field(index, text)?)),
+            PayloadModes::EEPROMDataPayload(a) => Ok(PayloadModes::EEPROMDataPayload(a.with_field(index, text)?)),
+            PayloadModes::EEPROMDataRequestPayload(a) => Ok(PayloadModes::EEPROMDataRequestPayload(a.with_field(index, text)?)),
+            PayloadModes::DiagnosticModePayload(a) => Ok(PayloadModes::DiagnosticModePayload(a.with_field(index, text)?)),
+        }
+    }
+
     fn draw_fields_as_table<B>(self, f: &mut Frame<B>, index: Option<i32>, area: tui::layout::Rect) where B: tui::backend::Backend {
         match self {
             PayloadModes::CameraCalibrationPayload(a) => a.draw_fields_as_table(f, index, area),
@@ -198,36 +331,69 @@ impl StructUI for PayloadModes {
 //Maximum of 100 columns!
 fn even_columns(columns: usize) -> Vec<Constraint> {
     let percent = 100/columns;
-    std::iter::repeat(Constraint::Percentage(percent as u16)).take(columns).collect()
+    std::iter::repeat_n(Constraint::Percentage(percent as u16), columns).collect()
 }
 
 
-fn ui<B: tui::backend::Backend>(f: &mut Frame<B>, status: Status) {
+//Draws the latest message of each type received, one table per type, under a line of counters.
+fn draw_incoming<B: tui::backend::Backend>(f: &mut Frame<B>, incoming: &Incoming, area: tui::layout::Rect) {
+    let split = Layout::default().direction(Direction::Vertical)
+    .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref()).split(area);
+
+    let camera = match incoming.lastcamera {Some(x) => format!("last from camera {}", x), None => "nothing yet".to_string()};
+    let mut counters = format!("{} received, {} errors, {:.1}/s, {}", incoming.received, incoming.errors, incoming.rate.rate, camera);
+    if let Some(error) = &incoming.lasterror {
+        counters = format!("{}\nlast error: {}", counters, error);
+    }
+    f.render_widget(Paragraph::new(counters).wrap(Wrap {trim: true}), split[0]);
+
+    let payloads: Vec<PayloadModes> = incoming.latest.iter().flatten().copied().collect();
+    if payloads.is_empty() {
+        return;
+    }
+    let tables = Layout::default().direction(Direction::Vertical).constraints(even_columns(payloads.len())).split(split[1]);
+    for (payload, area) in payloads.into_iter().zip(tables) {
+        payload.draw_fields_as_table(f, None, area);
+    }
+}
+
+fn ui<B: tui::backend::Backend>(f: &mut Frame<B>, status: Status, incoming: &Incoming, input: &str, notice: &str) {
 
     let main = Layout::default().direction(Direction::Vertical)
     .constraints([Constraint::Percentage(20), Constraint::Percentage(80), ].as_ref()).split(f.size());
     
-    let inoutsplit = Layout::default().direction(Direction::Horizontal).margin(0).constraints(even_columns(2).as_ref()).split(main[1]);
+    let inoutsplit = Layout::default().direction(Direction::Horizontal).margin(0).constraints(even_columns(2)).split(main[1]);
     
     let outstructblock = Block::default().title("Data Out").borders(Borders::ALL);
     let innerarea = outstructblock.inner(inoutsplit[0]);
     f.render_widget(outstructblock, inoutsplit[0]);
+    let outsplit = Layout::default().direction(Direction::Vertical)
+    .constraints([Constraint::Min(0), Constraint::Length(4)].as_ref()).split(innerarea);
     let payload = status.payload_mode;
-    payload.draw_fields_as_table(f, status.payload_index, innerarea);
+    payload.draw_fields_as_table(f, status.payload_index, outsplit[0]);
+
+    let edittext = format!("Edit: {}\n{}\nEnter to apply, Esc to cancel, F1 stream or freeze, F2 send, F3/F4 stream rate, F5 network settings", input, notice);
+    f.render_widget(Paragraph::new(edittext).wrap(Wrap {trim: true}), outsplit[1]);
     
 
     // let outstructtext = Paragraph::new(format!("{:?}", status.payload_mode)).block(outstructblock).wrap(Wrap {trim: true});
     // f.render_widget(outstructtext, inoutsplit[0]);
 
     let instructblock = Block::default().title("Data In").borders(Borders::ALL);
+    let incomingarea = instructblock.inner(inoutsplit[1]);
     f.render_widget(instructblock, inoutsplit[1]);
+    draw_incoming(f, incoming, incomingarea);
 
     let chunks = Layout::default().direction(Direction::Horizontal)
-    .constraints(even_columns(4).as_ref()).split(main[0]);
+    .constraints(even_columns(5)).split(main[0]);
     
 
     let pollblock = Block::default().title("Poll Mode").borders(Borders::ALL);
-    let polltext = tui::widgets::Paragraph::new(status.operating_mode.to_string()).block(pollblock);
+    let polltext = match status.operating_mode {
+        OperatingModes::StreamMode => format!("{}\n{} Hz, {:.1} sent/s", status.operating_mode, status.stream_rate, status.sent_rate),
+        OperatingModes::FreezeMode => format!("{}\n{:.1} sent/s", status.operating_mode, status.sent_rate),
+    };
+    let polltext = tui::widgets::Paragraph::new(polltext).block(pollblock);
     f.render_widget(polltext, chunks[0]);
 
 
@@ -235,23 +401,133 @@ fn ui<B: tui::backend::Backend>(f: &mut Frame<B>, status: Status) {
     let payloadtext = Paragraph::new(status.payload_mode.to_string()).block(payloadblock);
     f.render_widget(payloadtext, chunks[1]);
     
-    let addressblock = Block::default().title("Address").borders(Borders::ALL);
-    let addresstext = Paragraph::new(status.address.to_string()).block(addressblock);
-    f.render_widget(addresstext, chunks[2]);
+    //the setting being edited is highlighted the same way as the selected field
+    let settingstyle = |setting: Setting| match status.setting == Some(setting) {
+        true => Style::default().fg(Color::Black).bg(Color::White),
+        false => Style::default(),
+    };
 
-    let portblock = Block::default().title("Port").borders(Borders::ALL);
-    let porttext = Paragraph::new(status.port.to_string()).block(portblock);
-    f.render_widget(porttext, chunks[3]);
+    let bindblock = Block::default().title("Bind").borders(Borders::ALL).border_style(settingstyle(Setting::Bind));
+    let bindtext = Paragraph::new(status.settings.local.to_string()).block(bindblock);
+    f.render_widget(bindtext, chunks[2]);
+
+    let addressblock = Block::default().title("Address").borders(Borders::ALL).border_style(settingstyle(Setting::Address));
+    let addresstext = Paragraph::new(status.settings.address.to_string()).block(addressblock);
+    f.render_widget(addresstext, chunks[3]);
+
+    let portblock = Block::default().title("Port").borders(Borders::ALL).border_style(settingstyle(Setting::Port));
+    let porttext = Paragraph::new(status.settings.port.to_string()).block(portblock);
+    f.render_widget(porttext, chunks[4]);
 
 
 }
     
 
-fn main() -> Result<(), std::io::Error> {
-    let mut status = Status::default();
-    let mut inputBuffer = Vec::<char>::new();
+const USAGE: &str = "usage: freed-demo [--bind ADDRESS:PORT] [--address ADDRESS] [--port PORT] [--config FILE]
+
+A terminal UI for building free-d payloads by hand, sending them and showing what is received.
 
-    let socket = UdpSocket::bind(SocketAddr::new(status.address, status.port)).unwrap();
+  --bind ADDRESS:PORT  local address to bind and receive on (default 127.0.0.1:40000)
+  --address ADDRESS    address to send to (default 127.0.0.1)
+  --port PORT          port to send to (default 40000)
+  --config FILE        settings file, read at startup and written whenever the settings change
+                       (default freed-demo.conf in the user's config directory)
+
+Settings given as arguments override the settings file, and can be changed while running with F5.";
+
+struct Options {
+    config: Option<PathBuf>,
+    local: Option<SocketAddr>,
+    address: Option<IpAddr>,
+    port: Option<u16>,
+}
+
+///Parses the command line, or returns `None` if usage was asked for.
+fn parse_args() -> Result<Option<Options>, String> {
+    let mut options = Options { config: config::default_path(), local: None, address: None, port: None };
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--bind" => options.local = Some(value()?.parse().map_err(|_| "--bind needs an ADDRESS:PORT".to_string())?),
+            "--address" => options.address = Some(value()?.parse().map_err(|_| "--address needs an address".to_string())?),
+            "--port" => options.port = Some(value()?.parse().map_err(|_| "--port needs a port number".to_string())?),
+            "--config" => options.config = Some(PathBuf::from(value()?)),
+            "--help" | "-h" => return Ok(None),
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+    Ok(Some(options))
+}
+
+///Loads the settings file and applies the arguments over it.
+fn settings(options: &Options) -> Result<Settings, String> {
+    let mut settings = match &options.config {
+        Some(path) => Settings::load(path)?,
+        None => Settings::default(),
+    };
+    settings.local = options.local.unwrap_or(settings.local);
+    settings.address = options.address.unwrap_or(settings.address);
+    settings.port = options.port.unwrap_or(settings.port);
+    Ok(settings)
+}
+
+fn save_settings(settings: &Settings, config: &Option<PathBuf>) -> Result<(), String> {
+    match config {
+        Some(path) => settings.save(path),
+        None => Ok(()),
+    }
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args() {
+        Ok(Some(x)) => x,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(x) => {
+            eprintln!("freed-demo: {}\n", x);
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    let settings = match settings(&options) {
+        Ok(x) => x,
+        Err(x) => {
+            eprintln!("freed-demo: {}", x);
+            return ExitCode::FAILURE;
+        }
+    };
+    let link = match Link::bind(settings.local) {
+        Ok(x) => x,
+        Err(x) => {
+            eprintln!("freed-demo: could not bind {}: {}", settings.local, x);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Err(x) = run(settings, link, &options.config) {
+        eprintln!("freed-demo: {}", x);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+fn run(settings: Settings, link: Link, config: &Option<PathBuf>) -> Result<(), std::io::Error> {
+    let mut status = Status { settings, ..Status::default() };
+    let mut link = Some(link);
+    let mut inputbuffer = Vec::<char>::new();
+    let mut notice = String::new();
+    let mut sent = RateMeter::new();
+    let mut nextsend = Instant::now();
+    let mut incoming = Incoming::new();
+
+    //keep the settings that worked, including any given as arguments
+    if let Err(x) = save_settings(&status.settings, config) {
+        notice = x;
+    }
 
     crossterm::terminal::enable_raw_mode()?;
 
@@ -266,8 +542,39 @@ fn main() -> Result<(), std::io::Error> {
     let mut terminal = Terminal::new(backend)?;
 
     loop {
-        terminal.draw(|f| ui(f, status))?;
-        
+        for received in link.iter().flat_map(|x| x.received()) {
+            incoming.record(received);
+        }
+        incoming.rate.tick();
+
+        //in stream mode the payload is sent at the stream rate, in freeze mode only when F2 is pressed
+        if let OperatingModes::StreamMode = status.operating_mode {
+            if Instant::now() >= nextsend {
+                match status.send(link.as_ref()) {
+                    Ok(()) => sent.count(),
+                    Err(x) => notice = format!("Send failed: {}", x),
+                }
+                nextsend += Duration::from_secs_f64(1.0 / status.stream_rate);
+                //don't try to catch up after a stall
+                if nextsend < Instant::now() {
+                    nextsend = Instant::now();
+                }
+            }
+        }
+        sent.tick();
+        status.sent_rate = sent.rate;
+
+        let input: String = inputbuffer.iter().collect();
+        terminal.draw(|f| ui(f, status, &incoming, &input, &notice))?;
+
+        //wake up regularly to show messages received in the meantime, and in time for the next send when streaming
+        let timeout = match status.operating_mode {
+            OperatingModes::StreamMode => nextsend.saturating_duration_since(Instant::now()).min(Duration::from_millis(100)),
+            OperatingModes::FreezeMode => Duration::from_millis(100),
+        };
+        if !event::poll(timeout)? {
+            continue;
+        }
 
         if let Event::Key(event) = crossterm::event::read().expect("Failed to read line") {
             match event {
@@ -287,6 +594,24 @@ fn main() -> Result<(), std::io::Error> {
                 } => {
                     if num == 1 {
                         status.operating_mode = !status.operating_mode;
+                        nextsend = Instant::now();
+                    }
+                    if num == 2 {
+                        notice = match status.send(link.as_ref()) {
+                            Ok(()) => {
+                                sent.count();
+                                format!("Sent {} to {}:{}", status.payload_mode, status.settings.address, status.settings.port)
+                            }
+                            Err(x) => format!("Send failed: {}", x),
+                        };
+                    }
+                    if num == 3 || num == 4 {
+                        status.change_stream_rate(num == 4);
+                    }
+                    if num == 5 {
+                        status.setting = Setting::next(status.setting);
+                        status.payload_index = None;
+                        inputbuffer.clear();
                     }
 
                     
@@ -295,9 +620,13 @@ fn main() -> Result<(), std::io::Error> {
                 //change payload struct
                 KeyEvent {
                     code: KeyCode::Char(char),
-                    modifiers: event::KeyModifiers::NONE,
+                    modifiers: event::KeyModifiers::NONE | event::KeyModifiers::SHIFT,
                     ..
                 } => {
+                    if status.setting.is_some() {
+                        inputbuffer.push(char);
+                        continue;
+                    }
                     match status.payload_index {
                         None =>  match char.to_digit(10) {
                             Some(1) => status.change_payload_mode(status.payload_history[0]),
@@ -312,7 +641,7 @@ fn main() -> Result<(), std::io::Error> {
                             Some(0) => status.change_payload_mode(status.payload_history[9]), 
                             _ => continue
                         },
-                        Some(index) => {inputBuffer.push(char);
+                        Some(_) => {inputbuffer.push(char);
                             
                         }
 
@@ -326,12 +655,46 @@ fn main() -> Result<(), std::io::Error> {
                     ..
                 } => {
                     status.increment_index();
+                    status.setting = None;
+                    inputbuffer.clear();
                 }
 
                 KeyEvent {
                     code: KeyCode::BackTab,
                     ..
-                } => {status.decrement_index();}
+                } => {status.decrement_index(); status.setting = None; inputbuffer.clear();}
+
+                //apply the value typed to the highlighted field
+                KeyEvent {
+                    code: KeyCode::Enter,
+                    ..
+                } => {
+                    let text: String = inputbuffer.iter().collect();
+                    let result = match status.setting {
+                        Some(setting) => status.apply_setting(setting, text.trim(), &mut link)
+                            .and_then(|_| save_settings(&status.settings, config)),
+                        None => status.apply_edit(text.trim()),
+                    };
+                    notice = match result {
+                        Ok(()) => {inputbuffer.clear(); String::new()},
+                        Err(x) => x,
+                    };
+                }
+
+                KeyEvent {
+                    code: KeyCode::Backspace,
+                    ..
+                } => {inputbuffer.pop();}
+
+                KeyEvent {
+                    code: KeyCode::Esc,
+                    ..
+                } => {
+                    inputbuffer.clear();
+                    status.payload_index = None;
+                    status.setting = None;
+                    notice.clear();
+                }
 
 
                 _ => {}
@@ -1,6 +1,9 @@
-use std::{vec, fmt};
+use std::vec;
+use std::fmt::Display;
 
+use freed::common::*;
 use freed::payloads::*;
+use ux::{i24, u24};
 use tui::{backend::Backend, layout::{Rect, Layout, Direction, Constraint}, 
 Frame, widgets::{Table, Paragraph, Block, Cell, Row, Borders}, style::{Style, Modifier, Color}};
 
@@ -9,6 +12,9 @@ pub trait StructUI {
 
     fn to_array_of_strings(self) -> Vec<[String; 3]>;
 
+    ///Returns a copy with the field in row `index` of the table set from `text`, or why `text` isn't a valid value for it.
+    fn with_field(self, index: usize, text: &str) -> Result<Self, String> where Self: Sized;
+
     fn draw_fields_as_table<B>(self, f: &mut Frame<B>, index: Option<i32>, area: Rect) where B: Backend;
  
     fn table_template<B>(f: &mut Frame<B>, area: Rect, title: &str, index: Option<i32>, fields: Vec<[String; 3]>) where B: Backend {
@@ -49,12 +55,64 @@ pub trait StructUI {
 
 }
 
+///Parses an integer written in decimal or as 0x hex.
+fn parse_integer<T: TryFrom<i64>>(text: &str) -> Result<T, String> {
+    let parsed = match text.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => text.parse::<i64>(),
+    };
+    let value = parsed.map_err(|_| format!("{} is not a number", text))?;
+    T::try_from(value).map_err(|_| format!("{} is out of range", text))
+}
+
+///Parses a byte or address written in hex, with or without 0x, as they are shown in the table.
+fn parse_hex<T: TryFrom<u32>>(text: &str) -> Result<T, String> {
+    let value = u32::from_str_radix(text.trim_start_matches("0x"), 16).map_err(|_| format!("{} is not hex", text))?;
+    T::try_from(value).map_err(|_| format!("{} is too large", text))
+}
+
+///Parses an enum from its numeric code or its displayed name, ignoring case.
+fn parse_enum<T: TryFrom<u8, Error = String> + Display>(text: &str) -> Result<T, String> {
+    if let Ok(code) = parse_integer::<u8>(text) {
+        return T::try_from(code);
+    }
+    (0..=u8::MAX).filter_map(|x| T::try_from(x).ok()).find(|x| x.to_string().eq_ignore_ascii_case(text))
+        .ok_or_else(|| format!("{} is not a valid value", text))
+}
+
+fn parse_i24(text: &str) -> Result<i24, String> {
+    let value: i32 = parse_integer(text)?;
+    if value < i32::from(i24::MIN) || value > i32::from(i24::MAX) {
+        return Err(format!("{} is out of range, {} to {}", text, i24::MIN, i24::MAX));
+    }
+    Ok(i24::new(value))
+}
+
+fn parse_u24(text: &str) -> Result<u24, String> {
+    let value: u32 = parse_integer(text)?;
+    if value > u32::from(u24::MAX) {
+        return Err(format!("{} is out of range, 0 to {}", text, u24::MAX));
+    }
+    Ok(u24::new(value))
+}
+
+fn no_field(index: usize) -> String {
+    format!("there is no field {}", index)
+}
 
 impl StructUI for PollPayload {
     fn to_array_of_strings(self) -> Vec<[String; 3]> {
         vec![["Command".to_string(), self.command.to_string(), "".to_string()]]
     }
 
+    fn with_field(mut self, index: usize, text: &str) -> Result<Self, String> {
+        match index {
+            0 => self.command = parse_enum(text)?,
+            _ => return Err(no_field(index)),
+        }
+        Ok(self)
+    }
+
     fn draw_fields_as_table<B>(self, f: &mut Frame<B>, index: Option<i32>, area: Rect) where B: Backend {
         let fields = self.to_array_of_strings();
 
@@ -79,6 +137,21 @@ impl StructUI for PositionPollPayload {
     }
 
 
+    fn with_field(mut self, index: usize, text: &str) -> Result<Self, String> {
+        match index {
+            0 => self.pitch = parse_i24(text)?,
+            1 => self.yaw = parse_i24(text)?,
+            2 => self.roll = parse_i24(text)?,
+            3 => self.pos_z = parse_i24(text)?,
+            4 => self.pos_y = parse_i24(text)?,
+            5 => self.pos_x = parse_i24(text)?,
+            6 => self.zoom = parse_u24(text)?,
+            7 => self.focus = parse_u24(text)?,
+            _ => return Err(no_field(index)),
+        }
+        Ok(self)
+    }
+
     fn draw_fields_as_table<B>(self, f: &mut Frame<B>, index: Option<i32>, area: Rect) where B: Backend {
         let fields = self.to_array_of_strings();
 
@@ -99,10 +172,34 @@ impl StructUI for SystemStatusPayload {
         ["Targets Seen".to_string(), self.numtargetsseen.to_string(), "".to_string()],
         ["Targets Identified".to_string(), self.numtargetsidentified.to_string(), "".to_string()],
         ["Targets Used".to_string(), self.numtargetsused.to_string(), "".to_string()],
-        ["RMS Error".to_string(), self.rmserror.to_string(), "1/32768th Pixel".to_string()],
+        ["RMS Error".to_string(), self.rmserror.to_string(), "Pixel".to_string()],
         ]
     }
 
+    fn with_field(mut self, index: usize, text: &str) -> Result<Self, String> {
+        match index {
+            0 => self.switchsetting = parse_integer::<u8>(text)?.try_into()?,
+            1 => self.ledindication = parse_integer::<u8>(text)?.try_into()?,
+            2 => self.systemstatus = parse_enum(text)?,
+            3 => self.cpufirmwareversion = text.parse()?,
+            4 => self.pldfirmwareversion = text.parse()?,
+            5 => self.dspsoftwareversion = text.parse()?,
+            //a number of iterations, or an error by code or name
+            6 => self.dspstatus = match text.parse::<i8>() {
+                Ok(x) if x >= 0 => Ok(x),
+                Ok(x) => Err(DSPError::try_from(x)?),
+                Err(_) => Err((i8::MIN..0).filter_map(|x| DSPError::try_from(x).ok()).find(|x| x.to_string().eq_ignore_ascii_case(text))
+                    .ok_or_else(|| format!("{} is not a number of iterations or a DSP error", text))?),
+            },
+            7 => self.numtargetsseen = parse_integer(text)?,
+            8 => self.numtargetsidentified = parse_integer(text)?,
+            9 => self.numtargetsused = parse_integer(text)?,
+            10 => self.rmserror = Pixel32768th::from_pixels(text.parse().map_err(|_| format!("{} is not a number of pixels", text))?)?,
+            _ => return Err(no_field(index)),
+        }
+        Ok(self)
+    }
+
     fn draw_fields_as_table<B>(self, f: &mut Frame<B>, index: Option<i32>, area: Rect) where B: Backend {
         let fields = self.to_array_of_strings();
         
@@ -125,6 +222,24 @@ impl StructUI for SystemControlPayload {
         ["Min White Pixels".to_string(), self.minwhitepixels.to_string(), "".to_string()]
         ]
     }
+    fn with_field(mut self, index: usize, text: &str) -> Result<Self, String> {
+        let value = parse_integer(text)?;
+        match index {
+            0 => self.studioid = value,
+            1 => self.smoothing = value,
+            2 => self.maxasymmetry = value,
+            3 => self.halfboxwidth = value,
+            4 => self.blackvidthreshold = value,
+            5 => self.whitevidthreshold = value,
+            6 => self.blackvidclip = value,
+            7 => self.whitevidclip = value,
+            8 => self.maxblackpixels = value,
+            9 => self.minwhitepixels = value,
+            _ => return Err(no_field(index)),
+        }
+        Ok(self)
+    }
+
     fn draw_fields_as_table<B>(self, f: &mut Frame<B>, index: Option<i32>, area: Rect) where B: Backend {
         let fields = self.to_array_of_strings();
 
@@ -144,6 +259,19 @@ impl StructUI for TargetDataPayload {
         ]
     }
 
+    fn with_field(mut self, index: usize, text: &str) -> Result<Self, String> {
+        match index {
+            0 => self.studioid = parse_integer(text)?,
+            1 => self.targetnumber = parse_integer(text)?,
+            2 => self.targetx = parse_i24(text)?,
+            3 => self.targety = parse_i24(text)?,
+            4 => self.targetz = parse_i24(text)?,
+            5 => self.targetflags = parse_i24(text)?,
+            _ => return Err(no_field(index)),
+        }
+        Ok(self)
+    }
+
     fn draw_fields_as_table<B>(self, f: &mut Frame<B>, index: Option<i32>, area: Rect) where B: Backend {
         let fields = self.to_array_of_strings();
 
@@ -163,6 +291,19 @@ impl StructUI for ImageDataPayload {
         ]
     }
 
+    fn with_field(mut self, index: usize, text: &str) -> Result<Self, String> {
+        match index {
+            0 => self.targetindex = parse_integer(text)?,
+            1 => self.targetnum = parse_integer(text)?,
+            2 => self.targetx = parse_i24(text)?,
+            3 => self.targety = parse_i24(text)?,
+            4 => self.xerror = parse_i24(text)?,
+            5 => self.yerror = parse_i24(text)?,
+            _ => return Err(no_field(index)),
+        }
+        Ok(self)
+    }
+
     fn draw_fields_as_table<B>(self, f: &mut Frame<B>, index: Option<i32>, area: Rect) where B: Backend {
         let fields = self.to_array_of_strings();
         Self::table_template(f, area, "Image Data Payload", index, fields)
@@ -179,7 +320,16 @@ impl StructUI for EEPROMDataPayload {
         for byte in self.EEPROMdata {
             fields.push(["".to_string(), format!("{:#04x}", byte), "".to_string()]);
         }
-        return fields;
+        fields
+    }
+
+    fn with_field(mut self, index: usize, text: &str) -> Result<Self, String> {
+        match index {
+            0 => self.EEPROMaddress = parse_hex(text)?,
+            1..=16 => self.EEPROMdata[index - 1] = parse_hex(text)?,
+            _ => return Err(no_field(index)),
+        }
+        Ok(self)
     }
 
     fn draw_fields_as_table<B>(self, f: &mut Frame<B>, index: Option<i32>, area: Rect) where B: Backend {
@@ -195,6 +345,14 @@ impl StructUI for EEPROMDataRequestPayload {
             ["EEPROM Address".to_string(), format!("{:#06x}", self.EEPROMaddress), "".to_string()]
         ]
     }
+    fn with_field(mut self, index: usize, text: &str) -> Result<Self, String> {
+        match index {
+            0 => self.EEPROMaddress = parse_hex(text)?,
+            _ => return Err(no_field(index)),
+        }
+        Ok(self)
+    }
+
     fn draw_fields_as_table<B>(self, f: &mut Frame<B>, index: Option<i32>, area: Rect) where B: Backend {
         let fields = self.to_array_of_strings();
 
@@ -217,6 +375,23 @@ impl StructUI for CameraCalibrationPayload {
         ]
     }
 
+    fn with_field(mut self, index: usize, text: &str) -> Result<Self, String> {
+        let value = parse_i24(text)?;
+        match index {
+            0 => self.lenscentrex = value,
+            1 => self.lenscentrey = value,
+            2 => self.lensscalex = value,
+            3 => self.lensscaley = value,
+            4 => self.lensdistortiona = value,
+            5 => self.lensdistortionb = value,
+            6 => self.xoffset = value,
+            7 => self.yoffset = value,
+            8 => self.zoffset = value,
+            _ => return Err(no_field(index)),
+        }
+        Ok(self)
+    }
+
     fn draw_fields_as_table<B>(self, f: &mut Frame<B>, index: Option<i32>, area: Rect) where B: Backend {
         let fields = self.to_array_of_strings();
 
@@ -230,9 +405,99 @@ impl StructUI for DiagnosticModePayload {
             ["Diagnostic Flag".to_string(), self.diagnosticflag.to_string(), "".to_string()]
         ]
     }
+    fn with_field(mut self, index: usize, text: &str) -> Result<Self, String> {
+        match index {
+            0 => self.diagnosticflag = parse_enum(text)?,
+            _ => return Err(no_field(index)),
+        }
+        Ok(self)
+    }
+
     fn draw_fields_as_table<B>(self, f: &mut Frame<B>, index: Option<i32>, area: Rect) where B: Backend {
         let fields = self.to_array_of_strings();
 
         Self::table_template(f, area, "Diagnostic Mode Payload", index, fields)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn integers() {
+        assert_eq!(parse_integer::<u8>("255"), Ok(255));
+        assert_eq!(parse_integer::<u8>("0xff"), Ok(255));
+        assert_eq!(parse_integer::<i8>("-128"), Ok(-128));
+        assert_eq!(parse_integer::<u8>("256"), Err("256 is out of range".to_string()));
+        assert_eq!(parse_integer::<u8>("-1"), Err("-1 is out of range".to_string()));
+        assert_eq!(parse_integer::<u8>("ten"), Err("ten is not a number".to_string()));
+        assert!(parse_integer::<u8>("0x").is_err());
+    }
+
+    #[test]
+    fn i24_and_u24_bounds() {
+        assert_eq!(parse_i24("8388607"), Ok(i24::MAX));
+        assert_eq!(parse_i24("-8388608"), Ok(i24::MIN));
+        assert_eq!(parse_i24("0x7fffff"), Ok(i24::MAX));
+        assert_eq!(parse_i24("8388608"), Err("8388608 is out of range, -8388608 to 8388607".to_string()));
+        assert!(parse_i24("-8388609").is_err());
+
+        assert_eq!(parse_u24("16777215"), Ok(u24::MAX));
+        assert_eq!(parse_u24("0"), Ok(u24::new(0)));
+        assert_eq!(parse_u24("16777216"), Err("16777216 is out of range, 0 to 16777215".to_string()));
+        assert!(parse_u24("-1").is_err());
+    }
+
+    #[test]
+    fn enums_by_code_or_name() {
+        assert_eq!(parse_enum::<Commands>("0xD2"), Ok(Commands::SYSTEM_STATUS));
+        assert_eq!(parse_enum::<Commands>("210"), Ok(Commands::SYSTEM_STATUS));
+        assert_eq!(parse_enum::<Commands>("system status"), Ok(Commands::SYSTEM_STATUS));
+        assert_eq!(parse_enum::<DiagnosticModes>("Video Data 0xAA"), Ok(DiagnosticModes::VIDEO_DATA_0xAA));
+        assert_eq!(parse_enum::<DiagnosticModes>("64"), Ok(DiagnosticModes::VIDEO_DATA_0x55));
+        assert!(parse_enum::<DiagnosticModes>("1").is_err());
+        assert_eq!(parse_enum::<Commands>("Stop"), Err("Stop is not a valid value".to_string()));
+        assert!(parse_enum::<SystemStatus>("Normal").is_err());
+    }
+
+    #[test]
+    fn eeprom_bytes_are_hex() {
+        let payload = EEPROMDataPayload::default()
+            .with_field(0, "0x0120").unwrap()
+            .with_field(1, "ff").unwrap()
+            .with_field(16, "0x1A").unwrap();
+        assert_eq!(payload.EEPROMaddress, 0x120);
+        assert_eq!(payload.EEPROMdata[0], 0xFF);
+        assert_eq!(payload.EEPROMdata[15], 0x1A);
+        //10 is hex, as shown in the table
+        assert_eq!(payload.with_field(2, "10").unwrap().EEPROMdata[1], 0x10);
+
+        assert_eq!(payload.with_field(1, "100"), Err("100 is too large".to_string()));
+        assert_eq!(payload.with_field(1, "zz"), Err("zz is not hex".to_string()));
+        assert_eq!(payload.with_field(17, "00"), Err("there is no field 17".to_string()));
+        assert_eq!(EEPROMDataRequestPayload::default().with_field(0, "10000"), Err("10000 is too large".to_string()));
+    }
+
+    #[test]
+    fn with_field() {
+        let position = PositionPollPayload::default().with_field(0, "-8388608").unwrap().with_field(7, "16777215").unwrap();
+        assert_eq!(position.pitch, i24::MIN);
+        assert_eq!(position.focus, u24::MAX);
+        assert!(position.with_field(6, "-1").is_err());
+        assert_eq!(position.with_field(8, "0"), Err("there is no field 8".to_string()));
+
+        let status = SystemStatusPayload::default()
+            .with_field(2, "serial error").unwrap()
+            .with_field(6, "-1").unwrap()
+            .with_field(9, "12").unwrap();
+        assert_eq!(status.systemstatus, SystemStatus::SERIAL_ERROR);
+        assert!(status.dspstatus.is_err());
+        assert_eq!(status.numtargetsused, 12);
+        assert_eq!(status.with_field(6, "5").unwrap().dspstatus, Ok(5));
+        assert!(status.with_field(9, "256").is_err());
+
+        assert_eq!(PollPayload::default().with_field(0, "Position Poll").unwrap().command, Commands::POSITION_POLL);
+        assert_eq!(DiagnosticModePayload::default().with_field(0, "0xC0").unwrap().diagnosticflag, DiagnosticModes::VIDEO_DATA_TEST);
+        assert!(SystemControlPayload::default().with_field(0, "256").is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryIter};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use freed::payloads::{deserialise_payloads, Message, Payloads};
+use freed::transport::is_timeout;
+
+use crate::PayloadModes;
+
+///What the receive thread hands to the UI: a decoded message, or why a datagram could not be decoded.
+pub enum Received {
+    Message(Message<Payloads>),
+    Error(String),
+}
+
+///A socket bound to the local address, and the thread receiving on it. Dropping it stops the thread and closes the
+/// socket, so the address can be bound again straight away.
+pub struct Link {
+    pub socket: UdpSocket,
+    receiver: Receiver<Received>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Link {
+    pub fn bind(local: SocketAddr) -> io::Result<Link> {
+        let socket = UdpSocket::bind(local)?;
+        let receiving = socket.try_clone()?;
+        //wake up regularly to see if the thread should stop
+        receiving.set_read_timeout(Some(Duration::from_millis(100)))?;
+
+        let (sender, receiver) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopping = stop.clone();
+        let thread = thread::spawn(move || {
+            let mut buffer = [0_u8; 1024];
+            while !stopping.load(Ordering::Relaxed) {
+                let received = match receiving.recv_from(&mut buffer) {
+                    Ok((length, _)) => match deserialise_payloads(&buffer[..length]) {
+                        Ok(x) => Received::Message(x),
+                        Err(x) => Received::Error(x.description),
+                    },
+                    //signals interrupt receives that have a timeout rather than restarting them
+                    Err(x) if is_timeout(&x) || x.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(x) => Received::Error(x.to_string()),
+                };
+                if sender.send(received).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Link { socket, receiver, stop, thread: Some(thread) })
+    }
+
+    ///Everything received since the last call.
+    pub fn received(&self) -> TryIter<'_, Received> {
+        self.receiver.try_iter()
+    }
+}
+
+impl Drop for Link {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+///The latest message of each payload type received, and counters for the header of the "Data In" pane.
+pub struct Incoming {
+    pub latest: [Option<PayloadModes>; 10],
+    pub lastcamera: Option<u8>,
+    pub received: u64,
+    pub errors: u64,
+    pub lasterror: Option<String>,
+    pub rate: RateMeter,
+}
+
+impl Incoming {
+    pub fn new() -> Incoming {
+        Incoming {
+            latest: [None; 10],
+            lastcamera: None,
+            received: 0,
+            errors: 0,
+            lasterror: None,
+            rate: RateMeter::new(),
+        }
+    }
+
+    pub fn record(&mut self, received: Received) {
+        match received {
+            Received::Message(message) => {
+                let payload = PayloadModes::from(message.payload);
+                self.latest[payload.get_array_index()] = Some(payload);
+                self.lastcamera = Some(message.cameraid);
+                self.received += 1;
+                self.rate.count();
+            }
+            Received::Error(x) => {
+                self.errors += 1;
+                self.lasterror = Some(x);
+            }
+        }
+    }
+}
+
+///Messages per second, averaged over windows of about a second.
+#[derive(Copy, Clone)]
+pub struct RateMeter {
+    pub rate: f64,
+    windowstart: Instant,
+    windowcount: u64,
+}
+
+impl RateMeter {
+    pub fn new() -> RateMeter {
+        RateMeter { rate: 0.0, windowstart: Instant::now(), windowcount: 0 }
+    }
+
+    pub fn count(&mut self) {
+        self.windowcount += 1;
+    }
+
+    ///Updates the rate once the current window is over.
+    pub fn tick(&mut self) {
+        let elapsed = self.windowstart.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            self.rate = self.windowcount as f64 / elapsed.as_secs_f64();
+            self.windowstart = Instant::now();
+            self.windowcount = 0;
+        }
+    }
+}
@@ -0,0 +1,534 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::net::{SocketAddr, UdpSocket};
+use std::process::ExitCode;
+use std::time::Instant;
+
+use freed::capture::CaptureReader;
+use freed::common::{hex, parse_cameraid, Commands};
+use freed::json::JsonValue;
+use freed::payloads::*;
+use freed::transport::{bind_multicast, MulticastOptions};
+
+const USAGE: &str = "usage: freed-dump [--udp ADDRESS:PORT | --serial DEVICE | --capture FILE] [--format text|json|hex] [--interface INTERFACE] [--command HEX ...] [--camera ID ...]
+
+Prints every free-d message received, decoded.
+
+  --udp ADDRESS:PORT     receive over UDP, joining the address if it is a multicast group (default 0.0.0.0:40000)
+  --serial DEVICE        read a serial device, which should already be set to the right baud rate, e.g. with stty
+  --capture FILE         read a capture file written by freed-proxy or the capture module
+  --format FORMAT        text, one JSON object per line, or a hex dump (default text)
+  --interface INTERFACE  multicast interface, an IPv4 address or IPv6 interface index
+  --command HEX          only print messages with this command, e.g. D1
+  --camera ID            only print messages from this camera id
+
+Messages with a bad checksum or length are always printed, with the expected and actual values.";
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Format {
+    Text,
+    Json,
+    Hex,
+}
+
+#[derive(Clone)]
+enum Source {
+    Udp(SocketAddr),
+    Serial(String),
+    Capture(String),
+}
+
+struct Options {
+    source: Source,
+    format: Format,
+    multicast: MulticastOptions,
+    commands: Vec<Commands>,
+    cameras: Vec<u8>,
+}
+
+///Parses the command line, or returns `None` if usage was asked for.
+fn parse_args() -> Result<Option<Options>, String> {
+    let mut options = Options {
+        source: Source::Udp("0.0.0.0:40000".parse().unwrap()),
+        format: Format::Text,
+        multicast: MulticastOptions::default(),
+        commands: Vec::new(),
+        cameras: Vec::new(),
+    };
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--udp" => options.source = Source::Udp(value()?.parse().map_err(|_| "--udp needs an ADDRESS:PORT".to_string())?),
+            "--serial" => options.source = Source::Serial(value()?),
+            "--capture" => options.source = Source::Capture(value()?),
+            "--format" => options.format = match value()?.as_str() {
+                "text" => Format::Text,
+                "json" => Format::Json,
+                "hex" => Format::Hex,
+                x => return Err(format!("{} is not text, json or hex", x)),
+            },
+            "--interface" => options.multicast.interface = value()?.parse()?,
+            "--command" => options.commands.push(value()?.parse()?),
+            "--camera" => options.cameras.push(parse_cameraid(&value()?)?),
+            "--help" | "-h" => return Ok(None),
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+    Ok(Some(options))
+}
+
+///Where and when a message was received.
+struct Received {
+    time: String,
+    source: String,
+}
+
+///Why a message could not be decoded.
+fn problem(data: &[u8]) -> Option<String> {
+    if data.len() < 4 {
+        return Some(format!("{} bytes is too short for a message", data.len()));
+    }
+    let length = match Commands::try_from(data[0]).ok().and_then(|x| x.message_length()) {
+        Some(x) => x,
+        None => return Some(format!("0x{:02X} is not a message type", data[0])),
+    };
+    if length != data.len() {
+        return Some(format!("length {}, expected {}", data.len(), length));
+    }
+    let expected = generate_checksum(&data[..data.len() - 1]);
+    let actual = data[data.len() - 1];
+    if expected != actual {
+        return Some(format!("checksum mismatch: expected 0x{:02X}, got 0x{:02X}", expected, actual));
+    }
+    deserialise_payloads(data).err().map(|x| x.description)
+}
+
+fn describe_position(payload: &PositionPollPayload) -> String {
+    format!(
+        "pan {:.3}° tilt {:.3}° roll {:.3}° x {:.4}m y {:.4}m z {:.4}m zoom {} focus {} user {}",
+        payload.get(Axis::Yaw) / 32768.0,
+        payload.get(Axis::Pitch) / 32768.0,
+        payload.get(Axis::Roll) / 32768.0,
+        payload.get(Axis::X) / 64000.0,
+        payload.get(Axis::Y) / 64000.0,
+        payload.get(Axis::Z) / 64000.0,
+        payload.get(Axis::Zoom),
+        payload.get(Axis::Focus),
+        payload.userdefined,
+    )
+}
+
+fn describe_status(payload: &SystemStatusPayload) -> String {
+    let dsp = match payload.dspstatus {
+        Ok(x) => x.to_string(),
+        Err(x) => x.to_string(),
+    };
+    format!(
+        "{}, LEDs {}, switches {}, DSP {}, targets {} seen {} identified {} used, RMS error {:.3}px",
+        payload.systemstatus, payload.ledindication, payload.switchsetting, dsp,
+        payload.numtargetsseen, payload.numtargetsidentified, payload.numtargetsused, payload.rmserror.pixels(),
+    )
+}
+
+///Named fields of the payloads without a description of their own, in the order they are sent.
+fn payload_fields(payload: &Payloads) -> Vec<(&'static str, JsonValue)> {
+    let number = |x: f64| JsonValue::from(x);
+    fn signed(x: impl Into<i32>) -> JsonValue {
+        JsonValue::from(x.into() as f64)
+    }
+    match *payload {
+        Payloads::PollPayload(x) => vec![
+            ("command", JsonValue::from(format!("{:02X}", x.command as u8).as_str())),
+            ("type", JsonValue::from(x.command.to_string().as_str())),
+        ],
+        Payloads::SystemControlPayload(x) => vec![
+            ("studioid", number(x.studioid as f64)),
+            ("smoothing", number(x.smoothing as f64)),
+            ("maxasymmetry", number(x.maxasymmetry as f64)),
+            ("halfboxwidth", number(x.halfboxwidth as f64)),
+            ("blackvidthreshold", number(x.blackvidthreshold as f64)),
+            ("whitevidthreshold", number(x.whitevidthreshold as f64)),
+            ("blackvidclip", number(x.blackvidclip as f64)),
+            ("whitevidclip", number(x.whitevidclip as f64)),
+            ("maxblackpixels", number(x.maxblackpixels as f64)),
+            ("minwhitepixels", number(x.minwhitepixels as f64)),
+        ],
+        Payloads::TargetDataPayload(x) => vec![
+            ("studioid", number(x.studioid as f64)),
+            ("targetnumber", number(x.targetnumber as f64)),
+            ("targetx", signed(x.targetx)),
+            ("targety", signed(x.targety)),
+            ("targetz", signed(x.targetz)),
+            ("targetflags", signed(x.targetflags)),
+        ],
+        Payloads::ImageDataPayload(x) => vec![
+            ("targetindex", number(x.targetindex as f64)),
+            ("targetnum", number(x.targetnum as f64)),
+            ("targetx", signed(x.targetx)),
+            ("targety", signed(x.targety)),
+            ("xerror", signed(x.xerror)),
+            ("yerror", signed(x.yerror)),
+        ],
+        Payloads::EEPROMDataPayload(x) => vec![
+            ("address", number(x.EEPROMaddress as f64)),
+            ("data", JsonValue::from(hex(&x.EEPROMdata).as_str())),
+        ],
+        Payloads::EEPROMDataRequestPayload(x) => vec![
+            ("address", number(x.EEPROMaddress as f64)),
+        ],
+        Payloads::CameraCalibrationPayload(x) => vec![
+            ("lenscentrex", signed(x.lenscentrex)),
+            ("lenscentrey", signed(x.lenscentrey)),
+            ("lensscalex", signed(x.lensscalex)),
+            ("lensscaley", signed(x.lensscaley)),
+            ("lensdistortiona", signed(x.lensdistortiona)),
+            ("lensdistortionb", signed(x.lensdistortionb)),
+            ("xoffset", signed(x.xoffset)),
+            ("yoffset", signed(x.yoffset)),
+            ("zoffset", signed(x.zoffset)),
+        ],
+        Payloads::DiagnosticModePayload(x) => vec![
+            ("mode", JsonValue::from(x.diagnosticflag.to_string().as_str())),
+        ],
+        Payloads::PositionPollPayload(_) | Payloads::SystemStatusPayload(_) => Vec::new(),
+    }
+}
+
+fn describe_fields(payload: &Payloads) -> String {
+    let fields: Vec<String> = payload_fields(payload).into_iter()
+        .map(|(name, value)| match value {
+            JsonValue::String(x) => format!("{} {}", name, x),
+            x => format!("{} {}", name, x),
+        })
+        .collect();
+    fields.join(" ")
+}
+
+fn json_payload(payload: &Payloads) -> JsonValue {
+    let mut value = JsonValue::object();
+    match payload {
+        Payloads::PositionPollPayload(x) => {
+            for (name, axis, scale) in [
+                ("pan", Axis::Yaw, 32768.0),
+                ("tilt", Axis::Pitch, 32768.0),
+                ("roll", Axis::Roll, 32768.0),
+                ("x", Axis::X, 64000.0),
+                ("y", Axis::Y, 64000.0),
+                ("z", Axis::Z, 64000.0),
+                ("zoom", Axis::Zoom, 1.0),
+                ("focus", Axis::Focus, 1.0),
+            ] {
+                value.insert(name, JsonValue::from(x.get(axis) / scale));
+            }
+            value.insert("userdefined", JsonValue::from(x.userdefined as f64));
+        }
+        Payloads::SystemStatusPayload(x) => {
+            value.insert("status", JsonValue::from(x.systemstatus.to_string().as_str()));
+            value.insert("dspstatus", match x.dspstatus {
+                Ok(status) => JsonValue::from(status as f64),
+                Err(error) => JsonValue::from(error.to_string().as_str()),
+            });
+            value.insert("targetsseen", JsonValue::from(x.numtargetsseen as f64));
+            value.insert("targetsidentified", JsonValue::from(x.numtargetsidentified as f64));
+            value.insert("targetsused", JsonValue::from(x.numtargetsused as f64));
+            value.insert("rmserror", JsonValue::from(x.rmserror.pixels()));
+            value.insert("leds", JsonValue::from(x.ledindication.to_string().as_str()));
+            value.insert("switches", JsonValue::from(x.switchsetting.to_string().as_str()));
+            value.insert("cpufirmware", JsonValue::from(x.cpufirmwareversion.to_string().as_str()));
+            value.insert("pldfirmware", JsonValue::from(x.pldfirmwareversion.to_string().as_str()));
+            value.insert("dspsoftware", JsonValue::from(x.dspsoftwareversion.to_string().as_str()));
+        }
+        x => {
+            for (name, field) in payload_fields(x) {
+                value.insert(name, field);
+            }
+        }
+    }
+    value
+}
+
+struct Printer {
+    options: Options,
+    output: std::io::StdoutLock<'static>,
+}
+
+impl Printer {
+    fn wanted(&self, data: &[u8]) -> bool {
+        let command = data.first().and_then(|x| Commands::try_from(*x).ok());
+        let cameraid = data.get(1).copied();
+        (self.options.commands.is_empty() || command.is_some_and(|x| self.options.commands.contains(&x)))
+            && (self.options.cameras.is_empty() || cameraid.is_some_and(|x| self.options.cameras.contains(&x)))
+    }
+
+    fn print(&mut self, data: &[u8], received: &Received) -> std::io::Result<()> {
+        //malformed messages are always printed, even if their command or camera id doesn't match a filter
+        let problem = problem(data);
+        if problem.is_none() && !self.wanted(data) {
+            return Ok(());
+        }
+        let message = match problem {
+            None => deserialise_payloads(data).ok(),
+            Some(_) => None,
+        };
+
+        match self.options.format {
+            Format::Text => {
+                write!(self.output, "{} {} ", received.time, received.source)?;
+                match (&message, &problem) {
+                    (Some(message), _) => {
+                        let details = match &message.payload {
+                            Payloads::PositionPollPayload(x) => describe_position(x),
+                            Payloads::SystemStatusPayload(x) => describe_status(x),
+                            x => describe_fields(x),
+                        };
+                        writeln!(self.output, "camera {} {:02X} {}: {}", message.cameraid, data[0], message.command(), details)?;
+                    }
+                    (None, problem) => writeln!(self.output, "malformed: {} [{}]", problem.as_deref().unwrap_or_default(), hex(data))?,
+                }
+            }
+            Format::Json => {
+                let mut value = JsonValue::object();
+                value.insert("time", JsonValue::from(received.time.as_str()));
+                value.insert("source", JsonValue::from(received.source.as_str()));
+                match (&message, &problem) {
+                    (Some(message), _) => {
+                        value.insert("camera", JsonValue::from(message.cameraid as f64));
+                        value.insert("command", JsonValue::from(format!("{:02X}", data[0]).as_str()));
+                        value.insert("type", JsonValue::from(message.command().to_string().as_str()));
+                        value.insert("payload", json_payload(&message.payload));
+                    }
+                    (None, problem) => {
+                        value.insert("error", JsonValue::from(problem.as_deref().unwrap_or_default()));
+                        value.insert("data", JsonValue::from(hex(data).as_str()));
+                    }
+                }
+                writeln!(self.output, "{}", value)?;
+            }
+            Format::Hex => {
+                let summary = match (&message, &problem) {
+                    (Some(message), _) => format!("camera {} {}", message.cameraid, message.command()),
+                    (None, problem) => format!("malformed: {}", problem.as_deref().unwrap_or_default()),
+                };
+                writeln!(self.output, "{} {} {} bytes, {}", received.time, received.source, data.len(), summary)?;
+                for (index, line) in data.chunks(16).enumerate() {
+                    writeln!(self.output, "  {:04x}  {}", index * 16, hex(line))?;
+                }
+            }
+        }
+        self.output.flush()
+    }
+}
+
+fn elapsed(start: Instant) -> String {
+    format!("+{:.6}", start.elapsed().as_secs_f64())
+}
+
+fn dump_udp(printer: &mut Printer, address: SocketAddr) -> Result<(), String> {
+    let socket = match address.ip().is_multicast() {
+        true => bind_multicast(address, &printer.options.multicast),
+        false => UdpSocket::bind(address),
+    }.map_err(|x| format!("could not bind {}: {}", address, x))?;
+
+    let start = Instant::now();
+    let mut buffer = [0_u8; 65536];
+    loop {
+        let (length, source) = socket.recv_from(&mut buffer).map_err(|x| format!("receive failed: {}", x))?;
+        let received = Received { time: elapsed(start), source: source.to_string() };
+        printer.print(&buffer[..length], &received).map_err(|x| x.to_string())?;
+    }
+}
+
+///A piece of a serial stream, as split up by a `Framer`.
+#[derive(Debug, PartialEq)]
+enum Frame {
+    Message(Vec<u8>),
+    ///Bytes that start like a message but don't decode as one. Only the first byte is used up, since a message may
+    /// start inside the rest.
+    Malformed(Vec<u8>),
+    ///Bytes that cannot start a message.
+    Skipped(Vec<u8>),
+    ///The start of a message, cut off by the end of the stream.
+    Incomplete(Vec<u8>),
+}
+
+///Splits a serial stream into messages by the length implied by each command byte. After a bad message it looks for
+/// the next one a byte later, rather than throwing away everything up to where the bad one would have ended, so the
+/// stream resyncs on the next good message.
+#[derive(Default)]
+struct Framer {
+    pending: Vec<u8>,
+    skipped: Vec<u8>,
+}
+
+impl Framer {
+    fn push(&mut self, data: &[u8]) {
+        self.pending.extend_from_slice(data);
+    }
+
+    ///The next piece of the stream, or `None` if more bytes are needed to tell.
+    fn frame(&mut self) -> Option<Frame> {
+        while let Some(command) = self.pending.first() {
+            let Some(length) = Commands::try_from(*command).ok().and_then(|x| x.message_length()) else {
+                self.skipped.push(self.pending.remove(0));
+                continue;
+            };
+            if self.pending.len() < length {
+                return None;
+            }
+            //skipped bytes are reported before whatever follows them
+            if !self.skipped.is_empty() {
+                return Some(Frame::Skipped(std::mem::take(&mut self.skipped)));
+            }
+            if problem(&self.pending[..length]).is_some() {
+                let candidate = self.pending[..length].to_vec();
+                self.pending.remove(0);
+                return Some(Frame::Malformed(candidate));
+            }
+            return Some(Frame::Message(self.pending.drain(..length).collect()));
+        }
+        None
+    }
+
+    ///Whatever is left over once the stream has ended.
+    fn finish(&mut self) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        if !self.skipped.is_empty() {
+            frames.push(Frame::Skipped(std::mem::take(&mut self.skipped)));
+        }
+        if !self.pending.is_empty() {
+            frames.push(Frame::Incomplete(std::mem::take(&mut self.pending)));
+        }
+        frames
+    }
+}
+
+fn dump_serial(printer: &mut Printer, path: &str) -> Result<(), String> {
+    let mut device = File::open(path).map_err(|x| format!("could not open {}: {}", path, x))?;
+    let start = Instant::now();
+    let mut framer = Framer::default();
+    let mut buffer = [0_u8; 256];
+
+    loop {
+        let length = device.read(&mut buffer).map_err(|x| format!("read failed: {}", x))?;
+        let frames = match length {
+            0 => framer.finish(),
+            _ => {
+                framer.push(&buffer[..length]);
+                std::iter::from_fn(|| framer.frame()).collect()
+            }
+        };
+
+        for frame in frames {
+            let received = Received { time: elapsed(start), source: path.to_string() };
+            let (description, data) = match frame {
+                Frame::Message(data) | Frame::Malformed(data) => {
+                    printer.print(&data, &received).map_err(|x| x.to_string())?;
+                    continue;
+                }
+                Frame::Skipped(data) => ("skipped", data),
+                Frame::Incomplete(data) => ("incomplete message at the end of the stream,", data),
+            };
+            //keep JSON output to one message per line
+            if printer.options.format == Format::Json {
+                eprintln!("freed-dump: {} {} bytes [{}]", description, data.len(), hex(&data));
+            } else {
+                writeln!(printer.output, "{} {} {} {} bytes [{}]", received.time, received.source, description, data.len(), hex(&data)).map_err(|x| x.to_string())?;
+            }
+        }
+        if length == 0 {
+            return Ok(());
+        }
+    }
+}
+
+fn dump_capture(printer: &mut Printer, path: &str) -> Result<(), String> {
+    let file = File::open(path).map_err(|x| format!("could not open {}: {}", path, x))?;
+    let reader = CaptureReader::new(BufReader::new(file)).map_err(|x| x.to_string())?;
+
+    for record in reader {
+        let record = record.map_err(|x| x.to_string())?;
+        let time = format!("+{:.6}", record.offset.as_secs_f64());
+        let source = record.timecode.map_or("--:--:--:--".to_string(), |x| x.to_string());
+        printer.print(&record.data, &Received { time, source }).map_err(|x| x.to_string())?;
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args() {
+        Ok(Some(x)) => x,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(x) => {
+            eprintln!("freed-dump: {}\n", x);
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let source = options.source.clone();
+    let mut printer = Printer { options, output: std::io::stdout().lock() };
+    let result = match source {
+        Source::Udp(address) => dump_udp(&mut printer, address),
+        Source::Serial(path) => dump_serial(&mut printer, &path),
+        Source::Capture(path) => dump_capture(&mut printer, &path),
+    };
+
+    if let Err(x) = result {
+        eprintln!("freed-dump: {}", x);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use freed::common::Serialise;
+
+    #[test]
+    fn problems() {
+        let message = Message::new(PositionPollPayload::default(), 1).serialise();
+        assert_eq!(problem(&message), None);
+        assert_eq!(problem(&message[..3]).unwrap(), "3 bytes is too short for a message");
+        assert_eq!(problem(&[0x42, 1, 0, 0]).unwrap(), "0x42 is not a message type");
+        assert_eq!(problem(&message[..20]).unwrap(), "length 20, expected 29");
+
+        let mut bad = message.clone();
+        bad[28] ^= 0xFF;
+        assert_eq!(problem(&bad).unwrap(), format!("checksum mismatch: expected 0x{:02X}, got 0x{:02X}", message[28], bad[28]));
+
+        //a well formed status message with an unknown system status
+        let mut status = Message::new(SystemStatusPayload::default(), 1).serialise();
+        status[4] = 0xEE;
+        let last = status.len() - 1;
+        status[last] = generate_checksum(&status[..last]);
+        assert!(problem(&status).is_some());
+    }
+
+    #[test]
+    fn resync() {
+        let message = Message::new(PositionPollPayload::default(), 2).serialise();
+        let mut framer = Framer::default();
+        //bytes that can't start a message, a message cut short by noise, then a good message and the start of another
+        framer.push(&[0x00, 0x42, 0xD1, 0x00, 0x00]);
+        framer.push(&message);
+        framer.push(&message[..5]);
+
+        let frames: Vec<Frame> = std::iter::from_fn(|| framer.frame()).collect();
+        let mut cut = vec![0xD1, 0x00, 0x00];
+        cut.extend_from_slice(&message[..26]);
+        assert_eq!(frames, vec![
+            Frame::Skipped(vec![0x00, 0x42]),
+            Frame::Malformed(cut),
+            Frame::Skipped(vec![0x00, 0x00]),
+            Frame::Message(message.clone()),
+        ]);
+        assert_eq!(framer.finish(), vec![Frame::Incomplete(message[..5].to_vec())]);
+        assert!(framer.finish().is_empty());
+    }
+}
@@ -0,0 +1,159 @@
+use std::net::{SocketAddr, UdpSocket};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+use freed::common::Serialise;
+use freed::osc::{AddressSchema, OscController, OscEncoder, OscPacket, OscUnits};
+use freed::payloads::deserialise_payloads;
+use freed::transport::is_timeout;
+
+const USAGE: &str = "usage: freed-osc [--listen ADDRESS:PORT] --to ADDRESS:PORT [--schema TEMPLATE] [--units physical|raw] [--bundle] [--reverse [--rate HZ]]
+
+Converts free-d tracking to OSC, or with --reverse, OSC from a controller into free-d from a simulated unit.
+
+  --listen ADDRESS:PORT  address to receive on (default 0.0.0.0:40000, or 0.0.0.0:9000 with --reverse)
+  --to ADDRESS:PORT      address to send OSC to, or free-d with --reverse
+  --schema TEMPLATE      OSC address of each value (default /camera/{camera}/{field})
+  --units UNITS          physical (degrees, metres, fraction of the encoder range) or raw free-d units (default physical)
+  --bundle               send the values of each free-d message together as one OSC bundle
+  --reverse              drive a simulated unit from OSC, sending its pose as free-d
+  --rate HZ              how often the simulated unit sends its pose (default 50)
+
+Fields are pan, tilt, roll, x, y, z, zoom and focus, and for status messages status, targets_seen,
+targets_identified, targets_used and rms_error.";
+
+struct Options {
+    listen: Option<SocketAddr>,
+    to: SocketAddr,
+    schema: AddressSchema,
+    units: OscUnits,
+    bundle: bool,
+    reverse: bool,
+    rate: f64,
+}
+
+///Parses the command line, or returns `None` if usage was asked for.
+fn parse_args() -> Result<Option<Options>, String> {
+    let mut options = Options {
+        listen: None,
+        to: "127.0.0.1:9000".parse().unwrap(),
+        schema: AddressSchema::default(),
+        units: OscUnits::default(),
+        bundle: false,
+        reverse: false,
+        rate: 50.0,
+    };
+    let mut to = None;
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--listen" => options.listen = Some(value()?.parse().map_err(|_| "--listen needs an ADDRESS:PORT".to_string())?),
+            "--to" => to = Some(value()?.parse().map_err(|_| "--to needs an ADDRESS:PORT".to_string())?),
+            "--schema" => options.schema = value()?.parse()?,
+            "--units" => options.units = value()?.parse()?,
+            "--bundle" => options.bundle = true,
+            "--reverse" => options.reverse = true,
+            "--rate" => options.rate = value()?.parse().ok().filter(|x: &f64| *x > 0.0 && Duration::try_from_secs_f64(1.0 / x).is_ok())
+                .ok_or_else(|| "--rate needs a positive number".to_string())?,
+            "--help" | "-h" => return Ok(None),
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+
+    options.to = to.ok_or_else(|| "--to is needed".to_string())?;
+    Ok(Some(options))
+}
+
+///Forwards each free-d message received as OSC. A failed receive is logged and the bridge carries on, since it
+/// usually means one datagram was lost rather than the socket being unusable.
+fn bridge(socket: &UdpSocket, options: &Options) -> Result<(), String> {
+    let encoder = OscEncoder { schema: options.schema.clone(), units: options.units, bundle: options.bundle };
+    let mut buffer = [0_u8; 1024];
+
+    loop {
+        let length = match socket.recv_from(&mut buffer) {
+            Ok((x, _)) => x,
+            Err(x) => {
+                eprintln!("freed-osc: receive failed: {}", x);
+                continue;
+            }
+        };
+        let Ok(message) = deserialise_payloads(&buffer[..length]) else {
+            continue;
+        };
+        for packet in encoder.encode(&message) {
+            socket.send_to(&packet.encode(), options.to).map_err(|x| format!("send failed: {}", x))?;
+        }
+    }
+}
+
+///Applies OSC from controllers to a simulated unit, which sends the pose of every camera addressed at `rate`.
+fn simulate(socket: &UdpSocket, options: &Options) -> Result<(), String> {
+    let mut controller = OscController::new(options.schema.clone(), options.units);
+    let interval = Duration::from_secs_f64(1.0 / options.rate);
+    let mut buffer = [0_u8; 65536];
+    let mut nextsend = Instant::now();
+
+    loop {
+        let timeout = nextsend.saturating_duration_since(Instant::now()).max(Duration::from_millis(1));
+        socket.set_read_timeout(Some(timeout)).expect("Non-zero timeout");
+
+        match socket.recv_from(&mut buffer) {
+            Ok((length, source)) => {
+                if let Err(x) = OscPacket::decode(&buffer[..length]).and_then(|x| controller.apply(&x)) {
+                    eprintln!("freed-osc: from {}: {}", source, x);
+                }
+            }
+            Err(x) if is_timeout(&x) => {}
+            Err(x) => eprintln!("freed-osc: receive failed: {}", x),
+        }
+
+        if Instant::now() >= nextsend {
+            for message in controller.messages() {
+                socket.send_to(&message.serialise(), options.to).map_err(|x| format!("send failed: {}", x))?;
+            }
+            nextsend += interval;
+            //don't try to catch up after a stall
+            if nextsend < Instant::now() {
+                nextsend = Instant::now() + interval;
+            }
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args() {
+        Ok(Some(x)) => x,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(x) => {
+            eprintln!("freed-osc: {}\n", x);
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let default = if options.reverse { "0.0.0.0:9000" } else { "0.0.0.0:40000" };
+    let listen = options.listen.unwrap_or_else(|| default.parse().unwrap());
+    let socket = match UdpSocket::bind(listen) {
+        Ok(x) => x,
+        Err(x) => {
+            eprintln!("freed-osc: could not bind {}: {}", listen, x);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = match options.reverse {
+        true => simulate(&socket, &options),
+        false => bridge(&socket, &options),
+    };
+    if let Err(x) = result {
+        eprintln!("freed-osc: {}", x);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
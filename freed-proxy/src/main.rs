@@ -0,0 +1,202 @@
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+use freed::common::parse_cameraid;
+use freed::delay::{Delay, DelayLine};
+use freed::predict::PosePredictor;
+use freed::router::{Forward, Route, Router};
+use freed::transport::{bind_multicast, is_timeout, set_multicast_options, MulticastOptions, MAX_MESSAGE_LENGTH};
+
+const USAGE: &str = "usage: freed-proxy [--listen ADDRESS:PORT] [--interface INTERFACE] [--ttl HOPS] [--stats SECONDS] [--predict MILLISECONDS] [--delay DELAY] --route SPEC [--route SPEC ...]
+
+Receives free-d messages over UDP and forwards them to every matching route.
+
+  --listen ADDRESS:PORT  address to receive on, joining it if it is a multicast group (default 0.0.0.0:40000)
+  --interface INTERFACE  multicast interface, an IPv4 address or IPv6 interface index (default chosen by the system)
+  --ttl HOPS             TTL of messages forwarded to multicast groups (default 1)
+  --stats SECONDS        print per-route counters every SECONDS to stderr, 0 to disable (default 5)
+  --predict MILLISECONDS forward-predict camera poses to compensate for downstream latency
+  --delay DELAY          delay tracking to match delayed video, in milliseconds (40ms) or FRAMES@FPS (2.5@25)
+  --route SPEC           DESTINATION[,commands=D1+D2][,cameras=1+2][,remap=1:5+2:6]
+
+Commands are hex message types. Camera ids are decimal, or hex with a 0x prefix.
+Messages with a bad checksum or length are dropped.";
+
+struct Options {
+    listen: SocketAddr,
+    multicast: MulticastOptions,
+    stats: Duration,
+    predict: Option<Duration>,
+    delay: Option<Delay>,
+    routes: Vec<Route>,
+}
+
+fn parse_route(spec: &str) -> Result<Route, String> {
+    let mut parts = spec.split(',');
+    let destination = parts.next().unwrap_or_default();
+    let mut route = Route::new(destination.parse().map_err(|_| format!("{} is not an ADDRESS:PORT", destination))?);
+
+    for part in parts {
+        let (key, values) = part.split_once('=').ok_or_else(|| format!("expected key=value, found {}", part))?;
+        for value in values.split('+') {
+            match key {
+                "commands" => route.commands.push(value.parse()?),
+                "cameras" => route.cameras.push(parse_cameraid(value)?),
+                "remap" => {
+                    let (from, to) = value.split_once(':').ok_or_else(|| format!("expected FROM:TO, found {}", value))?;
+                    route.remap.insert(parse_cameraid(from)?, parse_cameraid(to)?);
+                }
+                _ => return Err(format!("unknown route option {}", key)),
+            }
+        }
+    }
+
+    Ok(route)
+}
+
+///Parses the command line, or returns `None` if usage was asked for.
+fn parse_args() -> Result<Option<Options>, String> {
+    let mut options = Options { listen: "0.0.0.0:40000".parse().unwrap(), multicast: MulticastOptions::default(), stats: Duration::from_secs(5), predict: None, delay: None, routes: Vec::new() };
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--listen" => options.listen = value()?.parse().map_err(|_| "--listen needs an ADDRESS:PORT".to_string())?,
+            "--interface" => options.multicast.interface = value()?.parse()?,
+            "--ttl" => options.multicast.ttl = value()?.parse().map_err(|_| "--ttl needs a number of hops".to_string())?,
+            "--stats" => options.stats = Duration::from_secs(value()?.parse().map_err(|_| "--stats needs a number of seconds".to_string())?),
+            "--predict" => options.predict = Some(Duration::from_millis(value()?.parse().map_err(|_| "--predict needs a number of milliseconds".to_string())?)),
+            "--delay" => options.delay = Some(value()?.parse()?),
+            "--route" => options.routes.push(parse_route(&value()?)?),
+            "--help" | "-h" => return Ok(None),
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+
+    if options.routes.is_empty() {
+        return Err("at least one --route is needed".to_string());
+    }
+    Ok(Some(options))
+}
+
+fn print_stats(router: &Router, receiveerrors: u64) {
+    eprintln!("{} received, {} malformed, {} receive errors", router.received, router.malformed, receiveerrors);
+    for route in router.routes() {
+        eprintln!("  {}", route);
+    }
+}
+
+///Sockets forwarded messages are sent from, one for each address family the routes use.
+struct Senders {
+    ipv4: Option<UdpSocket>,
+    ipv6: Option<UdpSocket>,
+}
+
+impl Senders {
+    fn get(&self, destination: SocketAddr) -> Option<&UdpSocket> {
+        match destination {
+            SocketAddr::V4(_) => self.ipv4.as_ref(),
+            SocketAddr::V6(_) => self.ipv6.as_ref(),
+        }
+    }
+}
+
+fn send(senders: &Senders, router: &mut Router, forwards: Vec<Forward>) {
+    for forward in forwards {
+        let sent = senders.get(forward.destination).map(|x| x.send_to(&forward.data, forward.destination));
+        if !matches!(sent, Some(Ok(_))) {
+            router.send_failed(forward.route);
+        }
+    }
+}
+
+///Binds the listening socket, joining it if it is a multicast group, and a socket to forward from for each address
+/// family the routes use. Messages are forwarded from the listening socket where it can be, but a socket bound to a
+/// group cannot send from it, and one family's socket cannot reach the other.
+fn bind(options: &Options) -> std::io::Result<(UdpSocket, Senders)> {
+    let listener = match options.listen.ip().is_multicast() {
+        true => bind_multicast(options.listen, &options.multicast)?,
+        false => UdpSocket::bind(options.listen)?,
+    };
+
+    let sender = |ipv4: bool| -> std::io::Result<Option<UdpSocket>> {
+        let mut destinations = options.routes.iter().map(|x| x.destination.ip()).filter(|x| x.is_ipv4() == ipv4).peekable();
+        if destinations.peek().is_none() {
+            return Ok(None);
+        }
+        let socket = match options.listen.ip() {
+            x if !x.is_multicast() && x.is_ipv4() == ipv4 => listener.try_clone()?,
+            _ if ipv4 => UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?,
+            _ => UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0))?,
+        };
+        //every multicast route of a family is sent with the same interface and TTL
+        if let Some(group) = destinations.find(|x| x.is_multicast()) {
+            set_multicast_options(&socket, group, &options.multicast)?;
+        }
+        Ok(Some(socket))
+    };
+    let senders = Senders { ipv4: sender(true)?, ipv6: sender(false)? };
+    Ok((listener, senders))
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args() {
+        Ok(Some(x)) => x,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(x) => {
+            eprintln!("freed-proxy: {}\n", x);
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let (socket, senders) = match bind(&options) {
+        Ok(x) => x,
+        Err(x) => {
+            eprintln!("freed-proxy: could not bind {}: {}", options.listen, x);
+            return ExitCode::FAILURE;
+        }
+    };
+    socket.set_read_timeout(Some(Duration::from_millis(250))).expect("Non-zero timeout");
+
+    let mut router = Router::new(options.routes);
+    if let Some(lead) = options.predict {
+        router.add_stage(Box::new(PosePredictor::new(lead)));
+    }
+    if let Some(delay) = options.delay {
+        router.add_stage(Box::new(DelayLine::new(delay)));
+        //held messages are released by polling, so wake up often enough to send them on time
+        socket.set_read_timeout(Some(Duration::from_millis(2))).expect("Non-zero timeout");
+    }
+    let mut buffer = [0_u8; MAX_MESSAGE_LENGTH * 2];
+    let mut laststats = Instant::now();
+    let mut receiveerrors = 0_u64;
+
+    loop {
+        match socket.recv_from(&mut buffer) {
+            Ok((length, _)) => {
+                let forwards = router.route(&buffer[..length], Instant::now());
+                send(&senders, &mut router, forwards);
+            }
+            Err(x) if is_timeout(&x) => {}
+            //errors such as an ICMP port unreachable reported as ConnectionReset only affect one datagram, so keep routing
+            Err(x) => {
+                receiveerrors += 1;
+                eprintln!("freed-proxy: receive failed: {}", x);
+            }
+        }
+
+        let forwards = router.poll(Instant::now());
+        send(&senders, &mut router, forwards);
+
+        if !options.stats.is_zero() && laststats.elapsed() >= options.stats {
+            print_stats(&router, receiveerrors);
+            laststats = Instant::now();
+        }
+    }
+}
@@ -0,0 +1,632 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::net::{SocketAddr, UdpSocket};
+use std::process::ExitCode;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use freed::common::{hex, parse_cameraid, Commands, Serialise};
+use freed::json::JsonValue;
+use freed::payloads::*;
+use freed::transport::{set_multicast_options, MulticastOptions};
+
+const USAGE: &str = "usage: freed-send [--to ADDRESS:PORT | --serial DEVICE] [--type TYPE] [--camera ID] [--file FILE] [--count N] [--rate HZ] [--bad-checksum] [--length BYTES] [--interface INTERFACE] [--ttl HOPS] [--dry-run] [FIELD=VALUE ...]
+
+Builds a free-d message from fields given as arguments or in a file and sends it, for bench testing receivers.
+
+  --to ADDRESS:PORT      address to send to, which may be a multicast group (default 127.0.0.1:40000)
+  --serial DEVICE        write to a serial device instead, which should already be set to the right baud rate
+  --type TYPE            poll, position, status, control, target, image, eeprom, eeprom-request, calibration,
+                         diagnostic, or a hex command such as D5 (default position)
+  --camera ID            camera id of the message (default 1)
+  --file FILE            read fields from a JSON object or a TOML file of key = value lines, which may also set
+                         type and camera. Fields given as arguments take precedence
+  --count N              number of times to send the message, 0 to send until interrupted (default 1)
+  --rate HZ              messages per second when sending more than one (default as fast as possible)
+  --bad-checksum         send the message with its checksum inverted
+  --length BYTES         truncate or zero pad the message to this many bytes, with a checksum that is otherwise valid
+  --interface INTERFACE  multicast interface, an IPv4 address or IPv6 interface index
+  --ttl HOPS             TTL of messages sent to a multicast group (default 1)
+  --dry-run              print the message in hex instead of sending it
+
+Fields are named as in the payload structs, e.g. pitch, yaw, zoom or EEPROMaddress, and any not given keep their
+default. Values are raw protocol units, in decimal or 0x hex. Numbers may instead have a unit, deg for rotations,
+m or mm for positions and px for the RMS error. EEPROMdata is a list of up to 16 bytes or a string of hex digits,
+and the command of a poll is a hex command.
+
+  freed-send --type position --camera 2 --count 0 --rate 50 yaw=45deg pos_x=1.5m zoom=0x8000
+  freed-send --type poll command=D2 --bad-checksum";
+
+///Where a field is in the serialised payload, and how many bytes it takes.
+struct Field {
+    name: &'static str,
+    offset: usize,
+    width: usize,
+    signed: bool,
+}
+
+const fn field(name: &'static str, offset: usize, width: usize, signed: bool) -> Field {
+    Field { name, offset, width, signed }
+}
+
+struct PayloadType {
+    name: &'static str,
+    commands: &'static [Commands],
+    fields: &'static [Field],
+}
+
+const TYPES: [PayloadType; 10] = [
+    PayloadType { name: "poll", commands: &[Commands::POLL], fields: &[field("command", 0, 1, false)] },
+    PayloadType {
+        name: "position",
+        commands: &[Commands::POSITION_POLL],
+        fields: &[
+            field("pitch", 0, 3, true),
+            field("yaw", 3, 3, true),
+            field("roll", 6, 3, true),
+            field("pos_z", 9, 3, true),
+            field("pos_y", 12, 3, true),
+            field("pos_x", 15, 3, true),
+            field("zoom", 18, 3, false),
+            field("focus", 21, 3, false),
+            field("userdefined", 24, 2, false),
+        ],
+    },
+    PayloadType {
+        name: "status",
+        commands: &[Commands::SYSTEM_STATUS],
+        fields: &[
+            field("switchsetting", 0, 1, false),
+            field("ledindication", 1, 1, false),
+            field("systemstatus", 2, 1, false),
+            field("cpufirmwareversion", 3, 1, false),
+            field("pldfirmwareversion", 4, 1, false),
+            field("dspsoftwareversion", 5, 1, false),
+            field("dspstatus", 6, 1, true),
+            field("numtargetsseen", 7, 1, false),
+            field("numtargetsidentified", 8, 1, false),
+            field("numtargetsused", 9, 1, false),
+            field("rmserror", 10, 3, false),
+        ],
+    },
+    PayloadType {
+        name: "control",
+        commands: &[Commands::SYSTEM_PARAMS],
+        fields: &[
+            field("studioid", 0, 1, false),
+            field("smoothing", 1, 1, false),
+            field("maxasymmetry", 2, 1, false),
+            field("halfboxwidth", 3, 1, false),
+            field("blackvidthreshold", 4, 1, false),
+            field("whitevidthreshold", 5, 1, false),
+            field("blackvidclip", 6, 1, false),
+            field("whitevidclip", 7, 1, false),
+            field("maxblackpixels", 8, 1, false),
+            field("minwhitepixels", 9, 1, false),
+        ],
+    },
+    PayloadType {
+        name: "target",
+        commands: &[Commands::FIRST_TARGET, Commands::NEXT_TARGET],
+        fields: &[
+            field("studioid", 0, 1, false),
+            field("targetnumber", 1, 2, false),
+            field("targetx", 3, 3, true),
+            field("targety", 6, 3, true),
+            field("targetz", 9, 3, true),
+            field("targetflags", 12, 3, true),
+        ],
+    },
+    PayloadType {
+        name: "image",
+        commands: &[Commands::FIRST_IMAGE, Commands::NEXT_IMAGE],
+        fields: &[
+            field("targetindex", 0, 1, false),
+            field("targetnum", 1, 2, false),
+            field("targetx", 3, 3, true),
+            field("targety", 6, 3, true),
+            field("xerror", 9, 3, true),
+            field("yerror", 12, 3, true),
+        ],
+    },
+    PayloadType {
+        name: "eeprom",
+        commands: &[Commands::EEPROM_DATA],
+        fields: &[field("EEPROMaddress", 0, 2, false), field("EEPROMdata", 2, 16, false)],
+    },
+    PayloadType { name: "eeprom-request", commands: &[Commands::REQUEST_EEPROM], fields: &[field("EEPROMaddress", 0, 2, false)] },
+    PayloadType {
+        name: "calibration",
+        commands: &[Commands::CAMERA_CALIBRATION],
+        fields: &[
+            field("lenscentrex", 0, 3, true),
+            field("lenscentrey", 3, 3, true),
+            field("lensscalex", 6, 3, true),
+            field("lensscaley", 9, 3, true),
+            field("lensdistortiona", 12, 3, true),
+            field("lensdistortionb", 15, 3, true),
+            field("xoffset", 18, 3, true),
+            field("yoffset", 21, 3, true),
+            field("zoffset", 24, 3, true),
+        ],
+    },
+    PayloadType { name: "diagnostic", commands: &[Commands::DIAGNOSTIC_MODE], fields: &[field("diagnosticflag", 0, 1, false)] },
+];
+
+enum Output {
+    Udp(SocketAddr),
+    Serial(String),
+}
+
+struct Options {
+    output: Output,
+    command: Option<Commands>,
+    cameraid: Option<u8>,
+    file: Option<String>,
+    fields: Vec<(String, String)>,
+    count: u64,
+    rate: Option<f64>,
+    badchecksum: bool,
+    length: Option<usize>,
+    multicast: MulticastOptions,
+    dryrun: bool,
+}
+
+///Finds the command of a message type given by name or hex command.
+fn parse_type(text: &str) -> Result<Commands, String> {
+    if let Some(payloadtype) = TYPES.iter().find(|x| x.name == text) {
+        return Ok(payloadtype.commands[0]);
+    }
+    let command = text.parse::<Commands>().map_err(|_| format!("{} is not a message type", text))?;
+    match payload_type(command) {
+        Some(_) => Ok(command),
+        None => Err(format!("{} is not a message type", text)),
+    }
+}
+
+fn payload_type(command: Commands) -> Option<&'static PayloadType> {
+    TYPES.iter().find(|x| x.commands.contains(&command))
+}
+
+///Parses the command line, or returns `None` if usage was asked for.
+fn parse_args() -> Result<Option<Options>, String> {
+    let mut options = Options {
+        output: Output::Udp("127.0.0.1:40000".parse().unwrap()),
+        command: None,
+        cameraid: None,
+        file: None,
+        fields: Vec::new(),
+        count: 1,
+        rate: None,
+        badchecksum: false,
+        length: None,
+        multicast: MulticastOptions::default(),
+        dryrun: false,
+    };
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--to" => options.output = Output::Udp(value()?.parse().map_err(|_| "--to needs an ADDRESS:PORT".to_string())?),
+            "--serial" => options.output = Output::Serial(value()?),
+            "--type" => options.command = Some(parse_type(&value()?)?),
+            "--camera" => options.cameraid = Some(parse_cameraid(&value()?)?),
+            "--file" => options.file = Some(value()?),
+            "--count" => options.count = value()?.parse().map_err(|_| "--count needs a number".to_string())?,
+            "--rate" => options.rate = Some(value()?.parse().ok().filter(|x: &f64| *x > 0.0 && Duration::try_from_secs_f64(1.0 / x).is_ok())
+                .ok_or_else(|| "--rate needs a positive number".to_string())?),
+            "--bad-checksum" => options.badchecksum = true,
+            "--length" => options.length = Some(value()?.parse().map_err(|_| "--length needs a number of bytes".to_string())?),
+            "--interface" => options.multicast.interface = value()?.parse()?,
+            "--ttl" => options.multicast.ttl = value()?.parse().map_err(|_| "--ttl needs a number of hops".to_string())?,
+            "--dry-run" => options.dryrun = true,
+            "--help" | "-h" => return Ok(None),
+            x => match x.split_once('=') {
+                Some((key, value)) if !key.starts_with('-') => options.fields.push((key.to_string(), value.to_string())),
+                _ => return Err(format!("unknown argument {}", arg)),
+            },
+        }
+    }
+    Ok(Some(options))
+}
+
+///Reads a flat TOML table of `key = value` lines into a JSON object. Only the subset of TOML needed for a list of
+/// fields is understood: numbers, strings and arrays, with `#` comments.
+fn parse_toml(text: &str) -> Result<JsonValue, String> {
+    let mut fields = JsonValue::object();
+    for (number, line) in text.lines().enumerate() {
+        let line = match line.trim() {
+            x if x.starts_with('"') || x.starts_with('\'') => x,
+            x => x.split('#').next().unwrap().trim(),
+        };
+        if line.is_empty() {
+            continue;
+        }
+        let (key, value) = line.split_once('=').ok_or_else(|| format!("line {}: expected key = value", number + 1))?;
+        let value = value.trim();
+        let value = match value.strip_prefix('\'').and_then(|x| x.strip_suffix('\'')) {
+            Some(literal) => JsonValue::from(literal),
+            //bare values JSON can't read, such as 0x hex or a unit, are parsed along with the arguments
+            None => JsonValue::parse(value).unwrap_or_else(|_| JsonValue::from(value)),
+        };
+        fields.insert(key.trim().trim_matches('"'), value);
+    }
+    Ok(fields)
+}
+
+fn read_file(path: &str) -> Result<Vec<(String, JsonValue)>, String> {
+    let text = fs::read_to_string(path).map_err(|x| format!("could not read {}: {}", path, x))?;
+    let parsed = match text.trim_start().starts_with('{') {
+        true => JsonValue::parse(&text),
+        false => parse_toml(&text),
+    };
+    match parsed.map_err(|x| format!("{}: {}", path, x))? {
+        JsonValue::Object(fields) => Ok(fields),
+        _ => Err(format!("{}: expected an object of fields", path)),
+    }
+}
+
+///Converts a field value to raw protocol units.
+fn parse_number(value: &JsonValue) -> Result<i64, String> {
+    let text = match value {
+        JsonValue::Number(x) => return Ok(x.round() as i64),
+        JsonValue::String(x) => x.trim(),
+        x => return Err(format!("{} is not a number", x)),
+    };
+    if let Some(hex) = text.strip_prefix("0x") {
+        return i64::from_str_radix(hex, 16).map_err(|_| format!("{} is not a number", text));
+    }
+    let (number, scale) = [("deg", 32768.0), ("mm", 64.0), ("m", 64000.0), ("px", 32768.0)]
+        .iter()
+        .find_map(|(unit, scale)| text.strip_suffix(unit).map(|x| (x, *scale)))
+        .unwrap_or((text, 1.0));
+    let number: f64 = number.trim().parse().map_err(|_| format!("{} is not a number", text))?;
+    Ok((number * scale).round() as i64)
+}
+
+fn parse_bytes(value: &JsonValue) -> Result<Vec<u8>, String> {
+    let bytes: Vec<i64> = match value {
+        JsonValue::Array(x) => x.iter().map(parse_number).collect::<Result<_, _>>()?,
+        JsonValue::String(x) => {
+            let digits: Vec<char> = x.chars().filter(|x| !x.is_whitespace()).collect();
+            if !digits.len().is_multiple_of(2) {
+                return Err(format!("{} is not a whole number of hex bytes", x));
+            }
+            let parsed: Result<Vec<i64>, _> = digits.chunks(2).map(|x| i64::from_str_radix(&x.iter().collect::<String>(), 16)).collect();
+            parsed.map_err(|_| format!("{} is not hex", x))?
+        }
+        x => return Err(format!("{} is not a list of bytes", x)),
+    };
+    bytes.into_iter().map(|x| u8::try_from(x).map_err(|_| format!("{} is not a byte", x))).collect()
+}
+
+///Writes `value` into `field` of a serialised payload.
+fn set_field(payload: &mut [u8], field: &Field, value: &JsonValue) -> Result<(), String> {
+    let bytes = match field.width {
+        16 => {
+            let mut bytes = parse_bytes(value)?;
+            if bytes.len() > field.width {
+                return Err(format!("{} takes at most {} bytes", field.name, field.width));
+            }
+            bytes.resize(field.width, 0);
+            bytes
+        }
+        _ => {
+            let number = match (field.name, value) {
+                ("command", JsonValue::String(x)) => x.parse::<Commands>()? as i64,
+                _ => parse_number(value)?,
+            };
+            let bits = 8 * field.width as u32;
+            let range = match field.signed {
+                true => -(1_i64 << (bits - 1))..=(1_i64 << (bits - 1)) - 1,
+                false => 0..=(1_i64 << bits) - 1,
+            };
+            if !range.contains(&number) {
+                return Err(format!("{} is out of range for {}, which takes {} to {}", number, field.name, range.start(), range.end()));
+            }
+            number.to_be_bytes()[8 - field.width..].to_vec()
+        }
+    };
+    payload[field.offset..field.offset + field.width].copy_from_slice(&bytes);
+    Ok(())
+}
+
+fn default_payload(command: Commands) -> Payloads {
+    match command {
+        Commands::POLL => Payloads::PollPayload(PollPayload::default()),
+        Commands::SYSTEM_STATUS => Payloads::SystemStatusPayload(SystemStatusPayload::default()),
+        Commands::SYSTEM_PARAMS => Payloads::SystemControlPayload(SystemControlPayload::default()),
+        Commands::FIRST_TARGET | Commands::NEXT_TARGET => Payloads::TargetDataPayload(TargetDataPayload::default()),
+        Commands::FIRST_IMAGE | Commands::NEXT_IMAGE => Payloads::ImageDataPayload(ImageDataPayload::default()),
+        Commands::EEPROM_DATA => Payloads::EEPROMDataPayload(EEPROMDataPayload::default()),
+        Commands::REQUEST_EEPROM => Payloads::EEPROMDataRequestPayload(EEPROMDataRequestPayload::default()),
+        Commands::CAMERA_CALIBRATION => Payloads::CameraCalibrationPayload(CameraCalibrationPayload::default()),
+        Commands::DIAGNOSTIC_MODE => Payloads::DiagnosticModePayload(DiagnosticModePayload::default()),
+        _ => Payloads::PositionPollPayload(PositionPollPayload::default()),
+    }
+}
+
+///Builds the serialised message from the options, before any deliberate corruption.
+fn build(options: &Options) -> Result<Vec<u8>, String> {
+    let mut fields = match &options.file {
+        Some(path) => read_file(path)?,
+        None => Vec::new(),
+    };
+    fields.extend(options.fields.iter().map(|(key, value)| (key.clone(), JsonValue::from(value.as_str()))));
+
+    let mut command = options.command;
+    let mut cameraid = options.cameraid;
+    let mut values = Vec::new();
+    for (key, value) in fields {
+        match key.as_str() {
+            //the command line takes precedence over the file
+            "type" if options.command.is_none() => command = Some(parse_type(value.as_str().ok_or("type needs to be a string")?)?),
+            "camera" if options.cameraid.is_none() => cameraid = Some(u8::try_from(parse_number(&value)?).map_err(|_| format!("{} is not a camera id", value))?),
+            "type" | "camera" => {}
+            _ => values.push((key, value)),
+        }
+    }
+
+    let command = command.unwrap_or(Commands::POSITION_POLL);
+    let payloadtype = payload_type(command).expect("Type was checked when parsed");
+    let mut payload = default_payload(command).serialise();
+    for (key, value) in values {
+        let field = payloadtype.fields.iter().find(|x| x.name == key).ok_or_else(|| {
+            let names: Vec<&str> = payloadtype.fields.iter().map(|x| x.name).collect();
+            format!("{} has no field {}, only {}", payloadtype.name, key, names.join(", "))
+        })?;
+        set_field(&mut payload, field, &value).map_err(|x| format!("{}: {}", key, x))?;
+    }
+
+    //decode the fields through the library, so enums and flags are checked, then rebuild the message around them
+    let mut data = vec![command as u8, 0];
+    data.extend(payload);
+    data.push(generate_checksum(&data));
+    let decoded = deserialise_payloads(&data).map_err(|x| x.description)?;
+    let message = Message::with_command(decoded.payload, command, cameraid.unwrap_or(1)).map_err(|x| x.to_string())?;
+    Ok(message.serialise())
+}
+
+///Applies the corruption asked for, so that only the length or only the checksum is wrong unless both are asked for.
+fn corrupt(mut data: Vec<u8>, options: &Options) -> Vec<u8> {
+    if let Some(length) = options.length {
+        data.resize(length, 0);
+        if length >= 2 {
+            data[length - 1] = generate_checksum(&data[..length - 1]);
+        }
+    }
+    if options.badchecksum {
+        if let Some(checksum) = data.last_mut() {
+            *checksum ^= 0xFF;
+        }
+    }
+    data
+}
+
+enum Sink {
+    Udp(UdpSocket, SocketAddr),
+    Serial(File),
+}
+
+impl Sink {
+    fn open(options: &Options) -> io::Result<Sink> {
+        match &options.output {
+            Output::Udp(remote) => {
+                let socket = match remote.is_ipv4() {
+                    true => UdpSocket::bind("0.0.0.0:0")?,
+                    false => UdpSocket::bind("[::]:0")?,
+                };
+                if remote.ip().is_multicast() {
+                    set_multicast_options(&socket, remote.ip(), &options.multicast)?;
+                }
+                Ok(Sink::Udp(socket, *remote))
+            }
+            Output::Serial(path) => Ok(Sink::Serial(OpenOptions::new().write(true).open(path)?)),
+        }
+    }
+
+    fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        match self {
+            Sink::Udp(socket, remote) => socket.send_to(data, *remote).map(|_| ()),
+            Sink::Serial(file) => file.write_all(data).and_then(|_| file.flush()),
+        }
+    }
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let data = corrupt(build(options)?, options);
+    if options.dryrun {
+        println!("{}", hex(&data));
+        return Ok(());
+    }
+
+    let mut sink = Sink::open(options).map_err(|x| format!("could not open output: {}", x))?;
+    let interval = options.rate.map(|x| Duration::from_secs_f64(1.0 / x));
+    let mut nextsend = Instant::now();
+    let mut sent = 0;
+
+    while options.count == 0 || sent < options.count {
+        if let Some(interval) = interval {
+            thread::sleep(nextsend.saturating_duration_since(Instant::now()));
+            nextsend += interval;
+        }
+        sink.send(&data).map_err(|x| format!("send failed: {}", x))?;
+        sent += 1;
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args() {
+        Ok(Some(x)) => x,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(x) => {
+            eprintln!("freed-send: {}\n", x);
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Err(x) = run(&options) {
+        eprintln!("freed-send: {}", x);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn options() -> Options {
+        Options {
+            output: Output::Udp("127.0.0.1:40000".parse().unwrap()),
+            command: None,
+            cameraid: None,
+            file: None,
+            fields: Vec::new(),
+            count: 1,
+            rate: None,
+            badchecksum: false,
+            length: None,
+            multicast: MulticastOptions::default(),
+            dryrun: true,
+        }
+    }
+
+    fn field_named(name: &str, command: Commands) -> &'static Field {
+        payload_type(command).unwrap().fields.iter().find(|x| x.name == name).unwrap()
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_number(&JsonValue::from(12.4)).unwrap(), 12);
+        assert_eq!(parse_number(&JsonValue::from("-40")).unwrap(), -40);
+        assert_eq!(parse_number(&JsonValue::from("0x8000")).unwrap(), 0x8000);
+        assert_eq!(parse_number(&JsonValue::from("45deg")).unwrap(), 45 * 32768);
+        assert_eq!(parse_number(&JsonValue::from("1.5m")).unwrap(), 96000);
+        assert_eq!(parse_number(&JsonValue::from("-10 mm")).unwrap(), -640);
+        assert_eq!(parse_number(&JsonValue::from("0.5px")).unwrap(), 16384);
+
+        assert!(parse_number(&JsonValue::from("ten")).is_err());
+        assert!(parse_number(&JsonValue::from("5ft")).is_err());
+        assert!(parse_number(&JsonValue::from("0xZZ")).is_err());
+        assert!(parse_number(&JsonValue::from(true)).is_err());
+    }
+
+    #[test]
+    fn toml() {
+        let fields = parse_toml("# a position\ntype = \"position\"\ncamera = 2\nyaw = 45deg # panned\n\"pos_x\" = '1m'\n\n").unwrap();
+        assert_eq!(fields.get("type").and_then(|x| x.as_str()), Some("position"));
+        assert_eq!(fields.get("camera").and_then(|x| x.as_f64()), Some(2.0));
+        assert_eq!(fields.get("yaw").and_then(|x| x.as_str()), Some("45deg"));
+        assert_eq!(fields.get("pos_x").and_then(|x| x.as_str()), Some("1m"));
+
+        assert!(parse_toml("yaw 45").is_err());
+    }
+
+    #[test]
+    fn field_ranges() {
+        let mut payload = default_payload(Commands::POSITION_POLL).serialise();
+        let yaw = field_named("yaw", Commands::POSITION_POLL);
+        set_field(&mut payload, yaw, &JsonValue::from("-1")).unwrap();
+        assert_eq!(payload[yaw.offset..yaw.offset + 3], [0xFF, 0xFF, 0xFF]);
+        assert!(set_field(&mut payload, yaw, &JsonValue::from("0x7FFFFF")).is_ok());
+        assert!(set_field(&mut payload, yaw, &JsonValue::from("0x800000")).is_err());
+        assert!(set_field(&mut payload, yaw, &JsonValue::from(-8388609.0)).is_err());
+
+        let zoom = field_named("zoom", Commands::POSITION_POLL);
+        assert!(set_field(&mut payload, zoom, &JsonValue::from("0xFFFFFF")).is_ok());
+        assert!(set_field(&mut payload, zoom, &JsonValue::from("-1")).is_err());
+
+        let mut payload = default_payload(Commands::EEPROM_DATA).serialise();
+        let data = field_named("EEPROMdata", Commands::EEPROM_DATA);
+        set_field(&mut payload, data, &JsonValue::from("0102")).unwrap();
+        assert_eq!(payload[2..5], [1, 2, 0]);
+        assert!(set_field(&mut payload, data, &JsonValue::from("00".repeat(17).as_str())).is_err());
+        assert!(set_field(&mut payload, data, &JsonValue::from("123")).is_err());
+
+        let mut payload = default_payload(Commands::POLL).serialise();
+        set_field(&mut payload, field_named("command", Commands::POLL), &JsonValue::from("D2")).unwrap();
+        assert_eq!(payload, [0xD2]);
+    }
+
+    ///The fields of a payload's `Debug` output, by name.
+    fn debug_fields(payload: &Payloads) -> Vec<(String, String)> {
+        let text = format!("{:?}", payload);
+        let inner = &text[text.find(" { ").expect("a struct") + 3..text.rfind(" }").expect("a struct")];
+        let mut fields = Vec::new();
+        let (mut depth, mut start) = (0, 0);
+        for (index, character) in inner.char_indices() {
+            match character {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                ',' if depth == 0 => {
+                    fields.push(inner[start..index].trim().to_string());
+                    start = index + 1;
+                }
+                _ => {}
+            }
+        }
+        fields.push(inner[start..].trim().to_string());
+        fields.iter().map(|x| {
+            let (name, value) = x.split_once(": ").expect("name: value");
+            (name.to_string(), value.to_string())
+        }).collect()
+    }
+
+    #[test]
+    fn every_field() {
+        //the table of field offsets has to agree with how the library lays out each payload
+        for payloadtype in &TYPES {
+            for command in payloadtype.commands {
+                let defaults = debug_fields(&default_payload(*command));
+                for field in payloadtype.fields {
+                    let value = match field.name {
+                        "command" => "D1",
+                        "diagnosticflag" => "0x40",
+                        "EEPROMdata" => "0505",
+                        _ => "5",
+                    };
+                    let options = Options { command: Some(*command), fields: vec![(field.name.to_string(), value.to_string())], ..options() };
+                    let data = build(&options).unwrap_or_else(|x| panic!("{} {}: {}", payloadtype.name, field.name, x));
+                    assert_eq!(data[0], *command as u8);
+
+                    let decoded = deserialise_payloads(&data).unwrap();
+                    let changed: Vec<String> = debug_fields(&decoded.payload).into_iter()
+                        .filter(|x| !defaults.contains(x))
+                        .map(|(name, _)| name)
+                        .collect();
+                    assert_eq!(changed, vec![field.name.to_string()], "{} {}", payloadtype.name, field.name);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn corruption() {
+        let data = Message::new(PositionPollPayload::default(), 1).serialise();
+        let checksum = data[data.len() - 1];
+
+        let bad = corrupt(data.clone(), &Options { badchecksum: true, ..options() });
+        assert_eq!(bad.len(), data.len());
+        assert_eq!(bad[bad.len() - 1], checksum ^ 0xFF);
+
+        //a different length keeps a checksum that is valid for the bytes sent
+        let short = corrupt(data.clone(), &Options { length: Some(10), ..options() });
+        assert_eq!(short.len(), 10);
+        assert_eq!(short[9], generate_checksum(&short[..9]));
+        let long = corrupt(data.clone(), &Options { length: Some(32), ..options() });
+        assert_eq!(long[29..31], [0, 0]);
+        assert_eq!(long[31], generate_checksum(&long[..31]));
+
+        let both = corrupt(data.clone(), &Options { length: Some(10), badchecksum: true, ..options() });
+        assert_eq!(both[9], generate_checksum(&both[..9]) ^ 0xFF);
+
+        assert_eq!(corrupt(data.clone(), &options()), data);
+    }
+}
@@ -0,0 +1,217 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+
+use bytes::{Buf, BytesMut};
+use socket2::SockRef;
+use tokio::net::{ToSocketAddrs, UdpSocket};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::common::*;
+use crate::payloads::*;
+use crate::transport::*;
+
+///Frames free-d messages on a byte stream such as a serial port or TCP connection, for use with `FramedRead`,
+/// `FramedWrite` or `Framed`.
+///
+/// Messages are framed by the length implied by their command byte. Bytes that do not start a valid message, and
+/// messages with a bad checksum, are skipped one byte at a time until the stream lines up again, and are counted in
+/// `skipped` rather than ending the stream.
+///
+/// ```rust,ignore
+/// let mut messages = FramedRead::new(serial, FreeDCodec::default());
+/// while let Some(message) = messages.next().await {
+///     println!("{}", message?.payload);
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct FreeDCodec {
+    pub skipped: u64,
+}
+
+impl Decoder for FreeDCodec {
+    type Item = Message<Payloads>;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        while let Some(command) = src.first() {
+            let length = Commands::try_from(*command).ok().and_then(|x| x.message_length());
+            let Some(length) = length else {
+                src.advance(1);
+                self.skipped += 1;
+                continue;
+            };
+            if src.len() < length {
+                src.reserve(length - src.len());
+                return Ok(None);
+            }
+
+            match deserialise_payloads(&src[..length]) {
+                Ok(message) => {
+                    src.advance(length);
+                    return Ok(Some(message));
+                }
+                Err(_) => {
+                    src.advance(1);
+                    self.skipped += 1;
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<T: Serialise + Default + Copy> Encoder<Message<T>> for FreeDCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Message<T>, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        dst.extend_from_slice(&item.serialise());
+        Ok(())
+    }
+}
+
+///Receives free-d messages over UDP, one per datagram. Datagrams that are not a valid message are dropped and
+/// counted in `malformed`.
+pub struct AsyncUdpReceiver {
+    socket: UdpSocket,
+    pub received: u64,
+    pub malformed: u64,
+}
+
+impl AsyncUdpReceiver {
+    pub fn new(socket: UdpSocket) -> AsyncUdpReceiver {
+        AsyncUdpReceiver { socket, received: 0, malformed: 0 }
+    }
+
+    pub async fn bind<A: ToSocketAddrs>(local: A) -> io::Result<AsyncUdpReceiver> {
+        Ok(AsyncUdpReceiver::new(UdpSocket::bind(local).await?))
+    }
+
+    ///Joins a multicast group, receiving on a shared port as `bind_multicast` describes.
+    pub async fn multicast(group: SocketAddr, options: &MulticastOptions) -> io::Result<AsyncUdpReceiver> {
+        let socket = bind_multicast(group, options)?;
+        socket.set_nonblocking(true)?;
+        Ok(AsyncUdpReceiver::new(UdpSocket::from_std(socket)?))
+    }
+
+    pub fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+
+    pub fn join(&self, group: IpAddr, interface: MulticastInterface) -> io::Result<()> {
+        join_multicast(SockRef::from(&self.socket), group, interface)
+    }
+
+    pub fn leave(&self, group: IpAddr, interface: MulticastInterface) -> io::Result<()> {
+        leave_multicast(SockRef::from(&self.socket), group, interface)
+    }
+
+    ///Waits for the next valid message, returning it along with the address it came from.
+    pub async fn recv(&mut self) -> io::Result<(Message<Payloads>, SocketAddr)> {
+        let mut buffer = [0_u8; MAX_MESSAGE_LENGTH * 2];
+        loop {
+            let (length, source) = self.socket.recv_from(&mut buffer).await?;
+            self.received += 1;
+            match deserialise_payloads(&buffer[..length]) {
+                Ok(message) => return Ok((message, source)),
+                Err(_) => self.malformed += 1,
+            }
+        }
+    }
+}
+
+///Sends free-d messages over UDP to a single remote address, one per datagram.
+pub struct AsyncUdpSender {
+    socket: UdpSocket,
+    remote: SocketAddr,
+}
+
+impl AsyncUdpSender {
+    pub fn new(socket: UdpSocket, remote: SocketAddr) -> AsyncUdpSender {
+        AsyncUdpSender { socket, remote }
+    }
+
+    ///Binds a new socket to `local` and sends to `remote`.
+    pub async fn bind<A: ToSocketAddrs>(local: A, remote: SocketAddr) -> io::Result<AsyncUdpSender> {
+        Ok(AsyncUdpSender::new(UdpSocket::bind(local).await?, remote))
+    }
+
+    ///Binds a new socket to `local` for sending to the multicast group `remote`, with the given interface, TTL and
+    /// loopback.
+    pub async fn multicast<A: ToSocketAddrs>(local: A, remote: SocketAddr, options: &MulticastOptions) -> io::Result<AsyncUdpSender> {
+        let sender = AsyncUdpSender::bind(local, remote).await?;
+        configure_multicast(SockRef::from(&sender.socket), remote.ip(), options)?;
+        Ok(sender)
+    }
+
+    pub fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+
+    pub fn remote(&self) -> SocketAddr {
+        self.remote
+    }
+
+    pub async fn send<T: Serialise + Default + Copy>(&self, message: Message<T>) -> io::Result<()> {
+        self.socket.send_to(&message.serialise(), self.remote).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn codec_resynchronises() {
+        let mut codec = FreeDCodec::default();
+        let first = Message::new(PositionPollPayload::default(), 1).serialise();
+        let second = Message::new(SystemStatusPayload::default(), 2).serialise();
+
+        let mut bad = first.clone();
+        *bad.last_mut().unwrap() ^= 0xFF;
+
+        let mut buffer = BytesMut::new();
+        buffer.extend_from_slice(&[0x00, 0x13]);
+        buffer.extend_from_slice(&first[..10]);
+        assert!(codec.decode(&mut buffer).unwrap().is_none());
+        buffer.extend_from_slice(&first[10..]);
+        assert_eq!(codec.decode(&mut buffer).unwrap().unwrap().cameraid, 1);
+
+        buffer.extend_from_slice(&bad);
+        buffer.extend_from_slice(&second);
+        let message = codec.decode(&mut buffer).unwrap().unwrap();
+        assert_eq!(message.cameraid, 2);
+        assert_eq!(message.command(), Commands::SYSTEM_STATUS);
+        assert!(buffer.is_empty());
+        assert!(codec.skipped >= bad.len() as u64 + 2);
+
+        codec.encode(Message::new(PositionPollPayload::default(), 1), &mut buffer).unwrap();
+        assert_eq!(&buffer[..], &first[..]);
+    }
+
+    #[tokio::test]
+    async fn udp_round_trip() {
+        let mut receiver = AsyncUdpReceiver::bind("127.0.0.1:0").await.unwrap();
+        let sender = AsyncUdpSender::bind("127.0.0.1:0", receiver.socket().local_addr().unwrap()).await.unwrap();
+
+        sender.socket().send_to(&[0xD1, 0x01], sender.remote()).await.unwrap();
+        sender.send(Message::new(PositionPollPayload::default(), 7)).await.unwrap();
+
+        let (message, source) = receiver.recv().await.unwrap();
+        assert_eq!(message.cameraid, 7);
+        assert_eq!(source, sender.socket().local_addr().unwrap());
+        assert_eq!((receiver.received, receiver.malformed), (2, 1));
+    }
+
+    #[tokio::test]
+    async fn multicast() {
+        let options = MulticastOptions { interface: MulticastInterface::Address("127.0.0.1".parse().unwrap()), ..MulticastOptions::default() };
+        let group = "239.255.40.3:45124".parse().unwrap();
+        let mut receiver = AsyncUdpReceiver::multicast(group, &options).await.unwrap();
+        let sender = AsyncUdpSender::multicast("127.0.0.1:0", group, &options).await.unwrap();
+
+        sender.send(Message::new(PositionPollPayload::default(), 4)).await.unwrap();
+        assert_eq!(receiver.recv().await.unwrap().0.cameraid, 4);
+        receiver.leave(group.ip(), options.interface).unwrap();
+    }
+}
@@ -0,0 +1,195 @@
+use std::io::{ErrorKind, Read, Write};
+use std::time::Duration;
+
+use crate::error::CaptureError;
+use crate::resample::FrameRate;
+use crate::timecode::Timecode;
+
+const CAPTURE_MAGIC: &[u8; 4] = b"FDCP";
+const CAPTURE_VERSION: u8 = 1;
+
+const HAS_TIMECODE: u8 = 0x01;
+const DROP_FRAME: u8 = 0x02;
+
+///A message recorded in a capture file. `offset` is the time it was received relative to the start of the capture,
+/// and `data` the raw message as received, so malformed messages can be captured too.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CaptureRecord {
+    pub offset: Duration,
+    pub timecode: Option<Timecode>,
+    pub data: Vec<u8>,
+}
+
+///Writes messages to a capture file, for replaying later or lining up with editorial.
+///
+/// Captures start with a header (`FDCP`, a version byte, and the frame rate of the timecode as two big endian `u32`s).
+/// Each record is the offset in nanoseconds as a `u64`, a flags byte, the hours, minutes, seconds and frames of the
+/// timecode, the length of the message as a `u16` and then the message itself.
+pub struct CaptureWriter<W: Write> {
+    writer: W,
+    rate: FrameRate,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    ///Starts a capture whose timecodes are at `rate`.
+    pub fn new(mut writer: W, rate: FrameRate) -> Result<CaptureWriter<W>, CaptureError> {
+        writer.write_all(CAPTURE_MAGIC)?;
+        writer.write_all(&[CAPTURE_VERSION])?;
+        writer.write_all(&rate.frames.to_be_bytes())?;
+        writer.write_all(&rate.seconds.to_be_bytes())?;
+        Ok(CaptureWriter { writer, rate })
+    }
+
+    pub fn rate(&self) -> FrameRate {
+        self.rate
+    }
+
+    pub fn write(&mut self, record: &CaptureRecord) -> Result<(), CaptureError> {
+        let length = u16::try_from(record.data.len())
+            .map_err(|_| CaptureError::InvalidCapture(format!("{} byte message is too long to capture", record.data.len())))?;
+
+        let mut header = [0_u8; 15];
+        header[..8].copy_from_slice(&(record.offset.as_nanos() as u64).to_be_bytes());
+        if let Some(timecode) = record.timecode {
+            if timecode.rate != self.rate {
+                return Err(CaptureError::InvalidCapture(format!("timecode at {} fps in a {} fps capture", timecode.rate, self.rate)));
+            }
+            header[8] = HAS_TIMECODE | if timecode.dropframe { DROP_FRAME } else { 0 };
+            header[9..13].copy_from_slice(&[timecode.hours, timecode.minutes, timecode.seconds, timecode.frames]);
+        }
+        header[13..].copy_from_slice(&length.to_be_bytes());
+
+        self.writer.write_all(&header)?;
+        self.writer.write_all(&record.data)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), CaptureError> {
+        Ok(self.writer.flush()?)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+///Reads the records of a capture file written by a `CaptureWriter`, in order.
+///
+/// ```rust,ignore
+/// let reader = CaptureReader::new(BufReader::new(File::open("take1.fdcp")?))?;
+/// for record in reader {
+///     let record = record?;
+///     println!("{:?} {}", record.timecode, command_type(&record.data)?);
+/// }
+/// ```
+pub struct CaptureReader<R: Read> {
+    reader: R,
+    rate: FrameRate,
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub fn new(mut reader: R) -> Result<CaptureReader<R>, CaptureError> {
+        let mut header = [0_u8; 13];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != CAPTURE_MAGIC {
+            return Err(CaptureError::InvalidCapture("missing FDCP header".to_string()));
+        }
+        if header[4] != CAPTURE_VERSION {
+            return Err(CaptureError::InvalidCapture(format!("unsupported version {}", header[4])));
+        }
+
+        let frames = u32::from_be_bytes(header[5..9].try_into().unwrap());
+        let seconds = u32::from_be_bytes(header[9..13].try_into().unwrap());
+        let rate = FrameRate::new(frames, seconds).map_err(CaptureError::InvalidCapture)?;
+        Ok(CaptureReader { reader, rate })
+    }
+
+    ///The frame rate of the timecodes in the capture.
+    pub fn rate(&self) -> FrameRate {
+        self.rate
+    }
+
+    ///Reads the next record, or returns `None` at the end of the capture.
+    pub fn read(&mut self) -> Result<Option<CaptureRecord>, CaptureError> {
+        let mut header = [0_u8; 15];
+        match self.reader.read(&mut header[..1]) {
+            Ok(0) => return Ok(None),
+            Ok(_) => {}
+            Err(x) if x.kind() == ErrorKind::Interrupted => return self.read(),
+            Err(x) => return Err(x.into()),
+        }
+        self.reader.read_exact(&mut header[1..])?;
+
+        let offset = Duration::from_nanos(u64::from_be_bytes(header[..8].try_into().unwrap()));
+        let timecode = match header[8] & HAS_TIMECODE {
+            0 => None,
+            _ => {
+                let timecode = Timecode::new(header[9], header[10], header[11], header[12], self.rate, header[8] & DROP_FRAME != 0);
+                Some(timecode.map_err(CaptureError::InvalidCapture)?)
+            }
+        };
+
+        let length = u16::from_be_bytes(header[13..].try_into().unwrap()) as usize;
+        let mut data = vec![0_u8; length];
+        self.reader.read_exact(&mut data)?;
+
+        Ok(Some(CaptureRecord { offset, timecode, data }))
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = Result<CaptureRecord, CaptureError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read().transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::*;
+    use crate::payloads::*;
+
+    #[test]
+    fn round_trip() {
+        let rate = FrameRate::FPS_29_97;
+        let start = Timecode::parse("10:00:00;00", rate).unwrap();
+        let message = Message::new(PositionPollPayload::default(), 1).serialise();
+
+        let mut writer = CaptureWriter::new(Vec::new(), rate).unwrap();
+        let records: Vec<CaptureRecord> = (0..5_u32).map(|x| {
+            let offset = Duration::from_millis(x as u64 * 33);
+            CaptureRecord { offset, timecode: Some(start.after(offset)), data: message.clone() }
+        }).collect();
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        writer.write(&CaptureRecord { offset: Duration::from_secs(1), timecode: None, data: vec![0xD1, 0x01] }).unwrap();
+
+        let data = writer.into_inner();
+        let reader = CaptureReader::new(data.as_slice()).unwrap();
+        assert_eq!(reader.rate(), rate);
+        let read: Vec<CaptureRecord> = reader.map(|x| x.unwrap()).collect();
+        assert_eq!(read[..5], records[..]);
+        assert_eq!(read[4].timecode.unwrap().to_string(), "10:00:00;03");
+        assert_eq!(read[5].timecode, None);
+    }
+
+    #[test]
+    fn invalid_captures() {
+        assert!(CaptureReader::new(&b"FDEE\x01\x00\x00\x00\x19\x00\x00\x00\x01"[..]).is_err());
+        assert!(CaptureReader::new(&b"FDCP\x01\x00\x00\x00\x00\x00\x00\x00\x01"[..]).is_err());
+
+        let mut writer = CaptureWriter::new(Vec::new(), FrameRate::FPS_25).unwrap();
+        let timecode = Timecode::from_frame_number(0, FrameRate::FPS_24, false);
+        assert!(writer.write(&CaptureRecord { offset: Duration::ZERO, timecode: Some(timecode), data: vec![] }).is_err());
+
+        //a record cut short
+        writer.write(&CaptureRecord { offset: Duration::ZERO, timecode: None, data: vec![1, 2, 3] }).unwrap();
+        let mut data = writer.into_inner();
+        data.pop();
+        let mut reader = CaptureReader::new(data.as_slice()).unwrap();
+        assert!(reader.next().unwrap().is_err());
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::{self, Display};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::payloads::*;
+use crate::router::Stage;
+
+///A delay, either in milliseconds or in frames of video at a given frame rate.
+///
+/// Parsed from and displayed as `40ms` (or just `40`) for milliseconds, and `2.5@25` for 2.5 frames at 25 frames
+/// per second.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Delay {
+    Milliseconds(f64),
+    Frames { frames: f64, rate: f64 },
+}
+
+impl Delay {
+    fn seconds(self) -> f64 {
+        match self {
+            Delay::Milliseconds(x) => x / 1000.0,
+            Delay::Frames { frames, rate } => frames / rate,
+        }
+    }
+
+    ///The delay as a `Duration`. Delays too long to represent, which `from_str` rejects, saturate at `Duration::MAX`.
+    pub fn duration(self) -> Duration {
+        Duration::try_from_secs_f64(self.seconds().max(0.0)).unwrap_or(Duration::MAX)
+    }
+}
+
+impl From<Duration> for Delay {
+    fn from(value: Duration) -> Self {
+        Delay::Milliseconds(value.as_secs_f64() * 1000.0)
+    }
+}
+
+impl Display for Delay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Delay::Milliseconds(x) => write!(f, "{}ms", x),
+            Delay::Frames { frames, rate } => write!(f, "{}@{}", frames, rate),
+        }
+    }
+}
+
+impl FromStr for Delay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let number = |text: &str| match text.trim().parse::<f64>() {
+            Ok(x) if x.is_finite() && x >= 0.0 => Ok(x),
+            _ => Err(format!("{} is not a delay", s)),
+        };
+
+        let delay = match s.split_once('@') {
+            Some((frames, rate)) => {
+                let rate = number(rate)?;
+                if rate == 0.0 {
+                    return Err(format!("{} has a frame rate of zero", s));
+                }
+                Delay::Frames { frames: number(frames)?, rate }
+            }
+            None => Delay::Milliseconds(number(s.trim_end_matches("ms"))?),
+        };
+        if Duration::try_from_secs_f64(delay.seconds()).is_err() {
+            return Err(format!("{} is too long a delay", s));
+        }
+        Ok(delay)
+    }
+}
+
+///Delays a stream of messages to line tracking up with video that has been delayed by a long pipeline.
+///
+/// Every `PositionPollPayload` received causes the pose the camera had `delay` earlier to be released, linearly
+/// interpolated between the two samples either side of that time, so the delay need not be a whole number of packets
+/// and the output keeps the cadence of the input. Nothing is released for a camera until enough history has been
+/// buffered. Other messages are held back unchanged and released by `poll` once they are due.
+///
+/// The delay can be changed while running with `set_delay`. Rather than jumping, the delay slews towards the new
+/// value by at most `slew` seconds per second (0.05 by default), so the output slows down or speeds up briefly and
+/// no samples are skipped or repeated. A `slew` of 1 or more is treated as immediate.
+///
+/// ```rust,ignore
+/// let delay = Arc::new(Mutex::new(DelayLine::new(Delay::Frames { frames: 3.0, rate: 25.0 })));
+/// router.add_stage(Box::new(delay.clone()));
+///
+/// delay.lock().unwrap().set_delay(Delay::Milliseconds(160.0));
+/// ```
+#[derive(Clone, Debug)]
+pub struct DelayLine {
+    pub slew: f64,
+    delay: Duration,
+    target: Duration,
+    last: Option<Instant>,
+    poses: BTreeMap<u8, VecDeque<(Instant, PositionPollPayload)>>,
+    pending: VecDeque<(Instant, Message<Payloads>)>,
+}
+
+impl DelayLine {
+    pub fn new(delay: Delay) -> DelayLine {
+        let delay = delay.duration();
+        DelayLine { slew: 0.05, delay, target: delay, last: None, poses: BTreeMap::new(), pending: VecDeque::new() }
+    }
+
+    ///The delay currently being applied, which lags behind the one last set while slewing.
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    ///The delay being slewed towards.
+    pub fn target(&self) -> Duration {
+        self.target
+    }
+
+    pub fn set_delay(&mut self, delay: Delay) {
+        self.target = delay.duration();
+        if self.slew >= 1.0 {
+            self.delay = self.target;
+        }
+    }
+
+    fn update(&mut self, now: Instant) {
+        if let Some(last) = self.last {
+            let step = self.slew.clamp(0.0, 1.0) * now.saturating_duration_since(last).as_secs_f64();
+            let current = self.delay.as_secs_f64();
+            let target = self.target.as_secs_f64();
+            let seconds = if target > current { target.min(current + step) } else { target.max(current - step) };
+            //a saturated target rounds up past Duration::MAX as f64
+            self.delay = Duration::try_from_secs_f64(seconds).unwrap_or(Duration::MAX);
+        }
+        self.last = Some(self.last.map_or(now, |x| x.max(now)));
+
+        //keep one sample older than the furthest back the delay can reach
+        if let Some(horizon) = now.checked_sub(self.delay.max(self.target)) {
+            for samples in self.poses.values_mut() {
+                while samples.len() > 1 && samples[1].0 <= horizon {
+                    samples.pop_front();
+                }
+            }
+        }
+    }
+
+    ///The pose of `cameraid` at time `at`, if it has been buffered.
+    fn pose_at(&self, cameraid: u8, at: Instant) -> Option<PositionPollPayload> {
+        let samples = self.poses.get(&cameraid)?;
+        let after = samples.iter().position(|(time, _)| *time >= at);
+
+        match after {
+            Some(0) if samples[0].0 > at => None,
+            Some(0) => Some(samples[0].1),
+            Some(index) => {
+                let (t0, from) = samples[index - 1];
+                let (t1, to) = samples[index];
+                let fraction = at.duration_since(t0).as_secs_f64() / t1.duration_since(t0).as_secs_f64();
+                Some(from.interpolate(&to, fraction))
+            }
+            None => samples.back().map(|(_, x)| *x),
+        }
+    }
+
+    ///Buffers a message received at `received`, returning the delayed messages released by it.
+    pub fn push(&mut self, message: Message<Payloads>, received: Instant) -> Vec<Message<Payloads>> {
+        self.update(received);
+
+        let payload = match message.payload {
+            Payloads::PositionPollPayload(x) => x,
+            _ => {
+                self.pending.push_back((received, message));
+                return self.release(received);
+            }
+        };
+
+        self.poses.entry(message.cameraid).or_default().push_back((received, payload));
+
+        let mut released = self.release(received);
+        let pose = received.checked_sub(self.delay).and_then(|at| self.pose_at(message.cameraid, at));
+        if let Some(pose) = pose {
+            let mut delayed = message;
+            delayed.payload = Payloads::PositionPollPayload(pose);
+            released.push(delayed);
+        }
+        released
+    }
+
+    ///Releases messages other than poses that are due by `now`.
+    pub fn release(&mut self, now: Instant) -> Vec<Message<Payloads>> {
+        self.update(now);
+
+        let mut released = Vec::<Message<Payloads>>::new();
+        while let Some((received, _)) = self.pending.front() {
+            if *received + self.delay > now {
+                break;
+            }
+            released.push(self.pending.pop_front().expect("Checked non-empty").1);
+        }
+        released
+    }
+}
+
+impl Stage for DelayLine {
+    fn process(&mut self, message: Message<Payloads>, received: Instant) -> Vec<Message<Payloads>> {
+        self.push(message, received)
+    }
+
+    fn poll(&mut self, now: Instant) -> Vec<Message<Payloads>> {
+        self.release(now)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(20);
+
+    fn pose(x: f64) -> Message<Payloads> {
+        let mut payload = PositionPollPayload::default();
+        payload.set(Axis::X, x);
+        Message::new(Payloads::PositionPollPayload(payload), 1)
+    }
+
+    fn x(messages: &[Message<Payloads>]) -> Vec<f64> {
+        messages.iter().filter_map(|m| match m.payload {
+            Payloads::PositionPollPayload(p) => Some(p.get(Axis::X)),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn parse_delay() {
+        assert_eq!("40ms".parse::<Delay>().unwrap().duration(), Duration::from_millis(40));
+        assert_eq!("40".parse::<Delay>().unwrap(), Delay::Milliseconds(40.0));
+        assert_eq!("2@25".parse::<Delay>().unwrap().duration(), Duration::from_millis(80));
+        assert_eq!(Delay::Frames { frames: 2.5, rate: 25.0 }.to_string().parse::<Delay>().unwrap(), Delay::Frames { frames: 2.5, rate: 25.0 });
+        assert!("-1".parse::<Delay>().is_err());
+        assert!("1@0".parse::<Delay>().is_err());
+    }
+
+    #[test]
+    fn delay_too_long() {
+        assert!("1@1e-300".parse::<Delay>().is_err());
+        assert!("1e300".parse::<Delay>().is_err());
+        assert_eq!(Delay::Frames { frames: 1.0, rate: 1e-300 }.duration(), Duration::MAX);
+    }
+
+    #[test]
+    fn whole_and_fractional_delay() {
+        let start = Instant::now();
+        let mut line = DelayLine::new(Delay::Frames { frames: 2.0, rate: 50.0 });
+        let mut output = Vec::new();
+        for frame in 0..6 {
+            output.extend(line.push(pose(frame as f64 * 100.0), start + FRAME * frame));
+        }
+        assert_eq!(x(&output), vec![0.0, 100.0, 200.0, 300.0]);
+
+        let mut line = DelayLine::new(Delay::Milliseconds(30.0));
+        let mut output = Vec::new();
+        for frame in 0..6 {
+            output.extend(line.push(pose(frame as f64 * 100.0), start + FRAME * frame));
+        }
+        assert_eq!(x(&output), vec![50.0, 150.0, 250.0, 350.0]);
+    }
+
+    #[test]
+    fn other_messages_held() {
+        let start = Instant::now();
+        let mut line = DelayLine::new(Delay::Milliseconds(100.0));
+        let status = Message::new(Payloads::SystemStatusPayload(SystemStatusPayload::default()), 1);
+
+        assert!(line.push(status, start).is_empty());
+        assert!(line.release(start + Duration::from_millis(99)).is_empty());
+        assert_eq!(line.release(start + Duration::from_millis(100)).len(), 1);
+        assert!(line.release(start + Duration::from_millis(200)).is_empty());
+    }
+
+    #[test]
+    fn live_adjustment() {
+        let start = Instant::now();
+        let mut line = DelayLine::new(Delay::Milliseconds(40.0));
+        line.slew = 0.5;
+
+        let mut output = Vec::new();
+        for frame in 0..10 {
+            output.extend(line.push(pose(frame as f64 * 100.0), start + FRAME * frame));
+        }
+        line.set_delay(Delay::Milliseconds(80.0));
+        for frame in 10..30 {
+            output.extend(line.push(pose(frame as f64 * 100.0), start + FRAME * frame));
+        }
+        assert_eq!(line.delay(), Duration::from_millis(80));
+
+        //the output slows down for a while but never jumps backwards or skips ahead
+        let output = x(&output);
+        for pair in output.windows(2) {
+            assert!(pair[1] >= pair[0] && pair[1] - pair[0] <= 100.0, "{:?}", output);
+        }
+        assert_eq!(*output.last().unwrap(), 2500.0);
+    }
+}
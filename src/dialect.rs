@@ -0,0 +1,331 @@
+use std::fmt::{self, Display};
+use std::path::Path;
+
+use ux::u24;
+
+use crate::common::*;
+use crate::error::DeserialiseError;
+use crate::payloads::*;
+
+///A range of raw encoder values, stretched onto the full 24 bit range of a canonical zoom or focus value. `min` may
+/// be greater than `max` for encoders that count down.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EncoderRange {
+    pub min: u32,
+    pub max: u32,
+}
+
+impl EncoderRange {
+    fn normalise(self, raw: f64) -> f64 {
+        if self.min == self.max {
+            return 0.0;
+        }
+        (raw - self.min as f64) / (self.max as f64 - self.min as f64) * u32::from(u24::MAX) as f64
+    }
+
+    fn denormalise(self, canonical: f64) -> f64 {
+        self.min as f64 + canonical / u32::from(u24::MAX) as f64 * (self.max as f64 - self.min as f64)
+    }
+}
+
+///How a vendor's D1 position messages differ from the canonical layout.
+///
+/// `order` lists the axis carried by each field in the order the fields are sent, so a head sending pan before tilt
+/// has `Yaw` first. The signed and unsigned fields can only be reordered among themselves. Axes in `inverted` have
+/// their sign flipped. `zoom` and `focus` give the raw encoder ranges, which are stretched onto the full 24 bit range.
+/// `extra` is the number of bytes the vendor appends after `userdefined`. They are ignored when decoding, and the
+/// checksum is expected to cover them.
+///
+/// Profiles are written in a small config format, one section per dialect:
+///
+/// ```text
+/// [panfirst]
+/// order = yaw, pitch, roll, pos_z, pos_y, pos_x, zoom, focus
+/// inverted = pitch
+/// zoom = 0x555, 0xFFF
+/// focus = 0, 4095
+/// extra = 2
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dialect {
+    pub name: String,
+    pub order: [Axis; 8],
+    pub inverted: Vec<Axis>,
+    pub zoom: Option<EncoderRange>,
+    pub focus: Option<EncoderRange>,
+    pub extra: usize,
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Self::canonical()
+    }
+}
+
+impl Dialect {
+    ///The dialect of messages that already follow the canonical layout.
+    pub fn canonical() -> Dialect {
+        Dialect { name: "canonical".to_string(), order: Axis::ALL, inverted: Vec::new(), zoom: None, focus: None, extra: 0 }
+    }
+
+    ///Checks `order` is a permutation of the axes keeping zoom and focus in the unsigned fields, and that only signed
+    /// axes are inverted.
+    pub fn validate(&self) -> Result<(), String> {
+        for axis in Axis::ALL {
+            if !self.order.contains(&axis) {
+                return Err(format!("{}: {} is missing from the order", self.name, axis));
+            }
+        }
+        if !self.order[6..].iter().all(|x| matches!(x, Axis::Zoom | Axis::Focus)) {
+            return Err(format!("{}: only zoom and focus can be sent in the last two fields", self.name));
+        }
+        //negating an unsigned encoder value would clamp it to zero, use the zoom and focus ranges to reverse them
+        if let Some(axis) = self.inverted.iter().find(|x| matches!(x, Axis::Zoom | Axis::Focus)) {
+            return Err(format!("{}: {} can't be inverted, give its range from high to low instead", self.name, axis));
+        }
+        Ok(())
+    }
+
+    ///Converts a payload decoded as if it were canonical into the canonical pose.
+    pub fn normalise(&self, payload: &PositionPollPayload) -> PositionPollPayload {
+        let mut output = *payload;
+        for (field, axis) in Axis::ALL.iter().zip(self.order) {
+            let mut value = payload.get(*field);
+            if self.inverted.contains(&axis) {
+                value = -value;
+            }
+            value = match (axis, self.zoom, self.focus) {
+                (Axis::Zoom, Some(range), _) | (Axis::Focus, _, Some(range)) => range.normalise(value),
+                _ => value,
+            };
+            output.set(axis, value);
+        }
+        output
+    }
+
+    ///Converts a canonical pose into the payload this dialect would send, the reverse of `normalise`.
+    pub fn denormalise(&self, payload: &PositionPollPayload) -> PositionPollPayload {
+        let mut output = *payload;
+        for (field, axis) in Axis::ALL.iter().zip(self.order) {
+            let mut value = payload.get(axis);
+            value = match (axis, self.zoom, self.focus) {
+                (Axis::Zoom, Some(range), _) | (Axis::Focus, _, Some(range)) => range.denormalise(value),
+                _ => value,
+            };
+            if self.inverted.contains(&axis) {
+                value = -value;
+            }
+            output.set(*field, value);
+        }
+        output
+    }
+
+    ///Decodes a complete D1 message in this dialect into a canonical pose.
+    pub fn decode(&self, data: &[u8]) -> Result<Message<PositionPollPayload>, DeserialiseError> {
+        let length = Commands::POSITION_POLL.message_length().expect("Fixed length") + self.extra;
+        if data.len() != length {
+            return Err(DeserialiseError { description: format!("{} expects {} byte D1 messages, not {}", self.name, length, data.len()) });
+        }
+        if data[0] != Commands::POSITION_POLL as u8 {
+            return Err(DeserialiseError { description: format!("0x{:02X} is not a D1 message", data[0]) });
+        }
+        if generate_checksum(&data[..length - 1]) != data[length - 1] {
+            return Err(DeserialiseError { description: "Checksum does not match".to_string() });
+        }
+
+        //strip the extra bytes, leaving a canonical message
+        let mut canonical = data[..length - self.extra - 1].to_vec();
+        canonical.push(generate_checksum(&canonical));
+
+        let mut message = deserialise::<PositionPollPayload>(&canonical)?;
+        message.payload = self.normalise(&message.payload);
+        Ok(message)
+    }
+
+    ///Encodes a canonical pose as a D1 message in this dialect, with the extra bytes set to zero.
+    pub fn encode(&self, message: &Message<PositionPollPayload>) -> Vec<u8> {
+        let mut vendor = *message;
+        vendor.payload = self.denormalise(&message.payload);
+
+        let mut data = vendor.serialise();
+        data.pop();
+        data.extend(std::iter::repeat_n(0, self.extra));
+        data.push(generate_checksum(&data));
+        data
+    }
+
+    ///Parses every profile in a config file.
+    pub fn parse_profiles(text: &str) -> Result<Vec<Dialect>, String> {
+        let mut dialects = Vec::<Dialect>::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let error = |x: String| format!("line {}: {}", number + 1, x);
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
+                dialects.push(Dialect { name: name.trim().to_string(), ..Dialect::canonical() });
+                continue;
+            }
+
+            let dialect = dialects.last_mut().ok_or_else(|| error("settings must follow a [name]".to_string()))?;
+            let (key, value) = line.split_once('=').ok_or_else(|| error(format!("expected key = value, found {}", line)))?;
+            let values: Vec<&str> = value.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()).collect();
+
+            match key.trim() {
+                "order" => {
+                    let axes = values.iter().map(|x| x.parse::<Axis>()).collect::<Result<Vec<Axis>, String>>().map_err(error)?;
+                    dialect.order = axes.try_into().map_err(|_| error("order must list all 8 axes".to_string()))?;
+                }
+                "inverted" => dialect.inverted = values.iter().map(|x| x.parse::<Axis>()).collect::<Result<_, String>>().map_err(error)?,
+                "zoom" => dialect.zoom = Some(parse_range(&values).map_err(error)?),
+                "focus" => dialect.focus = Some(parse_range(&values).map_err(error)?),
+                "extra" => dialect.extra = value.trim().parse().map_err(|_| error(format!("{} is not a number of bytes", value.trim())))?,
+                x => return Err(error(format!("unknown setting {}", x))),
+            }
+        }
+
+        for dialect in &dialects {
+            dialect.validate()?;
+        }
+        Ok(dialects)
+    }
+
+    pub fn load_profiles<P: AsRef<Path>>(path: P) -> Result<Vec<Dialect>, String> {
+        let text = std::fs::read_to_string(&path).map_err(|x| format!("{}: {}", path.as_ref().display(), x))?;
+        Dialect::parse_profiles(&text)
+    }
+}
+
+fn parse_range(values: &[&str]) -> Result<EncoderRange, String> {
+    let number = |text: &str| match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => text.parse::<u32>(),
+    }.map_err(|_| format!("{} is not an encoder value", text));
+
+    match values {
+        [min, max] => Ok(EncoderRange { min: number(min)?, max: number(max)? }),
+        _ => Err("expected a range as min, max".to_string()),
+    }
+}
+
+///Writes the dialect as a profile that `Dialect::parse_profiles` reads back.
+impl Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |axes: &[Axis]| axes.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(", ");
+
+        writeln!(f, "[{}]", self.name)?;
+        writeln!(f, "order = {}", list(&self.order))?;
+        if !self.inverted.is_empty() {
+            writeln!(f, "inverted = {}", list(&self.inverted))?;
+        }
+        if let Some(range) = self.zoom {
+            writeln!(f, "zoom = {}, {}", range.min, range.max)?;
+        }
+        if let Some(range) = self.focus {
+            writeln!(f, "focus = {}, {}", range.min, range.max)?;
+        }
+        writeln!(f, "extra = {}", self.extra)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ux::i24;
+
+    const PROFILES: &str = "
+        # pan is sent first and tilts the other way
+        [panfirst]
+        order = yaw, pitch, roll, pos_z, pos_y, pos_x, zoom, focus
+        inverted = pitch
+        zoom = 0x555, 0xFFF
+        focus = 4095, 0
+        extra = 2
+
+        [plain]
+    ";
+
+    fn canonical_pose() -> PositionPollPayload {
+        PositionPollPayload {
+            pitch: i24::new(-1000),
+            yaw: i24::new(2000),
+            roll: i24::new(30),
+            pos_x: i24::new(64),
+            zoom: u24::new(0),
+            focus: u24::MAX,
+            ..PositionPollPayload::default()
+        }
+    }
+
+    #[test]
+    fn parse_profiles() {
+        let dialects = Dialect::parse_profiles(PROFILES).unwrap();
+        assert_eq!(dialects.len(), 2);
+        assert_eq!(dialects[0].order[0], Axis::Yaw);
+        assert_eq!(dialects[0].inverted, vec![Axis::Pitch]);
+        assert_eq!(dialects[0].zoom, Some(EncoderRange { min: 0x555, max: 0xFFF }));
+        assert_eq!(dialects[1], Dialect { name: "plain".to_string(), ..Dialect::canonical() });
+
+        assert_eq!(Dialect::parse_profiles(&dialects[0].to_string()).unwrap()[0], dialects[0]);
+
+        assert!(Dialect::parse_profiles("order = yaw").is_err());
+        assert!(Dialect::parse_profiles("[x]\norder = yaw, pitch").is_err());
+        assert!(Dialect::parse_profiles("[x]\norder = zoom, yaw, roll, pos_z, pos_y, pos_x, pitch, focus").is_err());
+        assert!(Dialect::parse_profiles("[x]\ncolour = blue").is_err());
+        assert!(Dialect::parse_profiles("[x]\ninverted = zoom").is_err());
+    }
+
+    #[test]
+    fn decode_vendor_message() {
+        let dialect = &Dialect::parse_profiles(PROFILES).unwrap()[0];
+
+        //as the vendor would send it
+        let vendor = PositionPollPayload {
+            pitch: i24::new(2000),
+            yaw: i24::new(1000),
+            roll: i24::new(30),
+            pos_x: i24::new(64),
+            zoom: u24::new(0x555),
+            focus: u24::new(0),
+            ..PositionPollPayload::default()
+        };
+        let mut data = Message::new(vendor, 1).serialise();
+        data.pop();
+        data.extend([0xAB, 0xCD]);
+        data.push(generate_checksum(&data));
+
+        let message = dialect.decode(&data).unwrap();
+        assert_eq!(message.payload, canonical_pose());
+        assert_eq!(message.cameraid, 1);
+        assert_eq!(message.command(), Commands::POSITION_POLL);
+
+        assert!(dialect.decode(&data[..29]).is_err());
+        data[29] ^= 0xFF;
+        assert!(dialect.decode(&data).is_err());
+    }
+
+    #[test]
+    fn decode_other_command() {
+        //a camera calibration message is one byte longer than a D1 message, like a dialect with one extra byte
+        let dialect = Dialect { extra: 1, ..Dialect::canonical() };
+        let data = Message::new(CameraCalibrationPayload::default(), 1).serialise();
+        assert_eq!(data.len(), 30);
+        assert!(dialect.decode(&data).is_err());
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let dialects = Dialect::parse_profiles(PROFILES).unwrap();
+        let message = Message::new(canonical_pose(), 3);
+
+        for dialect in dialects.iter().chain([Dialect::canonical()].iter()) {
+            let data = dialect.encode(&message);
+            assert_eq!(data.len(), 29 + dialect.extra);
+            assert_eq!(dialect.decode(&data).unwrap().payload, canonical_pose());
+        }
+        assert_eq!(Dialect::canonical().encode(&message), message.serialise());
+    }
+}
@@ -0,0 +1,456 @@
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::common::*;
+use crate::error::EEPROMError;
+use crate::payloads::*;
+use crate::transport::*;
+
+///Number of bytes carried by a single `EEPROMDataPayload`.
+pub const EEPROM_BLOCK_SIZE: usize = 16;
+
+///Size of the address space reachable through the 16 bit `EEPROMaddress` field.
+pub const EEPROM_ADDRESS_SPACE: usize = 0x10000;
+
+const IMAGE_MAGIC: &[u8; 4] = b"FDEE";
+
+///An in-memory copy of a region of a free-d unit's EEPROM, made up of `EEPROM_BLOCK_SIZE` blocks.
+/// Each block remembers whether it has been read yet, so a partially read image can be passed back
+/// to `EEPROMManager::read_image` to resume the transfer.
+///
+/// Images are saved to disk as a small header (`FDEE`, the start address and the length), followed by
+/// the raw EEPROM bytes and one byte per block marking whether it is valid.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EEPROMImage {
+    start: u16,
+    data: Vec<u8>,
+    valid: Vec<bool>,
+}
+
+///A contiguous run of bytes that differ between two images. `address` is the EEPROM address of the first byte.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EEPROMDifference {
+    pub address: u16,
+    pub ours: Vec<u8>,
+    pub theirs: Vec<u8>,
+}
+
+///Progress of an EEPROM transfer, reported once per block.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EEPROMProgress {
+    pub address: u16,
+    pub completed: usize,
+    pub total: usize,
+    pub retries: usize,
+}
+
+impl EEPROMImage {
+    ///Creates an empty image covering `blocks` blocks from `start`. Panics if the region runs past the end of
+    /// the address space or `start` is not aligned to a block.
+    pub fn new(start: u16, blocks: usize) -> EEPROMImage {
+        assert!((start as usize).is_multiple_of(EEPROM_BLOCK_SIZE), "EEPROM images must start on a block boundary");
+        assert!(start as usize + blocks * EEPROM_BLOCK_SIZE <= EEPROM_ADDRESS_SPACE, "EEPROM image runs past the address space");
+
+        EEPROMImage { start, data: vec![0; blocks * EEPROM_BLOCK_SIZE], valid: vec![false; blocks] }
+    }
+
+    ///Creates an empty image of the entire address space.
+    pub fn full() -> EEPROMImage {
+        EEPROMImage::new(0, EEPROM_ADDRESS_SPACE / EEPROM_BLOCK_SIZE)
+    }
+
+    pub fn start(&self) -> u16 {
+        self.start
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.valid.len()
+    }
+
+    ///EEPROM address of the block at `index`.
+    pub fn block_address(&self, index: usize) -> u16 {
+        (self.start as usize + index * EEPROM_BLOCK_SIZE) as u16
+    }
+
+    pub fn block(&self, index: usize) -> [u8; EEPROM_BLOCK_SIZE] {
+        self.data[index * EEPROM_BLOCK_SIZE..(index + 1) * EEPROM_BLOCK_SIZE].try_into().unwrap()
+    }
+
+    ///Stores a block and marks it as valid.
+    pub fn set_block(&mut self, index: usize, data: [u8; EEPROM_BLOCK_SIZE]) {
+        self.data[index * EEPROM_BLOCK_SIZE..(index + 1) * EEPROM_BLOCK_SIZE].copy_from_slice(&data);
+        self.valid[index] = true;
+    }
+
+    pub fn is_block_valid(&self, index: usize) -> bool {
+        self.valid[index]
+    }
+
+    ///True once every block in the image has been read.
+    pub fn is_complete(&self) -> bool {
+        self.valid.iter().all(|x| *x)
+    }
+
+    ///Compares two images covering the same region, returning each run of differing bytes.
+    pub fn diff(&self, other: &EEPROMImage) -> Result<Vec<EEPROMDifference>, EEPROMError> {
+        if self.start != other.start || self.data.len() != other.data.len() {
+            return Err(EEPROMError::InvalidImage(format!(
+                "cannot diff {:#06x}+{} bytes against {:#06x}+{} bytes",
+                self.start, self.data.len(), other.start, other.data.len())));
+        }
+
+        let mut differences = Vec::<EEPROMDifference>::new();
+        let mut run: Option<usize> = None;
+
+        for index in 0..=self.data.len() {
+            let differs = index < self.data.len() && self.data[index] != other.data[index];
+            match (run, differs) {
+                (None, true) => run = Some(index),
+                (Some(from), false) => {
+                    differences.push(EEPROMDifference {
+                        address: (self.start as usize + from) as u16,
+                        ours: self.data[from..index].to_vec(),
+                        theirs: other.data[from..index].to_vec(),
+                    });
+                    run = None;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(differences)
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), EEPROMError> {
+        writer.write_all(IMAGE_MAGIC)?;
+        writer.write_all(&self.start.to_be_bytes())?;
+        writer.write_all(&(self.data.len() as u32).to_be_bytes())?;
+        writer.write_all(&self.data)?;
+        writer.write_all(&self.valid.iter().map(|x| *x as u8).collect::<Vec<u8>>())?;
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<EEPROMImage, EEPROMError> {
+        let mut header = [0_u8; 10];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != IMAGE_MAGIC {
+            return Err(EEPROMError::InvalidImage("missing FDEE header".to_string()));
+        }
+
+        let start = u16::from_be_bytes(header[4..6].try_into().unwrap());
+        let length = u32::from_be_bytes(header[6..10].try_into().unwrap()) as usize;
+        if !length.is_multiple_of(EEPROM_BLOCK_SIZE) || !(start as usize).is_multiple_of(EEPROM_BLOCK_SIZE)
+            || start as usize + length > EEPROM_ADDRESS_SPACE {
+            return Err(EEPROMError::InvalidImage(format!("{} bytes from {:#06x} is not a valid block range", length, start)));
+        }
+
+        let mut image = EEPROMImage::new(start, length / EEPROM_BLOCK_SIZE);
+        reader.read_exact(&mut image.data)?;
+        let mut valid = vec![0_u8; image.valid.len()];
+        reader.read_exact(&mut valid)?;
+        image.valid = valid.iter().map(|x| *x != 0).collect();
+
+        Ok(image)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), EEPROMError> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_to(&mut file)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<EEPROMImage, EEPROMError> {
+        let mut file = std::io::BufReader::new(std::fs::File::open(path)?);
+        EEPROMImage::read_from(&mut file)
+    }
+}
+
+///Reads and writes a unit's EEPROM block by block using `EEPROMDataRequestPayload` and `EEPROMDataPayload` messages.
+///
+/// Every request waits up to `timeout` for the matching reply, and is retried up to `retries` times before giving up.
+/// With `verify_reads` set, each block is read twice and only accepted if both reads agree. Writes are always
+/// verified by reading the block back.
+///
+/// ```rust,ignore
+/// let transport = UdpTransport::bind(local, unit)?;
+/// let mut manager = EEPROMManager::new(transport, ALL_CAMERAS);
+///
+/// let mut image = EEPROMImage::full();
+/// while let Err(EEPROMError::Timeout { .. }) = manager.read_image(&mut image, |_| {}) {
+///     //blocks already read are kept, so just try again
+/// }
+/// image.save("unit.fdee")?;
+/// ```
+pub struct EEPROMManager<T: Transport> {
+    transport: T,
+    pub cameraid: u8,
+    pub timeout: Duration,
+    pub retries: usize,
+    pub verify_reads: bool,
+    retried: usize,
+}
+
+impl<T: Transport> EEPROMManager<T> {
+    pub fn new(transport: T, cameraid: u8) -> EEPROMManager<T> {
+        EEPROMManager {
+            transport,
+            cameraid,
+            timeout: Duration::from_millis(200),
+            retries: 3,
+            verify_reads: true,
+            retried: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+
+    ///Reads a single block, retrying on timeouts and (with `verify_reads`) inconsistent reads.
+    pub fn read_block(&mut self, address: u16) -> Result<[u8; EEPROM_BLOCK_SIZE], EEPROMError> {
+        let mut inconsistent = false;
+
+        for attempt in 0..=self.retries {
+            if attempt > 0 {
+                self.retried += 1;
+            }
+
+            let first = match self.request_block(address)? {
+                Some(x) => x,
+                None => continue,
+            };
+            if !self.verify_reads {
+                return Ok(first);
+            }
+
+            match self.request_block(address)? {
+                Some(second) if second == first => return Ok(first),
+                Some(_) => inconsistent = true,
+                None => {}
+            }
+        }
+
+        match inconsistent {
+            true => Err(EEPROMError::VerifyFailed { address }),
+            false => Err(EEPROMError::Timeout { address }),
+        }
+    }
+
+    ///Writes a single block and reads it back to check it was stored.
+    pub fn write_block(&mut self, address: u16, data: [u8; EEPROM_BLOCK_SIZE]) -> Result<(), EEPROMError> {
+        let message = Message::new(EEPROMDataPayload { EEPROMaddress: address, EEPROMdata: data }, self.cameraid);
+
+        for attempt in 0..=self.retries {
+            if attempt > 0 {
+                self.retried += 1;
+            }
+
+            self.transport.send(&message.serialise())?;
+            if self.request_block(address)? == Some(data) {
+                return Ok(());
+            }
+        }
+
+        Err(EEPROMError::VerifyFailed { address })
+    }
+
+    ///Reads every block of `image` that is not yet valid. If this fails part way through, the blocks that were
+    /// read are kept in `image`, and calling this again resumes from the first missing block.
+    pub fn read_image<F: FnMut(EEPROMProgress)>(&mut self, image: &mut EEPROMImage, mut progress: F) -> Result<(), EEPROMError> {
+        let total = image.block_count();
+
+        for index in 0..total {
+            if image.is_block_valid(index) {
+                continue;
+            }
+
+            let address = image.block_address(index);
+            let data = self.read_block(address)?;
+            image.set_block(index, data);
+
+            progress(EEPROMProgress { address, completed: index + 1, total, retries: self.retried });
+        }
+
+        Ok(())
+    }
+
+    ///Writes every valid block of `image` at or after `from`, verifying each one. Pass `image.start()` to
+    /// restore the whole image, or the address from a failed attempt's error to resume it.
+    pub fn write_image<F: FnMut(EEPROMProgress)>(&mut self, image: &EEPROMImage, from: u16, mut progress: F) -> Result<(), EEPROMError> {
+        let total = image.block_count();
+
+        for index in 0..total {
+            let address = image.block_address(index);
+            if address < from || !image.is_block_valid(index) {
+                continue;
+            }
+
+            self.write_block(address, image.block(index))?;
+
+            progress(EEPROMProgress { address, completed: index + 1, total, retries: self.retried });
+        }
+
+        Ok(())
+    }
+
+    ///Sends a single request and waits for the matching reply. Unrelated or corrupt messages are ignored.
+    /// Returns `None` if no reply arrives within the timeout.
+    fn request_block(&mut self, address: u16) -> Result<Option<[u8; EEPROM_BLOCK_SIZE]>, EEPROMError> {
+        let request = Message::new(EEPROMDataRequestPayload { EEPROMaddress: address }, self.cameraid);
+        self.transport.send(&request.serialise())?;
+
+        let deadline = Instant::now() + self.timeout;
+        let mut buffer = [0_u8; MAX_MESSAGE_LENGTH];
+
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+
+            let length = match self.transport.receive(&mut buffer, Some(deadline - now)) {
+                Ok(x) => x,
+                Err(x) if is_timeout(&x) => return Ok(None),
+                Err(x) => return Err(x.into()),
+            };
+
+            if let Ok(reply) = deserialise::<EEPROMDataPayload>(&buffer[..length]) {
+                if reply.command() == Commands::EEPROM_DATA && reply.payload.EEPROMaddress == address {
+                    return Ok(Some(reply.payload.EEPROMdata));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::io;
+
+    ///Answers EEPROM requests from memory, optionally swallowing the first few replies.
+    struct SimulatedUnit {
+        memory: Vec<u8>,
+        replies: VecDeque<Vec<u8>>,
+        drop_replies: usize,
+    }
+
+    impl SimulatedUnit {
+        fn new(size: usize) -> SimulatedUnit {
+            SimulatedUnit { memory: (0..size).map(|x| (x * 7) as u8).collect(), replies: VecDeque::new(), drop_replies: 0 }
+        }
+    }
+
+    impl Transport for SimulatedUnit {
+        fn send(&mut self, data: &[u8]) -> io::Result<()> {
+            match command_type(data).unwrap() {
+                Commands::REQUEST_EEPROM => {
+                    let request = deserialise::<EEPROMDataRequestPayload>(data).unwrap().payload;
+                    let address = request.EEPROMaddress as usize;
+                    let payload = EEPROMDataPayload {
+                        EEPROMaddress: request.EEPROMaddress,
+                        EEPROMdata: self.memory[address..address + EEPROM_BLOCK_SIZE].try_into().unwrap(),
+                    };
+                    if self.drop_replies > 0 {
+                        self.drop_replies -= 1;
+                    } else {
+                        self.replies.push_back(Message::new(payload, ALL_CAMERAS).serialise());
+                    }
+                }
+                Commands::EEPROM_DATA => {
+                    let payload = deserialise::<EEPROMDataPayload>(data).unwrap().payload;
+                    let address = payload.EEPROMaddress as usize;
+                    self.memory[address..address + EEPROM_BLOCK_SIZE].copy_from_slice(&payload.EEPROMdata);
+                }
+                _ => {}
+            }
+            Ok(())
+        }
+
+        fn receive(&mut self, buffer: &mut [u8], _timeout: Option<Duration>) -> io::Result<usize> {
+            match self.replies.pop_front() {
+                Some(x) => {
+                    buffer[..x.len()].copy_from_slice(&x);
+                    Ok(x.len())
+                }
+                None => Err(io::Error::from(io::ErrorKind::TimedOut)),
+            }
+        }
+    }
+
+    #[test]
+    fn read_image() {
+        let unit = SimulatedUnit::new(256);
+        let expected = unit.memory.clone();
+        let mut manager = EEPROMManager::new(unit, ALL_CAMERAS);
+
+        let mut image = EEPROMImage::new(0, 256 / EEPROM_BLOCK_SIZE);
+        let mut reports = 0;
+        manager.read_image(&mut image, |_| reports += 1).unwrap();
+
+        assert!(image.is_complete());
+        assert_eq!(image.data(), &expected[..]);
+        assert_eq!(reports, 16);
+    }
+
+    #[test]
+    fn read_image_resumes_after_timeout() {
+        let mut unit = SimulatedUnit::new(64);
+        unit.drop_replies = 2;
+        let mut manager = EEPROMManager::new(unit, ALL_CAMERAS);
+        manager.retries = 1;
+        manager.verify_reads = false;
+
+        let mut image = EEPROMImage::new(0, 4);
+        match manager.read_image(&mut image, |_| {}) {
+            Err(EEPROMError::Timeout { address }) => assert_eq!(address, 0),
+            _ => panic!("expected a timeout"),
+        }
+
+        manager.read_image(&mut image, |_| {}).unwrap();
+        assert!(image.is_complete());
+    }
+
+    #[test]
+    fn write_image_and_diff() {
+        let unit = SimulatedUnit::new(64);
+        let mut manager = EEPROMManager::new(unit, ALL_CAMERAS);
+
+        let mut original = EEPROMImage::new(0, 4);
+        manager.read_image(&mut original, |_| {}).unwrap();
+
+        let mut modified = original.clone();
+        modified.set_block(2, [0xAA; EEPROM_BLOCK_SIZE]);
+        manager.write_image(&modified, modified.start(), |_| {}).unwrap();
+
+        let mut readback = EEPROMImage::new(0, 4);
+        manager.read_image(&mut readback, |_| {}).unwrap();
+        assert_eq!(readback, modified);
+
+        let differences = original.diff(&readback).unwrap();
+        assert_eq!(differences.len(), 1);
+        assert_eq!(differences[0].address, 0x20);
+        assert_eq!(differences[0].theirs, vec![0xAA; EEPROM_BLOCK_SIZE]);
+    }
+
+    #[test]
+    fn image_save_and_load() {
+        let mut image = EEPROMImage::new(0x0100, 3);
+        image.set_block(1, [0x55; EEPROM_BLOCK_SIZE]);
+
+        let mut serial = Vec::<u8>::new();
+        image.write_to(&mut serial).unwrap();
+
+        let loaded = EEPROMImage::read_from(&mut serial.as_slice()).unwrap();
+        assert_eq!(loaded, image);
+        assert!(!loaded.is_block_valid(0));
+        assert!(loaded.is_block_valid(1));
+    }
+}
@@ -8,7 +8,7 @@ pub struct DeserialiseError {
 }
 impl DeserialiseError {
     pub fn length_template(length: usize, payload: &str) -> String {
-        return format!("Misformed data - the array must be exactly {} bytes for the payload type: {}", length, payload);
+        format!("Misformed data - the array must be exactly {} bytes for the payload type: {}", length, payload)
     }
 }
 impl std::fmt::Display for DeserialiseError {
@@ -17,6 +17,8 @@ impl std::fmt::Display for DeserialiseError {
     }
 }
 
+impl Error for DeserialiseError {}
+
 #[derive(Debug)]
 pub struct InvalidCommand {
     pub allowedcommands: (Commands, Commands),
@@ -25,8 +27,106 @@ pub struct InvalidCommand {
 
 impl Display for InvalidCommand {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Only {} or {} commands may be used with the {} payload", self.allowedcommands.1, self.allowedcommands.1, self.payload)
+        match self.allowedcommands {
+            (x, y) if x == y => write!(f, "Only {} commands may be used with the {} payload", x, self.payload),
+            (x, y) => write!(f, "Only {} or {} commands may be used with the {} payload", x, y, self.payload),
+        }
     }   
 }
 
-impl Error for InvalidCommand {}
\ No newline at end of file
+impl Error for InvalidCommand {}
+
+///Errors raised while reading or writing the EEPROM of a free-d unit with an `EEPROMManager`.
+/// `Timeout` and `VerifyFailed` carry the address of the block that failed, so the transfer may be resumed from there.
+#[derive(Debug)]
+pub enum EEPROMError {
+    Io(std::io::Error),
+    Timeout { address: u16 },
+    VerifyFailed { address: u16 },
+    InvalidImage(String),
+}
+
+impl Display for EEPROMError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(x) => write!(f, "I/O error during EEPROM transfer: {}", x),
+            Self::Timeout { address } => write!(f, "Timed out waiting for EEPROM block {:#06x}", address),
+            Self::VerifyFailed { address } => write!(f, "EEPROM block {:#06x} did not read back as written", address),
+            Self::InvalidImage(x) => write!(f, "Invalid EEPROM image: {}", x),
+        }
+    }
+}
+
+impl Error for EEPROMError {}
+
+impl From<std::io::Error> for EEPROMError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+///Errors raised while reading or writing capture files.
+#[derive(Debug)]
+pub enum CaptureError {
+    Io(std::io::Error),
+    InvalidCapture(String),
+}
+
+impl Display for CaptureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(x) => write!(f, "I/O error in capture file: {}", x),
+            Self::InvalidCapture(x) => write!(f, "Invalid capture file: {}", x),
+        }
+    }
+}
+
+impl Error for CaptureError {}
+
+impl From<std::io::Error> for CaptureError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+///Errors raised while exporting a take.
+#[derive(Debug)]
+pub enum ExportError {
+    Io(std::io::Error),
+    InvalidTake(String),
+}
+
+impl Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(x) => write!(f, "I/O error during export: {}", x),
+            Self::InvalidTake(x) => write!(f, "Cannot export take: {}", x),
+        }
+    }
+}
+
+impl Error for ExportError {}
+
+impl From<std::io::Error> for ExportError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+///Errors raised while decoding OSC packets or applying them to a simulated unit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OscError {
+    InvalidPacket(String),
+    InvalidMessage { address: String, description: String },
+}
+
+impl Display for OscError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidPacket(x) => write!(f, "Invalid OSC packet: {}", x),
+            Self::InvalidMessage { address, description } => write!(f, "Invalid OSC message to {}: {}", address, description),
+        }
+    }
+}
+
+impl Error for OscError {}
@@ -0,0 +1,537 @@
+use std::io::{Read, Write};
+
+use crate::capture::CaptureReader;
+use crate::error::{CaptureError, ExportError};
+use crate::json::JsonValue;
+use crate::lens::LensTable;
+use crate::payloads::*;
+use crate::resample::FrameRate;
+use crate::timecode::Timecode;
+use crate::transform::*;
+
+///Focus distances are clamped to this many metres, since few packages accept infinity.
+pub const MAX_FOCUS_DISTANCE: f64 = 10000.0;
+
+///One frame of a recorded take.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TakeFrame {
+    pub pose: PositionPollPayload,
+    pub timecode: Option<Timecode>,
+}
+
+///A recorded sequence of poses, one per frame at `rate`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Take {
+    pub rate: FrameRate,
+    pub frames: Vec<TakeFrame>,
+}
+
+///How exported frames are numbered: counting up from `start`, or from the timecode of each frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Numbering {
+    Index { start: u64 },
+    Timecode,
+}
+
+impl Default for Numbering {
+    fn default() -> Self {
+        Numbering::Index { start: 1 }
+    }
+}
+
+///Settings shared by every exporter. `origin` re-zeroes the take on a studio origin before it is converted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExportOptions {
+    pub name: String,
+    pub numbering: Numbering,
+    pub origin: Option<Origin>,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions { name: "freedCamera".to_string(), numbering: Numbering::default(), origin: None }
+    }
+}
+
+///A frame ready to be written, in the target coordinate system.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct ExportFrame {
+    pub number: u64,
+    pub transform: Transform,
+    pub focal_length: f64,
+    pub focus_distance: f64,
+}
+
+impl Take {
+    pub fn new(rate: FrameRate) -> Take {
+        Take { rate, frames: Vec::new() }
+    }
+
+    pub fn push(&mut self, pose: PositionPollPayload, timecode: Option<Timecode>) {
+        self.frames.push(TakeFrame { pose, timecode });
+    }
+
+    ///Collects the position messages of a capture, optionally only those from one camera. Each message becomes one
+    /// frame, so captures should be resampled to the frame rate first if the tracker did not send one per frame.
+    pub fn from_capture<R: Read>(capture: CaptureReader<R>, cameraid: Option<u8>) -> Result<Take, CaptureError> {
+        let mut take = Take::new(capture.rate());
+        for record in capture {
+            let record = record?;
+            if let Ok(message) = deserialise::<PositionPollPayload>(&record.data) {
+                if cameraid.is_none_or(|x| x == message.cameraid) {
+                    take.push(message.payload, record.timecode);
+                }
+            }
+        }
+        Ok(take)
+    }
+
+    pub fn frame_numbers(&self, numbering: Numbering) -> Result<Vec<u64>, ExportError> {
+        self.frames.iter().enumerate().map(|(index, frame)| match numbering {
+            Numbering::Index { start } => Ok(start + index as u64),
+            Numbering::Timecode => frame.timecode
+                .map(|x| x.frame_number())
+                .ok_or_else(|| ExportError::InvalidTake(format!("frame {} has no timecode", index))),
+        }).collect()
+    }
+
+    ///The frames converted for export. Frame numbers have to strictly increase, which timecode numbering may not if
+    /// the tracker sent more than one message per frame, so resample such takes to the frame rate first.
+    pub(crate) fn export_frames(&self, lens: &LensTable, convention: &Convention, options: &ExportOptions) -> Result<Vec<ExportFrame>, ExportError> {
+        let numbers = self.frame_numbers(options.numbering)?;
+        if let Some(pair) = numbers.windows(2).find(|x| x[1] <= x[0]) {
+            return Err(ExportError::InvalidTake(format!("frame {} follows frame {}, frame numbers need to increase", pair[1], pair[0])));
+        }
+
+        Ok(self.frames.iter().zip(numbers).map(|(frame, number)| {
+            let mut pose = Pose::from_payload(&frame.pose);
+            if let Some(origin) = &options.origin {
+                pose = pose.relative_to(origin);
+            }
+            ExportFrame {
+                number,
+                transform: pose.convert(convention),
+                focal_length: lens.focal_length(pose.zoom),
+                focus_distance: lens.focus_distance(pose.focus).min(MAX_FOCUS_DISTANCE),
+            }
+        }).collect())
+    }
+}
+
+///Writes a Nuke `.chan` file: one line per frame of the frame number, translation, rotation and vertical field of
+/// view. Nuke is Y up in metres, and cameras should be set to the default ZXY rotation order on import.
+pub fn write_chan<W: Write>(take: &Take, lens: &LensTable, options: &ExportOptions, writer: &mut W) -> Result<(), ExportError> {
+    for frame in take.export_frames(lens, &Convention::Y_UP, options)? {
+        let [tx, ty, tz] = frame.transform.translation;
+        let [rx, ry, rz] = euler_angles(&frame.transform.matrix(), EulerOrder::ZXY);
+        writeln!(writer, "{}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}", frame.number, tx, ty, tz, rx, ry, rz, lens.vertical_fov(frame.focal_length))?;
+    }
+    Ok(())
+}
+
+///Frame rates that Maya has a time unit for, other than the named ones, as frames per second.
+const MAYA_FPS: [u32; 31] = [
+    2, 3, 4, 5, 6, 8, 10, 12, 16, 20, 40, 75, 80, 100, 120, 125, 150, 200, 240, 250, 300, 375, 400, 500, 600, 750, 1200,
+    1500, 2000, 3000, 6000,
+];
+
+///Maya's name for a frame rate, as used by `currentUnit -t`. Maya only has a fixed list of time units.
+fn maya_time_unit(rate: FrameRate) -> Result<String, ExportError> {
+    Ok(match (rate.frames, rate.seconds) {
+        (15, 1) => "game".to_string(),
+        (24, 1) => "film".to_string(),
+        (25, 1) => "pal".to_string(),
+        (30, 1) => "ntsc".to_string(),
+        (48, 1) => "show".to_string(),
+        (50, 1) => "palf".to_string(),
+        (60, 1) => "ntscf".to_string(),
+        (x, 1) if MAYA_FPS.contains(&x) => format!("{}fps", x),
+        (24000, 1001) => "23.976fps".to_string(),
+        (30000, 1001) => "29.97fps".to_string(),
+        (48000, 1001) => "47.952fps".to_string(),
+        (60000, 1001) => "59.94fps".to_string(),
+        _ => return Err(ExportError::InvalidTake(format!("Maya has no time unit for {} fps", rate))),
+    })
+}
+
+///Writes a Maya ASCII scene holding a camera animated by one curve per channel. The scene is Y up in centimetres
+/// with the default XYZ rotation order. Focal length is keyed in millimetres and focus distance in centimetres.
+pub fn write_maya<W: Write>(take: &Take, lens: &LensTable, options: &ExportOptions, writer: &mut W) -> Result<(), ExportError> {
+    let frames = take.export_frames(lens, &Convention::MAYA, options)?;
+    let name = &options.name;
+    let shape = format!("{}Shape", name);
+
+    writeln!(writer, "//Maya ASCII 2018 scene")?;
+    writeln!(writer, "requires maya \"2018\";")?;
+    writeln!(writer, "currentUnit -l centimeter -a degree -t {};", maya_time_unit(take.rate)?)?;
+    writeln!(writer, "createNode transform -n \"{}\";", name)?;
+    writeln!(writer, "createNode camera -n \"{}\" -p \"{}\";", shape, name)?;
+    writeln!(writer, "\tsetAttr -k off \".v\";")?;
+    writeln!(writer, "\tsetAttr \".cap\" -type \"double2\" {:.6} {:.6};", lens.sensor_width / 25.4, lens.sensor_height / 25.4)?;
+
+    let channels = [
+        ("animCurveTL", name, "translateX", "tx"),
+        ("animCurveTL", name, "translateY", "ty"),
+        ("animCurveTL", name, "translateZ", "tz"),
+        ("animCurveTA", name, "rotateX", "rx"),
+        ("animCurveTA", name, "rotateY", "ry"),
+        ("animCurveTA", name, "rotateZ", "rz"),
+        ("animCurveTU", &shape, "focalLength", "fl"),
+        ("animCurveTL", &shape, "focusDistance", "fd"),
+    ];
+    let values: Vec<[f64; 8]> = frames.iter().map(|x| {
+        let [tx, ty, tz] = x.transform.translation;
+        let [rx, ry, rz] = euler_angles(&x.transform.matrix(), EulerOrder::XYZ);
+        [tx, ty, tz, rx, ry, rz, x.focal_length, x.focus_distance * 100.0]
+    }).collect();
+
+    for (channel, (curve, node, attribute, short)) in channels.iter().enumerate() {
+        writeln!(writer, "createNode {} -n \"{}_{}\";", curve, node, attribute)?;
+        write!(writer, "\tsetAttr -s {} \".ktv[0:{}]\"", frames.len(), frames.len().saturating_sub(1))?;
+        for (frame, value) in frames.iter().zip(&values) {
+            write!(writer, " {} {:.6}", frame.number, value[channel])?;
+        }
+        writeln!(writer, ";")?;
+        writeln!(writer, "connectAttr \"{}_{}.o\" \"{}.{}\";", node, attribute, node, short)?;
+    }
+
+    writeln!(writer, "// End of scene")?;
+    Ok(())
+}
+
+///Writes a Python script that recreates the take in Blender when run from its text editor or with
+/// `blender --python`. The camera is keyed with a quaternion rotation, with Z up in metres.
+pub fn write_blender<W: Write>(take: &Take, lens: &LensTable, options: &ExportOptions, writer: &mut W) -> Result<(), ExportError> {
+    let frames = take.export_frames(lens, &Convention::BLENDER, options)?;
+    let nominal = take.rate.fps().round().max(1.0);
+
+    writeln!(writer, "import bpy")?;
+    writeln!(writer)?;
+    writeln!(writer, "scene = bpy.context.scene")?;
+    writeln!(writer, "scene.render.fps = {}", nominal)?;
+    writeln!(writer, "scene.render.fps_base = {}", nominal * take.rate.seconds as f64 / take.rate.frames as f64)?;
+    if let (Some(first), Some(last)) = (frames.first(), frames.last()) {
+        writeln!(writer, "scene.frame_start = {}", first.number)?;
+        writeln!(writer, "scene.frame_end = {}", last.number)?;
+    }
+    writeln!(writer)?;
+    writeln!(writer, "data = bpy.data.cameras.new({:?})", options.name)?;
+    writeln!(writer, "data.sensor_fit = 'HORIZONTAL'")?;
+    writeln!(writer, "data.sensor_width = {}", lens.sensor_width)?;
+    writeln!(writer, "data.sensor_height = {}", lens.sensor_height)?;
+    writeln!(writer, "data.dof.use_dof = True")?;
+    writeln!(writer, "camera = bpy.data.objects.new({:?}, data)", options.name)?;
+    writeln!(writer, "camera.rotation_mode = 'QUATERNION'")?;
+    writeln!(writer, "scene.collection.objects.link(camera)")?;
+    writeln!(writer)?;
+    writeln!(writer, "#frame, location, rotation (w, x, y, z), focal length (mm), focus distance (m)")?;
+    writeln!(writer, "frames = [")?;
+    for frame in &frames {
+        let [x, y, z] = frame.transform.translation;
+        let q = frame.transform.rotation;
+        writeln!(writer, "    ({}, ({:.6}, {:.6}, {:.6}), ({:.8}, {:.8}, {:.8}, {:.8}), {:.6}, {:.6}),",
+            frame.number, x, y, z, q.w, q.x, q.y, q.z, frame.focal_length, frame.focus_distance)?;
+    }
+    writeln!(writer, "]")?;
+    writeln!(writer)?;
+    writeln!(writer, "for frame, location, rotation, lens, focus in frames:")?;
+    writeln!(writer, "    camera.location = location")?;
+    writeln!(writer, "    camera.rotation_quaternion = rotation")?;
+    writeln!(writer, "    camera.keyframe_insert('location', frame=frame)")?;
+    writeln!(writer, "    camera.keyframe_insert('rotation_quaternion', frame=frame)")?;
+    writeln!(writer, "    data.lens = lens")?;
+    writeln!(writer, "    data.keyframe_insert('lens', frame=frame)")?;
+    writeln!(writer, "    data.dof.focus_distance = focus")?;
+    writeln!(writer, "    data.dof.keyframe_insert('focus_distance', frame=frame)")?;
+    Ok(())
+}
+
+///Writes a USDA layer holding a `UsdGeomCamera` with time sampled transform, focal length and focus distance. The
+/// stage is Y up in centimetres, so the apertures and focal length are in millimetres as USD expects.
+pub fn write_usda<W: Write>(take: &Take, lens: &LensTable, options: &ExportOptions, writer: &mut W) -> Result<(), ExportError> {
+    let frames = take.export_frames(lens, &Convention::MAYA, options)?;
+
+    writeln!(writer, "#usda 1.0")?;
+    writeln!(writer, "(")?;
+    writeln!(writer, "    defaultPrim = {:?}", options.name)?;
+    writeln!(writer, "    metersPerUnit = 0.01")?;
+    writeln!(writer, "    upAxis = \"Y\"")?;
+    if let (Some(first), Some(last)) = (frames.first(), frames.last()) {
+        writeln!(writer, "    startTimeCode = {}", first.number)?;
+        writeln!(writer, "    endTimeCode = {}", last.number)?;
+    }
+    writeln!(writer, "    timeCodesPerSecond = {}", take.rate.fps())?;
+    writeln!(writer, "    framesPerSecond = {}", take.rate.fps())?;
+    writeln!(writer, ")")?;
+    writeln!(writer)?;
+    writeln!(writer, "def Camera {:?}", options.name)?;
+    writeln!(writer, "{{")?;
+    writeln!(writer, "    float horizontalAperture = {}", lens.sensor_width)?;
+    writeln!(writer, "    float verticalAperture = {}", lens.sensor_height)?;
+
+    writeln!(writer, "    float focalLength.timeSamples = {{")?;
+    for frame in &frames {
+        writeln!(writer, "        {}: {:.6},", frame.number, frame.focal_length)?;
+    }
+    writeln!(writer, "    }}")?;
+
+    writeln!(writer, "    float focusDistance.timeSamples = {{")?;
+    for frame in &frames {
+        writeln!(writer, "        {}: {:.6},", frame.number, frame.focus_distance * 100.0)?;
+    }
+    writeln!(writer, "    }}")?;
+
+    //USD multiplies row vectors, so its matrices are the transpose of ours
+    writeln!(writer, "    matrix4d xformOp:transform.timeSamples = {{")?;
+    for frame in &frames {
+        let m = frame.transform.transform();
+        let rows: Vec<String> = (0..4).map(|column| {
+            format!("({:.8}, {:.8}, {:.8}, {:.8})", m[0][column], m[1][column], m[2][column], m[3][column])
+        }).collect();
+        writeln!(writer, "        {}: ( {} ),", frame.number, rows.join(", "))?;
+    }
+    writeln!(writer, "    }}")?;
+    writeln!(writer, "    uniform token[] xformOpOrder = [\"xformOp:transform\"]")?;
+    writeln!(writer, "}}")?;
+    Ok(())
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(data: &[u8]) -> String {
+    let mut output = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for index in 0..4 {
+            if index <= chunk.len() {
+                output.push(BASE64[(bits >> (18 - 6 * index) & 0x3F) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
+///Writes a glTF 2.0 file with a single camera node, animated by translation and rotation channels. The buffer is
+/// embedded as a data URI, so the file stands alone. Times start at zero on the first frame.
+///
+/// glTF cameras have no focus distance, and can only animate their field of view through the `KHR_animation_pointer`
+/// extension, which is used but not required. Viewers without it show the field of view of the first frame. The focus
+/// distance of each frame in metres is kept in the `extras` of the animation.
+pub fn write_gltf<W: Write>(take: &Take, lens: &LensTable, options: &ExportOptions, writer: &mut W) -> Result<(), ExportError> {
+    let frames = take.export_frames(lens, &Convention::Y_UP, options)?;
+    let first = frames.first().ok_or_else(|| ExportError::InvalidTake("glTF animations need at least one frame".to_string()))?;
+
+    //frame numbers only increase, so times do too and the first frame is the earliest
+    let times: Vec<f32> = frames.iter().map(|x| ((x.number - first.number) as f64 / take.rate.fps()) as f32).collect();
+    let yfov = |frame: &ExportFrame| lens.vertical_fov(frame.focal_length).to_radians() as f32;
+
+    //times, translations, rotations as x, y, z, w and vertical fields of view, one after the other
+    let mut buffer = Vec::<u8>::new();
+    let mut views = Vec::new();
+    let mut push = |values: Vec<f32>| {
+        views.push((buffer.len(), values.len() * 4));
+        buffer.extend(values.iter().flat_map(|x| x.to_le_bytes()));
+    };
+    push(times.clone());
+    push(frames.iter().flat_map(|x| x.transform.translation.map(|x| x as f32)).collect());
+    push(frames.iter().flat_map(|x| {
+        let q = x.transform.rotation;
+        [q.x, q.y, q.z, q.w].map(|x| x as f32)
+    }).collect());
+    push(frames.iter().map(yfov).collect());
+
+    let count = frames.len();
+    let bufferviews: Vec<String> = views.iter()
+        .map(|(offset, length)| format!("{{\"buffer\":0,\"byteOffset\":{},\"byteLength\":{}}}", offset, length))
+        .collect();
+    let accessors = [
+        format!("{{\"bufferView\":0,\"componentType\":5126,\"count\":{},\"type\":\"SCALAR\",\"min\":[{}],\"max\":[{}]}}", count, times[0], times[count - 1]),
+        format!("{{\"bufferView\":1,\"componentType\":5126,\"count\":{},\"type\":\"VEC3\"}}", count),
+        format!("{{\"bufferView\":2,\"componentType\":5126,\"count\":{},\"type\":\"VEC4\"}}", count),
+        format!("{{\"bufferView\":3,\"componentType\":5126,\"count\":{},\"type\":\"SCALAR\"}}", count),
+    ];
+    let focus: Vec<String> = frames.iter().map(|x| format!("{:.6}", x.focus_distance)).collect();
+
+    writeln!(writer, "{{")?;
+    writeln!(writer, "  \"asset\": {{\"version\": \"2.0\", \"generator\": \"freed\"}},")?;
+    writeln!(writer, "  \"extensionsUsed\": [\"KHR_animation_pointer\"],")?;
+    writeln!(writer, "  \"scene\": 0,")?;
+    writeln!(writer, "  \"scenes\": [{{\"nodes\": [0]}}],")?;
+    writeln!(writer, "  \"nodes\": [{{\"name\": {}, \"camera\": 0}}],", JsonValue::from(options.name.as_str()))?;
+    writeln!(writer, "  \"cameras\": [{{\"type\": \"perspective\", \"perspective\": {{\"yfov\": {}, \"aspectRatio\": {}, \"znear\": 0.01}}}}],",
+        yfov(first), lens.sensor_width / lens.sensor_height)?;
+    writeln!(writer, "  \"animations\": [{{")?;
+    writeln!(writer, "    \"name\": {},", JsonValue::from(options.name.as_str()))?;
+    writeln!(writer, "    \"samplers\": [")?;
+    writeln!(writer, "      {{\"input\": 0, \"output\": 1, \"interpolation\": \"LINEAR\"}},")?;
+    writeln!(writer, "      {{\"input\": 0, \"output\": 2, \"interpolation\": \"LINEAR\"}},")?;
+    writeln!(writer, "      {{\"input\": 0, \"output\": 3, \"interpolation\": \"LINEAR\"}}")?;
+    writeln!(writer, "    ],")?;
+    writeln!(writer, "    \"channels\": [")?;
+    writeln!(writer, "      {{\"sampler\": 0, \"target\": {{\"node\": 0, \"path\": \"translation\"}}}},")?;
+    writeln!(writer, "      {{\"sampler\": 1, \"target\": {{\"node\": 0, \"path\": \"rotation\"}}}},")?;
+    writeln!(writer, "      {{\"sampler\": 2, \"target\": {{\"path\": \"pointer\", \"extensions\": {{\"KHR_animation_pointer\": {{\"pointer\": \"/cameras/0/perspective/yfov\"}}}}}}}}")?;
+    writeln!(writer, "    ],")?;
+    writeln!(writer, "    \"extras\": {{\"focusDistance\": [{}]}}", focus.join(", "))?;
+    writeln!(writer, "  }}],")?;
+    writeln!(writer, "  \"accessors\": [\n    {}\n  ],", accessors.join(",\n    "))?;
+    writeln!(writer, "  \"bufferViews\": [\n    {}\n  ],", bufferviews.join(",\n    "))?;
+    writeln!(writer, "  \"buffers\": [{{\"byteLength\": {}, \"uri\": \"data:application/octet-stream;base64,{}\"}}]", buffer.len(), base64(&buffer))?;
+    writeln!(writer, "}}")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::capture::*;
+    use crate::common::*;
+    use std::time::Duration;
+
+    fn take() -> Take {
+        let mut lens = PositionPollPayload::default();
+        let mut take = Take::new(FrameRate::FPS_25);
+        let start = Timecode::parse("01:00:00:00", FrameRate::FPS_25).unwrap();
+
+        for frame in 0..3 {
+            lens.set(Axis::Yaw, frame as f64 * 90.0 * UNITS_PER_DEGREE);
+            lens.set(Axis::X, frame as f64 * UNITS_PER_METRE);
+            lens.set(Axis::Zoom, frame as f64 * 500.0);
+            take.push(lens, Some(start.add_frames(frame)));
+        }
+        take
+    }
+
+    fn lens() -> LensTable {
+        LensTable::parse("sensor 36 24\nzoom 0 18\nzoom 1000 58\nfocus 0 2").unwrap()
+    }
+
+    type Exporter = fn(&Take, &LensTable, &ExportOptions, &mut Vec<u8>) -> Result<(), ExportError>;
+
+    fn export(exporter: Exporter, options: &ExportOptions) -> String {
+        let mut output = Vec::new();
+        exporter(&take(), &lens(), options, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn numbering() {
+        let take = take();
+        assert_eq!(take.frame_numbers(Numbering::default()).unwrap(), vec![1, 2, 3]);
+        assert_eq!(take.frame_numbers(Numbering::Timecode).unwrap(), vec![90000, 90001, 90002]);
+
+        let mut untimed = take.clone();
+        untimed.frames[1].timecode = None;
+        assert!(untimed.frame_numbers(Numbering::Timecode).is_err());
+    }
+
+    #[test]
+    fn repeated_frames() {
+        //two messages in the same frame, as when tracking runs faster than the frame rate
+        let mut repeated = take();
+        repeated.frames[2].timecode = repeated.frames[1].timecode;
+        let options = ExportOptions { numbering: Numbering::Timecode, ..ExportOptions::default() };
+        let exporters: [Exporter; 5] = [write_chan, write_maya, write_blender, write_usda, write_gltf];
+        for exporter in exporters {
+            assert!(exporter(&repeated, &lens(), &options, &mut Vec::new()).is_err());
+        }
+        assert!(write_usda(&repeated, &lens(), &ExportOptions::default(), &mut Vec::new()).is_ok());
+    }
+
+    #[test]
+    fn chan() {
+        let chan = export(write_chan, &ExportOptions::default());
+        let lines: Vec<Vec<f64>> = chan.lines().map(|x| x.split('\t').map(|x| x.parse().unwrap()).collect()).collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], vec![1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, lines[0][7]]);
+        assert!((lines[0][7] - 67.38).abs() < 0.01);
+        //panned right by 90 degrees is -90 about Nuke's Y axis
+        assert_eq!(lines[1][..7], [2.0, 1.0, 0.0, 0.0, 0.0, -90.0, 0.0]);
+    }
+
+    #[test]
+    fn maya() {
+        let options = ExportOptions { name: "cam".to_string(), numbering: Numbering::Timecode, origin: None };
+        let maya = export(write_maya, &options);
+        assert!(maya.contains("currentUnit -l centimeter -a degree -t pal;"));
+        assert!(maya.contains("setAttr -s 3 \".ktv[0:2]\" 90000 0.000000 90001 100.000000 90002 200.000000;"));
+        assert!(maya.contains("createNode animCurveTU -n \"camShape_focalLength\";"));
+        assert!(maya.contains("connectAttr \"camShape_focusDistance.o\" \"camShape.fd\";"));
+        assert!(maya.contains("90001 38.000000"));
+
+        assert_eq!(maya_time_unit(FrameRate::FPS_23_976).unwrap(), "23.976fps");
+        assert_eq!(maya_time_unit(FrameRate::FPS_29_97).unwrap(), "29.97fps");
+        assert_eq!(maya_time_unit(FrameRate::FPS_59_94).unwrap(), "59.94fps");
+        assert!(maya_time_unit(FrameRate::new(120000, 1001).unwrap()).is_err());
+        assert_eq!(maya_time_unit(FrameRate::new(15, 1).unwrap()).unwrap(), "game");
+        assert_eq!(maya_time_unit(FrameRate::new(120, 1).unwrap()).unwrap(), "120fps");
+        assert!(maya_time_unit(FrameRate::new(29, 1).unwrap()).is_err());
+        assert!(maya_time_unit(FrameRate::new(1000, 1).unwrap()).is_err());
+        assert!(maya_time_unit(FrameRate::new(25, 2).unwrap()).is_err());
+    }
+
+    #[test]
+    fn blender() {
+        let script = export(write_blender, &ExportOptions::default());
+        assert!(script.contains("scene.render.fps = 25"));
+        assert!(script.contains("scene.frame_end = 3"));
+        assert!(script.contains("    (1, (0.000000, 0.000000, 0.000000), (0.70710678, 0.70710678, 0.00000000, 0.00000000), 18.000000, 2.000000),"));
+    }
+
+    #[test]
+    fn from_capture() {
+        let mut writer = CaptureWriter::new(Vec::new(), FrameRate::FPS_25).unwrap();
+        for (cameraid, pose) in [(1, PositionPollPayload::default()), (2, PositionPollPayload::default())] {
+            let data = Message::new(pose, cameraid).serialise();
+            writer.write(&CaptureRecord { offset: Duration::ZERO, timecode: None, data }).unwrap();
+        }
+        let status = Message::new(SystemStatusPayload::default(), 1).serialise();
+        writer.write(&CaptureRecord { offset: Duration::ZERO, timecode: None, data: status }).unwrap();
+
+        let data = writer.into_inner();
+        assert_eq!(Take::from_capture(CaptureReader::new(data.as_slice()).unwrap(), None).unwrap().frames.len(), 2);
+        assert_eq!(Take::from_capture(CaptureReader::new(data.as_slice()).unwrap(), Some(2)).unwrap().frames.len(), 1);
+    }
+
+    #[test]
+    fn usda() {
+        let usda = export(write_usda, &ExportOptions::default());
+        assert!(usda.starts_with("#usda 1.0\n"));
+        assert!(usda.contains("    defaultPrim = \"freedCamera\""));
+        assert!(usda.contains("    endTimeCode = 3"));
+        assert!(usda.contains("    timeCodesPerSecond = 25"));
+        assert!(usda.contains("def Camera \"freedCamera\""));
+        assert!(usda.contains("        2: 38.000000,"));
+        assert!(usda.contains("        1: 200.000000,"));
+        //translation in the last row, in centimetres
+        assert!(usda.contains("(0.00000000, 0.00000000, 0.00000000, 1.00000000) ),"));
+        assert!(usda.contains("(100.00000000, 0.00000000, 0.00000000, 1.00000000) ),"));
+    }
+
+    #[test]
+    fn gltf() {
+        assert_eq!(base64(b"Man"), "TWFu");
+        assert_eq!(base64(b"Ma"), "TWE=");
+        assert_eq!(base64(b"M"), "TQ==");
+
+        let gltf = export(write_gltf, &ExportOptions::default());
+        assert!(gltf.contains("\"version\": \"2.0\""));
+        assert!(gltf.contains("\"nodes\": [{\"name\": \"freedCamera\", \"camera\": 0}]"));
+        assert!(gltf.contains("\"count\":3,\"type\":\"SCALAR\",\"min\":[0],\"max\":[0.08]"));
+        //3 frames of time, translation, rotation and field of view
+        assert!(gltf.contains(&format!("\"byteLength\": {}", 3 * (1 + 3 + 4 + 1) * 4)));
+        assert!(gltf.contains("\"focusDistance\": [2.000000, 2.000000, 2.000000]"));
+
+        let mut output = Vec::new();
+        assert!(write_gltf(&Take::new(FrameRate::FPS_25), &lens(), &ExportOptions::default(), &mut output).is_err());
+
+        //timecode running backwards, which would put frames before the start of the animation
+        let mut backwards = take();
+        backwards.frames[2].timecode = Some(Timecode::parse("00:59:59:24", FrameRate::FPS_25).unwrap());
+        let options = ExportOptions { numbering: Numbering::Timecode, ..ExportOptions::default() };
+        assert!(write_gltf(&backwards, &lens(), &options, &mut output).is_err());
+    }
+}
@@ -0,0 +1,286 @@
+use std::f64::consts::PI;
+use std::time::Instant;
+
+use crate::payloads::*;
+
+///Exponential moving average. `alpha` is the weight given to each new sample, from 0 (ignore new samples) to 1
+/// (no smoothing). Does not take the time between samples into account.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ExponentialFilter {
+    pub alpha: f64,
+    state: Option<f64>,
+}
+
+impl ExponentialFilter {
+    pub fn new(alpha: f64) -> ExponentialFilter {
+        ExponentialFilter { alpha, state: None }
+    }
+
+    pub fn filter(&mut self, value: f64) -> f64 {
+        let filtered = match self.state {
+            Some(previous) => self.alpha * value + (1.0 - self.alpha) * previous,
+            None => value,
+        };
+        self.state = Some(filtered);
+        filtered
+    }
+}
+
+///The 1€ filter (Casiez et al. 2012). A low pass filter whose cutoff rises with speed, which removes jitter while
+/// the camera is still without adding lag when it moves.
+///
+/// `min_cutoff` is the cutoff in Hz when still, `beta` how quickly the cutoff rises with speed (in raw units per
+/// second), and `derivative_cutoff` the cutoff in Hz used when estimating speed.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OneEuroFilter {
+    pub min_cutoff: f64,
+    pub beta: f64,
+    pub derivative_cutoff: f64,
+    state: Option<(f64, f64)>,
+}
+
+impl OneEuroFilter {
+    pub fn new(min_cutoff: f64, beta: f64, derivative_cutoff: f64) -> OneEuroFilter {
+        OneEuroFilter { min_cutoff, beta, derivative_cutoff, state: None }
+    }
+
+    fn smoothing(dt: f64, cutoff: f64) -> f64 {
+        let tau = 1.0 / (2.0 * PI * cutoff);
+        1.0 / (1.0 + tau / dt)
+    }
+
+    ///Filters a sample taken `dt` seconds after the previous one.
+    pub fn filter(&mut self, value: f64, dt: f64) -> f64 {
+        let (previous, previousderivative) = match self.state {
+            Some(x) if dt > 0.0 => x,
+            Some((previous, _)) => return previous,
+            None => {
+                self.state = Some((value, 0.0));
+                return value;
+            }
+        };
+
+        let derivative = (value - previous) / dt;
+        let a = Self::smoothing(dt, self.derivative_cutoff);
+        let derivative = a * derivative + (1.0 - a) * previousderivative;
+
+        let cutoff = self.min_cutoff + self.beta * derivative.abs();
+        let a = Self::smoothing(dt, cutoff);
+        let filtered = a * value + (1.0 - a) * previous;
+
+        self.state = Some((filtered, derivative));
+        filtered
+    }
+}
+
+///Kalman filter with a constant velocity model. `process_noise` is the variance of the acceleration the camera is
+/// expected to undergo, and `measurement_noise` the variance of the tracker's jitter, both in raw units.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct KalmanFilter {
+    pub process_noise: f64,
+    pub measurement_noise: f64,
+    state: Option<([f64; 2], [[f64; 2]; 2])>,
+}
+
+impl KalmanFilter {
+    pub fn new(process_noise: f64, measurement_noise: f64) -> KalmanFilter {
+        KalmanFilter { process_noise, measurement_noise, state: None }
+    }
+
+    ///Filters a sample taken `dt` seconds after the previous one.
+    pub fn filter(&mut self, value: f64, dt: f64) -> f64 {
+        let ([position, velocity], p) = match self.state {
+            Some(x) => x,
+            None => {
+                self.state = Some(([value, 0.0], [[self.measurement_noise, 0.0], [0.0, self.measurement_noise]]));
+                return value;
+            }
+        };
+
+        //predict
+        let q = self.process_noise;
+        let position = position + velocity * dt;
+        let p = [
+            [p[0][0] + dt * (p[1][0] + p[0][1]) + dt * dt * p[1][1] + q * dt.powi(3) / 3.0, p[0][1] + dt * p[1][1] + q * dt * dt / 2.0],
+            [p[1][0] + dt * p[1][1] + q * dt * dt / 2.0, p[1][1] + q * dt],
+        ];
+
+        //update
+        let innovation = value - position;
+        let s = p[0][0] + self.measurement_noise;
+        let k = [p[0][0] / s, p[1][0] / s];
+        let position = position + k[0] * innovation;
+        let velocity = velocity + k[1] * innovation;
+        let p = [
+            [(1.0 - k[0]) * p[0][0], (1.0 - k[0]) * p[0][1]],
+            [p[1][0] - k[1] * p[0][0], p[1][1] - k[1] * p[0][1]],
+        ];
+
+        self.state = Some(([position, velocity], p));
+        position
+    }
+}
+
+///The filter applied to a single axis by a `PoseFilter`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Filter {
+    None,
+    Exponential(ExponentialFilter),
+    OneEuro(OneEuroFilter),
+    Kalman(KalmanFilter),
+}
+
+impl Filter {
+    pub fn filter(&mut self, value: f64, dt: f64) -> f64 {
+        match self {
+            Filter::None => value,
+            Filter::Exponential(x) => x.filter(value),
+            Filter::OneEuro(x) => x.filter(value, dt),
+            Filter::Kalman(x) => x.filter(value, dt),
+        }
+    }
+}
+
+///Smooths a stream of `PositionPollPayload`s, with a separately configured `Filter` for each `Axis`.
+///
+/// Rotations are unwrapped before filtering, so a camera panning through +/-180 degrees is filtered as one continuous
+/// movement rather than a jump of a full turn, and wrapped back into +/-180 degrees afterwards.
+///
+/// ```rust,ignore
+/// let mut filter = PoseFilter::new(Filter::OneEuro(OneEuroFilter::new(1.0, 0.0001, 1.0)));
+/// filter.set(Axis::Zoom, Filter::None);
+///
+/// let smoothed: PositionPollPayload = filter.filter(&message.payload, Instant::now());
+/// ```
+#[derive(Clone, Debug)]
+pub struct PoseFilter {
+    filters: [Filter; 8],
+    unwrapped: [f64; 8],
+    last: Option<Instant>,
+}
+
+impl PoseFilter {
+    ///A filter applying copies of `filter` to every axis.
+    pub fn new(filter: Filter) -> PoseFilter {
+        PoseFilter { filters: [filter; 8], unwrapped: [0.0; 8], last: None }
+    }
+
+    ///Replaces the filter for one axis, discarding its state.
+    pub fn set(&mut self, axis: Axis, filter: Filter) {
+        self.filters[axis.index()] = filter;
+    }
+
+    pub fn get(&self, axis: Axis) -> &Filter {
+        &self.filters[axis.index()]
+    }
+
+    ///Filters a pose received at `received`.
+    pub fn filter(&mut self, payload: &PositionPollPayload, received: Instant) -> PositionPollPayload {
+        let dt = match self.last {
+            Some(last) => received.saturating_duration_since(last).as_secs_f64(),
+            None => 0.0,
+        };
+        let first = self.last.is_none();
+        self.last = Some(received);
+
+        let mut output = *payload;
+        for axis in Axis::ALL {
+            let index = axis.index();
+            let mut value = payload.get(axis);
+
+            if axis.is_rotation() {
+                if !first {
+                    value = self.unwrapped[index] + angle_difference(self.unwrapped[index], value);
+                }
+                self.unwrapped[index] = value;
+            }
+
+            let filtered = self.filters[index].filter(value, dt);
+            output.set(axis, if axis.is_rotation() { wrap_angle(filtered) } else { filtered });
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::*;
+    use std::time::Duration;
+
+    const DEGREE: f64 = 32768.0;
+
+    fn yaw(degrees: f64) -> PositionPollPayload {
+        let mut payload = PositionPollPayload::default();
+        payload.set(Axis::Yaw, degrees * DEGREE);
+        payload
+    }
+
+    #[test]
+    fn exponential_converges() {
+        let mut filter = ExponentialFilter::new(0.5);
+        assert_eq!(filter.filter(10.0), 10.0);
+        assert_eq!(filter.filter(20.0), 15.0);
+        assert_eq!(filter.filter(20.0), 17.5);
+    }
+
+    #[test]
+    fn yaw_wraparound() {
+        let mut filter = PoseFilter::new(Filter::Exponential(ExponentialFilter::new(0.5)));
+        let start = Instant::now();
+
+        filter.filter(&yaw(178.0), start);
+        let output = filter.filter(&yaw(-178.0), start + Duration::from_millis(20));
+
+        //halfway between 178 and 182 degrees is 180, not 0
+        assert_eq!(output.get(Axis::Yaw).abs(), 180.0 * DEGREE);
+
+        let output = filter.filter(&yaw(-176.0), start + Duration::from_millis(40));
+        assert_eq!(output.get(Axis::Yaw), -178.0 * DEGREE);
+    }
+
+    #[test]
+    fn one_euro_removes_jitter() {
+        let mut filter = OneEuroFilter::new(1.0, 0.0, 1.0);
+        let mut worst: f64 = 0.0;
+
+        for sample in 0..100 {
+            let jitter = if sample % 2 == 0 { 50.0 } else { -50.0 };
+            let output = filter.filter(1000.0 + jitter, 1.0 / 50.0);
+            if sample > 50 {
+                worst = worst.max((output - 1000.0).abs());
+            }
+        }
+        assert!(worst < 10.0, "{}", worst);
+    }
+
+    #[test]
+    fn kalman_tracks_ramp() {
+        let mut filter = KalmanFilter::new(1.0, 100.0);
+        let mut output = 0.0;
+
+        for sample in 0..200 {
+            output = filter.filter(sample as f64 * 10.0, 0.02);
+        }
+        assert!((output - 1990.0).abs() < 5.0, "{}", output);
+    }
+
+    #[test]
+    fn per_axis_configuration() {
+        let mut filter = PoseFilter::new(Filter::Exponential(ExponentialFilter::new(0.5)));
+        filter.set(Axis::Zoom, Filter::None);
+        let start = Instant::now();
+
+        let mut payload = PositionPollPayload::default();
+        filter.filter(&payload, start);
+
+        payload.set(Axis::Zoom, 1000.0);
+        payload.set(Axis::X, 1000.0);
+        let output = filter.filter(&payload, start + Duration::from_millis(20));
+
+        assert_eq!(output.get(Axis::Zoom), 1000.0);
+        assert_eq!(output.get(Axis::X), 500.0);
+        assert_eq!(Message::new(output, 1).serialise().len(), 29);
+    }
+}
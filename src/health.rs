@@ -0,0 +1,371 @@
+use std::fmt::{self, Display};
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::common::*;
+use crate::payloads::*;
+use crate::transport::*;
+
+///Alerts raised by a `HealthMonitor` as a unit's reported state changes.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum HealthEvent {
+    ///The unit lit its fault or DSP alert LED, or reported a status other than `SYSTEM_NORMAL`.
+    FaultRaised(SystemStatus),
+    FaultCleared,
+    ///The unit's reported status changed, e.g. from `SYSTEM_NORMAL` to `VBLANK_FAIL`.
+    StatusChanged { from: SystemStatus, to: SystemStatus },
+    TooFewTargets { used: u8 },
+    TargetsRecovered { used: u8 },
+    ///RMS error in pixels rose above `HealthThresholds::max_rms_error`.
+    RMSErrorHigh { pixels: f64 },
+    RMSErrorRecovered { pixels: f64 },
+    DSPReset,
+    ///The DSP reported an error other than a reset or too few targets.
+    DSPError(DSPError),
+    ///The unit stopped answering status polls.
+    Unresponsive,
+    Responsive,
+}
+
+impl Display for HealthEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FaultRaised(status) => write!(f, "Fault raised: {}", status),
+            Self::FaultCleared => write!(f, "Fault cleared"),
+            Self::StatusChanged { from, to } => write!(f, "Status changed from {} to {}", from, to),
+            Self::TooFewTargets { used } => write!(f, "Too few targets: {} in use", used),
+            Self::TargetsRecovered { used } => write!(f, "Targets recovered: {} in use", used),
+            Self::RMSErrorHigh { pixels } => write!(f, "RMS error high: {:.3} pixels", pixels),
+            Self::RMSErrorRecovered { pixels } => write!(f, "RMS error recovered: {:.3} pixels", pixels),
+            Self::DSPReset => write!(f, "DSP reset"),
+            Self::DSPError(error) => write!(f, "DSP error: {}", error),
+            Self::Unresponsive => write!(f, "Unit is not responding to status polls"),
+            Self::Responsive => write!(f, "Unit is responding to status polls again"),
+        }
+    }
+}
+
+///Limits used by `HealthMonitor` to decide when to raise and clear events.
+///
+/// Conditions are only raised after `raise_after` consecutive bad samples, and only cleared after `clear_after`
+/// consecutive good samples, so a value bouncing around a limit does not flood operators with alerts. The RMS
+/// error additionally has to fall below `clear_rms_error` before it is considered good again.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HealthThresholds {
+    pub min_targets: u8,
+    pub max_rms_error: f64,
+    pub clear_rms_error: f64,
+    pub raise_after: usize,
+    pub clear_after: usize,
+}
+
+impl Default for HealthThresholds {
+    fn default() -> Self {
+        Self {
+            min_targets: 4,
+            max_rms_error: 1.0,
+            clear_rms_error: 0.75,
+            raise_after: 3,
+            clear_after: 5,
+        }
+    }
+}
+
+///Debounced on/off state of a single monitored condition.
+#[derive(Copy, Clone, Debug, Default)]
+struct Condition {
+    active: bool,
+    count: usize,
+}
+
+impl Condition {
+    ///Feeds one sample into the condition, returning `Some(true)` when it is raised and `Some(false)` when it clears.
+    fn update(&mut self, bad: bool, good: bool, thresholds: &HealthThresholds) -> Option<bool> {
+        let (towards, limit) = match self.active {
+            false => (bad, thresholds.raise_after),
+            true => (good, thresholds.clear_after),
+        };
+
+        match towards {
+            true => self.count += 1,
+            false => self.count = 0,
+        }
+
+        if self.count >= limit.max(1) {
+            self.active = !self.active;
+            self.count = 0;
+            return Some(self.active);
+        }
+        None
+    }
+}
+
+///Tracks the health of a single free-d unit over time from its `SystemStatusPayload`s, turning raw status bytes into
+/// `HealthEvent`s. Status payloads can be fed in with `update` as they arrive, or requested from the unit with `poll`.
+///
+/// ```rust,ignore
+/// let mut monitor = HealthMonitor::new(HealthThresholds::default());
+/// loop {
+///     for event in monitor.poll(&mut transport, cameraid, Duration::from_millis(500))? {
+///         println!("{}", event);
+///     }
+///     std::thread::sleep(Duration::from_secs(1));
+/// }
+/// ```
+pub struct HealthMonitor {
+    pub thresholds: HealthThresholds,
+    last: Option<SystemStatusPayload>,
+    last_seen: Option<Instant>,
+    fault: Condition,
+    targets: Condition,
+    rmserror: Condition,
+    responsive: Condition,
+}
+
+impl HealthMonitor {
+    pub fn new(thresholds: HealthThresholds) -> HealthMonitor {
+        HealthMonitor {
+            thresholds,
+            last: None,
+            last_seen: None,
+            fault: Condition::default(),
+            targets: Condition::default(),
+            rmserror: Condition::default(),
+            responsive: Condition::default(),
+        }
+    }
+
+    ///The most recent status payload received from the unit.
+    pub fn last_status(&self) -> Option<SystemStatusPayload> {
+        self.last
+    }
+
+    pub fn last_seen(&self) -> Option<Instant> {
+        self.last_seen
+    }
+
+    pub fn is_faulted(&self) -> bool {
+        self.fault.active
+    }
+
+    ///Consumes a status payload reported by the unit, returning any events it caused.
+    pub fn update(&mut self, status: &SystemStatusPayload) -> Vec<HealthEvent> {
+        let mut events = Vec::<HealthEvent>::new();
+        let thresholds = self.thresholds;
+
+        if self.responsive.update(false, true, &thresholds) == Some(false) {
+            events.push(HealthEvent::Responsive);
+        }
+
+        if let Some(last) = self.last {
+            if last.systemstatus != status.systemstatus {
+                events.push(HealthEvent::StatusChanged { from: last.systemstatus, to: status.systemstatus });
+            }
+        }
+
+        let faulted = status.systemstatus != SystemStatus::SYSTEM_NORMAL
+            || status.ledindication.intersects(LEDFlags::FAULT | LEDFlags::DSP_ALERT);
+        match self.fault.update(faulted, !faulted, &thresholds) {
+            Some(true) => events.push(HealthEvent::FaultRaised(status.systemstatus)),
+            Some(false) => events.push(HealthEvent::FaultCleared),
+            None => {}
+        }
+
+        let used = status.numtargetsused;
+        let toofew = used < thresholds.min_targets || status.dspstatus == Err(DSPError::TOO_FEW_TARGETS);
+        match self.targets.update(toofew, !toofew, &thresholds) {
+            Some(true) => events.push(HealthEvent::TooFewTargets { used }),
+            Some(false) => events.push(HealthEvent::TargetsRecovered { used }),
+            None => {}
+        }
+
+        let pixels = status.rmserror.pixels();
+        match self.rmserror.update(pixels > thresholds.max_rms_error, pixels < thresholds.clear_rms_error, &thresholds) {
+            Some(true) => events.push(HealthEvent::RMSErrorHigh { pixels }),
+            Some(false) => events.push(HealthEvent::RMSErrorRecovered { pixels }),
+            None => {}
+        }
+
+        //DSP errors are edge triggered, as each one is a discrete occurrence rather than an ongoing state
+        let previous = self.last.map(|x| x.dspstatus);
+        match status.dspstatus {
+            Err(error) if previous != Some(Err(error)) => match error {
+                DSPError::DSP_RESET => events.push(HealthEvent::DSPReset),
+                DSPError::TOO_FEW_TARGETS => {}
+                error => events.push(HealthEvent::DSPError(error)),
+            },
+            _ => {}
+        }
+
+        self.last = Some(*status);
+        self.last_seen = Some(Instant::now());
+        events
+    }
+
+    ///Records that a status poll went unanswered, returning `Unresponsive` once enough polls in a row have been missed.
+    pub fn missed(&mut self) -> Vec<HealthEvent> {
+        let thresholds = self.thresholds;
+        match self.responsive.update(true, false, &thresholds) {
+            Some(true) => vec![HealthEvent::Unresponsive],
+            _ => vec![],
+        }
+    }
+
+    ///Requests a `SystemStatusPayload` from the unit and waits up to `timeout` for the reply, returning any events
+    /// it caused. Other messages received while waiting are ignored.
+    pub fn poll<T: Transport>(&mut self, transport: &mut T, cameraid: u8, timeout: Duration) -> io::Result<Vec<HealthEvent>> {
+        let request = Message::new(PollPayload { command: Commands::SYSTEM_STATUS }, cameraid);
+        transport.send(&request.serialise())?;
+
+        let deadline = Instant::now() + timeout;
+        let mut buffer = [0_u8; MAX_MESSAGE_LENGTH];
+
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(self.missed());
+            }
+
+            let length = match transport.receive(&mut buffer, Some(deadline - now)) {
+                Ok(x) => x,
+                Err(x) if is_timeout(&x) => return Ok(self.missed()),
+                Err(x) => return Err(x),
+            };
+
+            if let Ok(reply) = deserialise::<SystemStatusPayload>(&buffer[..length]) {
+                if reply.command() == Commands::SYSTEM_STATUS {
+                    return Ok(self.update(&reply.payload));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::VecDeque;
+    use ux::u24;
+
+    ///Records what is sent and plays back queued replies, timing out once they run out.
+    #[derive(Default)]
+    struct ScriptedUnit {
+        sent: Vec<Vec<u8>>,
+        replies: VecDeque<Vec<u8>>,
+    }
+
+    impl Transport for ScriptedUnit {
+        fn send(&mut self, data: &[u8]) -> io::Result<()> {
+            self.sent.push(data.to_vec());
+            Ok(())
+        }
+
+        fn receive(&mut self, buffer: &mut [u8], _timeout: Option<Duration>) -> io::Result<usize> {
+            match self.replies.pop_front() {
+                Some(x) => {
+                    buffer[..x.len()].copy_from_slice(&x);
+                    Ok(x.len())
+                }
+                None => Err(io::Error::from(io::ErrorKind::TimedOut)),
+            }
+        }
+    }
+
+    fn healthy() -> SystemStatusPayload {
+        SystemStatusPayload {
+            ledindication: LEDFlags::VIDEO_PRESENT | LEDFlags::VIDEO_OK,
+            numtargetsseen: 10,
+            numtargetsidentified: 8,
+            numtargetsused: 8,
+            dspstatus: Ok(3),
+            ..SystemStatusPayload::default()
+        }
+    }
+
+    #[test]
+    fn fault_raised_and_cleared_with_hysteresis() {
+        let thresholds = HealthThresholds { raise_after: 2, clear_after: 2, ..HealthThresholds::default() };
+        let mut monitor = HealthMonitor::new(thresholds);
+        assert!(monitor.update(&healthy()).is_empty());
+
+        let mut faulty = healthy();
+        faulty.ledindication |= LEDFlags::FAULT;
+
+        assert!(monitor.update(&faulty).is_empty());
+        assert_eq!(monitor.update(&faulty), vec![HealthEvent::FaultRaised(SystemStatus::SYSTEM_NORMAL)]);
+        assert!(monitor.is_faulted());
+
+        //a single good sample in between does not clear the fault
+        assert!(monitor.update(&healthy()).is_empty());
+        assert!(monitor.update(&faulty).is_empty());
+        assert!(monitor.update(&healthy()).is_empty());
+        assert_eq!(monitor.update(&healthy()), vec![HealthEvent::FaultCleared]);
+    }
+
+    #[test]
+    fn rms_error_band() {
+        let thresholds = HealthThresholds { raise_after: 1, clear_after: 1, max_rms_error: 1.0, clear_rms_error: 0.5, ..HealthThresholds::default() };
+        let mut monitor = HealthMonitor::new(thresholds);
+
+        let mut status = healthy();
+        status.rmserror = Pixel32768th::from_pixels(1.5).unwrap();
+        assert_eq!(monitor.update(&status), vec![HealthEvent::RMSErrorHigh { pixels: 1.5 }]);
+
+        //below the raise limit, but not below the clear limit
+        status.rmserror = Pixel32768th::from_pixels(0.75).unwrap();
+        assert!(monitor.update(&status).is_empty());
+
+        status.rmserror = Pixel32768th(u24::new(0));
+        assert_eq!(monitor.update(&status), vec![HealthEvent::RMSErrorRecovered { pixels: 0.0 }]);
+    }
+
+    #[test]
+    fn too_few_targets_and_dsp_reset() {
+        let thresholds = HealthThresholds { raise_after: 1, clear_after: 1, ..HealthThresholds::default() };
+        let mut monitor = HealthMonitor::new(thresholds);
+
+        let mut status = healthy();
+        status.numtargetsused = 2;
+        assert_eq!(monitor.update(&status), vec![HealthEvent::TooFewTargets { used: 2 }]);
+
+        status.dspstatus = Err(DSPError::DSP_RESET);
+        assert_eq!(monitor.update(&status), vec![HealthEvent::DSPReset]);
+        //the reset is only reported once
+        assert!(monitor.update(&status).is_empty());
+
+        assert_eq!(monitor.update(&healthy()), vec![HealthEvent::TargetsRecovered { used: 8 }]);
+    }
+
+    #[test]
+    fn unresponsive_after_missed_polls() {
+        let thresholds = HealthThresholds { raise_after: 2, clear_after: 1, ..HealthThresholds::default() };
+        let mut monitor = HealthMonitor::new(thresholds);
+
+        assert!(monitor.missed().is_empty());
+        assert_eq!(monitor.missed(), vec![HealthEvent::Unresponsive]);
+        assert_eq!(monitor.update(&healthy()), vec![HealthEvent::Responsive]);
+    }
+
+    #[test]
+    fn poll() {
+        let thresholds = HealthThresholds { raise_after: 1, clear_after: 1, ..HealthThresholds::default() };
+        let mut monitor = HealthMonitor::new(thresholds);
+        let mut unit = ScriptedUnit::default();
+
+        let mut faulty = healthy();
+        faulty.ledindication |= LEDFlags::FAULT;
+        //position data arriving before the reply is ignored
+        unit.replies.push_back(Message::new(PositionPollPayload::default(), 1).serialise());
+        unit.replies.push_back(Message::new(faulty, 1).serialise());
+
+        let events = monitor.poll(&mut unit, 1, Duration::from_secs(1)).unwrap();
+        assert_eq!(events, vec![HealthEvent::FaultRaised(SystemStatus::SYSTEM_NORMAL)]);
+        assert_eq!(monitor.last_status(), Some(faulty));
+        assert_eq!(unit.sent, vec![Message::new(PollPayload { command: Commands::SYSTEM_STATUS }, 1).serialise()]);
+        assert_eq!(unit.sent[0][..3], [0xD0, 1, 0xD2]);
+
+        //no reply counts as a missed poll
+        assert_eq!(monitor.poll(&mut unit, 1, Duration::from_secs(1)).unwrap(), vec![HealthEvent::Unresponsive]);
+        assert_eq!(unit.sent.len(), 2);
+    }
+}
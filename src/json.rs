@@ -0,0 +1,390 @@
+use std::fmt::{self, Display, Write};
+
+///A JSON value. Object members keep the order they were parsed or inserted in, so documents round trip unchanged.
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    pub fn object() -> JsonValue {
+        JsonValue::Object(Vec::new())
+    }
+
+    pub fn parse(text: &str) -> Result<JsonValue, String> {
+        let mut parser = Parser { text: text.as_bytes(), position: 0, depth: 0 };
+        let value = parser.value()?;
+        parser.whitespace();
+        if parser.position != text.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(value)
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            JsonValue::Number(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsonValue::Bool(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<JsonValue>> {
+        match self {
+            JsonValue::Array(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    ///A member of an object, or `None` if this is not an object or has no such member.
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(members) => members.iter().find(|(x, _)| x == key).map(|(_, x)| x),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut JsonValue> {
+        match self {
+            JsonValue::Object(members) => members.iter_mut().find(|(x, _)| x == key).map(|(_, x)| x),
+            _ => None,
+        }
+    }
+
+    ///Follows a path of object members.
+    pub fn pointer(&self, path: &[&str]) -> Option<&JsonValue> {
+        path.iter().try_fold(self, |value, key| value.get(key))
+    }
+
+    ///Sets a member, replacing any existing one in place. Values that are not objects are replaced by one first.
+    pub fn insert(&mut self, key: &str, value: JsonValue) {
+        if !matches!(self, JsonValue::Object(_)) {
+            *self = JsonValue::object();
+        }
+        if let JsonValue::Object(members) = self {
+            match members.iter_mut().find(|(x, _)| x == key) {
+                Some(member) => member.1 = value,
+                None => members.push((key.to_string(), value)),
+            }
+        }
+    }
+
+    ///Sets the value at a path of object members, creating objects along the way.
+    pub fn insert_path(&mut self, path: &[&str], value: JsonValue) {
+        match path {
+            [] => *self = value,
+            [key] => self.insert(key, value),
+            [key, rest @ ..] => {
+                if !matches!(self.get(key), Some(JsonValue::Object(_))) {
+                    self.insert(key, JsonValue::object());
+                }
+                self.get_mut(key).expect("Inserted above").insert_path(rest, value);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<JsonValue> {
+        match self {
+            JsonValue::Object(members) => {
+                let index = members.iter().position(|(x, _)| x == key)?;
+                Some(members.remove(index).1)
+            }
+            _ => None,
+        }
+    }
+
+    ///Removes the value at a path of object members, then any objects on the path left empty.
+    pub fn remove_path(&mut self, path: &[&str]) -> Option<JsonValue> {
+        match path {
+            [] => None,
+            [key] => self.remove(key),
+            [key, rest @ ..] => {
+                let child = self.get_mut(key)?;
+                let removed = child.remove_path(rest);
+                if matches!(child, JsonValue::Object(x) if x.is_empty()) {
+                    self.remove(key);
+                }
+                removed
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, text: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in text.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+///Writes compact JSON. Numbers that are not finite are written as `null`, since JSON cannot represent them.
+impl Display for JsonValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonValue::Null => f.write_str("null"),
+            JsonValue::Bool(x) => write!(f, "{}", x),
+            JsonValue::Number(x) if x.is_finite() => write!(f, "{}", x),
+            JsonValue::Number(_) => f.write_str("null"),
+            JsonValue::String(x) => write_string(f, x),
+            JsonValue::Array(values) => {
+                f.write_char('[')?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                f.write_char(']')
+            }
+            JsonValue::Object(members) => {
+                f.write_char('{')?;
+                for (index, (key, value)) in members.iter().enumerate() {
+                    if index > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+impl From<f64> for JsonValue {
+    fn from(value: f64) -> Self {
+        JsonValue::Number(value)
+    }
+}
+
+impl From<&str> for JsonValue {
+    fn from(value: &str) -> Self {
+        JsonValue::String(value.to_string())
+    }
+}
+
+impl From<bool> for JsonValue {
+    fn from(value: bool) -> Self {
+        JsonValue::Bool(value)
+    }
+}
+
+///Deepest nesting of arrays and objects the parser accepts, so that hostile input can't overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+    text: &'a [u8],
+    position: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, description: &str) -> String {
+        format!("{} at byte {}", description, self.position)
+    }
+
+    fn whitespace(&mut self) {
+        while matches!(self.text.get(self.position), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str) -> Result<(), String> {
+        if !self.text[self.position..].starts_with(literal.as_bytes()) {
+            return Err(self.error(&format!("expected {}", literal)));
+        }
+        self.position += literal.len();
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<JsonValue, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+        self.depth += 1;
+        let value = self.element();
+        self.depth -= 1;
+        value
+    }
+
+    fn element(&mut self) -> Result<JsonValue, String> {
+        self.whitespace();
+        match self.text.get(self.position) {
+            Some(b'n') => self.expect("null").map(|_| JsonValue::Null),
+            Some(b't') => self.expect("true").map(|_| JsonValue::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| JsonValue::Bool(false)),
+            Some(b'"') => self.string().map(JsonValue::String),
+            Some(b'[') => {
+                self.position += 1;
+                let mut values = Vec::new();
+                self.whitespace();
+                if self.text.get(self.position) == Some(&b']') {
+                    self.position += 1;
+                    return Ok(JsonValue::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    self.whitespace();
+                    match self.text.get(self.position) {
+                        Some(b',') => self.position += 1,
+                        Some(b']') => {
+                            self.position += 1;
+                            return Ok(JsonValue::Array(values));
+                        }
+                        _ => return Err(self.error("expected , or ]")),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.position += 1;
+                let mut members = Vec::new();
+                self.whitespace();
+                if self.text.get(self.position) == Some(&b'}') {
+                    self.position += 1;
+                    return Ok(JsonValue::Object(members));
+                }
+                loop {
+                    self.whitespace();
+                    if self.text.get(self.position) != Some(&b'"') {
+                        return Err(self.error("expected a member name"));
+                    }
+                    let key = self.string()?;
+                    self.whitespace();
+                    self.expect(":")?;
+                    members.push((key, self.value()?));
+                    self.whitespace();
+                    match self.text.get(self.position) {
+                        Some(b',') => self.position += 1,
+                        Some(b'}') => {
+                            self.position += 1;
+                            return Ok(JsonValue::Object(members));
+                        }
+                        _ => return Err(self.error("expected , or }")),
+                    }
+                }
+            }
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn number(&mut self) -> Result<JsonValue, String> {
+        let start = self.position;
+        while matches!(self.text.get(self.position), Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) {
+            self.position += 1;
+        }
+        let text = std::str::from_utf8(&self.text[start..self.position]).expect("ASCII digits");
+        text.parse().map(JsonValue::Number).map_err(|_| format!("{} is not a number at byte {}", text, start))
+    }
+
+    fn hex(&mut self) -> Result<u32, String> {
+        let digits = self.text.get(self.position..self.position + 4).ok_or_else(|| self.error("truncated escape"))?;
+        let value = std::str::from_utf8(digits).ok().and_then(|x| u32::from_str_radix(x, 16).ok());
+        self.position += 4;
+        value.ok_or_else(|| self.error("invalid escape"))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.position += 1;
+        let mut output = Vec::new();
+        loop {
+            match self.text.get(self.position) {
+                None => return Err(self.error("unterminated string")),
+                Some(b'"') => {
+                    self.position += 1;
+                    return String::from_utf8(output).map_err(|_| self.error("string is not UTF-8"));
+                }
+                Some(b'\\') => {
+                    self.position += 2;
+                    let c = match self.text.get(self.position - 1) {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            let mut code = self.hex()?;
+                            //characters outside the basic plane are escaped as a surrogate pair
+                            if (0xD800..0xDC00).contains(&code) {
+                                self.expect("\\u")?;
+                                let low = self.hex()?;
+                                code = 0x10000 + ((code - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+                            }
+                            char::from_u32(code).ok_or_else(|| self.error("invalid escape"))?
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    output.extend(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                Some(x) => {
+                    output.push(*x);
+                    self.position += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let text = r#"{"name":"a \"b\"\n","values":[1,-2.5,1e3,true,null],"nested":{"x":{}},"empty":[]}"#;
+        let value = JsonValue::parse(text).unwrap();
+        assert_eq!(value.pointer(&["values"]).unwrap().as_array().unwrap()[2], JsonValue::Number(1000.0));
+        assert_eq!(value.get("name").unwrap().as_str(), Some("a \"b\"\n"));
+        assert_eq!(JsonValue::parse(&value.to_string()).unwrap(), value);
+        assert_eq!(JsonValue::parse(r#" "\u00e9\ud83d\ude00" "#).unwrap(), JsonValue::from("é😀"));
+        assert_eq!(JsonValue::String("\u{1}".to_string()).to_string(), "\"\\u0001\"");
+
+        for invalid in ["", "{", "[1,]", "{\"a\" 1}", "tru", "\"abc", "1 2", &"[".repeat(200000)] {
+            assert!(JsonValue::parse(invalid).is_err(), "{}", invalid);
+        }
+        assert!(JsonValue::parse(&format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH))).is_ok());
+    }
+
+    #[test]
+    fn paths() {
+        let mut value = JsonValue::object();
+        value.insert_path(&["lens", "encoders", "zoom"], JsonValue::from(0.5));
+        value.insert_path(&["lens", "focalLength"], JsonValue::from(35.0));
+        assert_eq!(value.to_string(), r#"{"lens":{"encoders":{"zoom":0.5},"focalLength":35}}"#);
+
+        assert_eq!(value.remove_path(&["lens", "encoders", "zoom"]), Some(JsonValue::from(0.5)));
+        assert_eq!(value.to_string(), r#"{"lens":{"focalLength":35}}"#);
+        assert_eq!(value.remove_path(&["lens", "iris"]), None);
+    }
+}
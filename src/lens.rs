@@ -0,0 +1,136 @@
+use std::path::Path;
+
+///Maps raw zoom and focus encoder values to focal length and focus distance, by linear interpolation between
+/// calibrated points. Values outside the calibrated range are clamped to its ends.
+///
+/// Tables are written one point per line, along with the sensor size in millimetres:
+///
+/// ```text
+/// sensor 23.76 13.365
+/// zoom 0 18.0        # encoder value, focal length in mm
+/// zoom 65535 55.0
+/// focus 0 0.45       # encoder value, focus distance in metres
+/// focus 65535 inf
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct LensTable {
+    pub sensor_width: f64,
+    pub sensor_height: f64,
+    zoom: Vec<(u32, f64)>,
+    focus: Vec<(u32, f64)>,
+}
+
+impl Default for LensTable {
+    ///A 35mm lens focused at 3m, on a 36x24mm sensor.
+    fn default() -> Self {
+        Self::fixed(35.0, 3.0)
+    }
+}
+
+fn interpolate(points: &[(u32, f64)], encoder: u32) -> f64 {
+    let after = points.iter().position(|(x, _)| *x >= encoder);
+    match after {
+        None => points.last().map_or(0.0, |x| x.1),
+        Some(0) => points[0].1,
+        Some(index) => {
+            let (x0, y0) = points[index - 1];
+            let (x1, y1) = points[index];
+            let fraction = (encoder - x0) as f64 / (x1 - x0) as f64;
+            //towards infinity, interpolate the reciprocal so the distance runs out smoothly
+            if y1.is_infinite() || y0.is_infinite() {
+                return 1.0 / (1.0 / y0 + (1.0 / y1 - 1.0 / y0) * fraction);
+            }
+            y0 + (y1 - y0) * fraction
+        }
+    }
+}
+
+impl LensTable {
+    ///A prime lens with a fixed focal length in millimetres and focus distance in metres, on a 36x24mm sensor.
+    pub fn fixed(focal_length: f64, focus_distance: f64) -> LensTable {
+        LensTable { sensor_width: 36.0, sensor_height: 24.0, zoom: vec![(0, focal_length)], focus: vec![(0, focus_distance)] }
+    }
+
+    ///Adds a calibrated zoom point, replacing any at the same encoder value.
+    pub fn add_zoom(&mut self, encoder: u32, focal_length: f64) {
+        self.zoom.retain(|(x, _)| *x != encoder);
+        self.zoom.push((encoder, focal_length));
+        self.zoom.sort_by_key(|(x, _)| *x);
+    }
+
+    ///Adds a calibrated focus point, replacing any at the same encoder value.
+    pub fn add_focus(&mut self, encoder: u32, focus_distance: f64) {
+        self.focus.retain(|(x, _)| *x != encoder);
+        self.focus.push((encoder, focus_distance));
+        self.focus.sort_by_key(|(x, _)| *x);
+    }
+
+    ///The focal length in millimetres at a zoom encoder value.
+    pub fn focal_length(&self, zoom: u32) -> f64 {
+        interpolate(&self.zoom, zoom)
+    }
+
+    ///The focus distance in metres at a focus encoder value.
+    pub fn focus_distance(&self, focus: u32) -> f64 {
+        interpolate(&self.focus, focus)
+    }
+
+    ///The horizontal field of view in degrees at a focal length.
+    pub fn horizontal_fov(&self, focal_length: f64) -> f64 {
+        (2.0 * (self.sensor_width / (2.0 * focal_length)).atan()).to_degrees()
+    }
+
+    ///The vertical field of view in degrees at a focal length.
+    pub fn vertical_fov(&self, focal_length: f64) -> f64 {
+        (2.0 * (self.sensor_height / (2.0 * focal_length)).atan()).to_degrees()
+    }
+
+    pub fn parse(text: &str) -> Result<LensTable, String> {
+        let mut table = LensTable { zoom: Vec::new(), focus: Vec::new(), ..LensTable::default() };
+
+        for (number, line) in text.lines().enumerate() {
+            let fields: Vec<&str> = line.split('#').next().unwrap_or_default().split_whitespace().collect();
+            let error = || format!("line {}: expected sensor WIDTH HEIGHT, zoom ENCODER MM or focus ENCODER METRES", number + 1);
+            let number = |x: &str| x.parse::<f64>().map_err(|_| error());
+            let encoder = |x: &str| x.parse::<u32>().map_err(|_| error());
+
+            match fields[..] {
+                [] => {}
+                ["sensor", width, height] => (table.sensor_width, table.sensor_height) = (number(width)?, number(height)?),
+                ["zoom", x, y] => table.add_zoom(encoder(x)?, number(y)?),
+                ["focus", x, y] => table.add_focus(encoder(x)?, number(y)?),
+                _ => return Err(error()),
+            }
+        }
+
+        if table.zoom.is_empty() || table.focus.is_empty() {
+            return Err("lens tables need at least one zoom and one focus point".to_string());
+        }
+        Ok(table)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<LensTable, String> {
+        let text = std::fs::read_to_string(&path).map_err(|x| format!("{}: {}", path.as_ref().display(), x))?;
+        LensTable::parse(&text)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn interpolation() {
+        let table = LensTable::parse("sensor 36 24\nzoom 0 18\nzoom 1000 58 # long end\nfocus 0 0.5\nfocus 100 inf").unwrap();
+        assert_eq!(table.focal_length(0), 18.0);
+        assert_eq!(table.focal_length(250), 28.0);
+        assert_eq!(table.focal_length(5000), 58.0);
+        assert_eq!(table.focus_distance(0), 0.5);
+        assert_eq!(table.focus_distance(50), 1.0);
+        assert_eq!(table.focus_distance(100), f64::INFINITY);
+        assert!((table.horizontal_fov(18.0) - 90.0).abs() < 0.1);
+
+        assert!(LensTable::parse("zoom 0 18").is_err());
+        assert!(LensTable::parse("zoom 0").is_err());
+    }
+}
@@ -1,9 +1,29 @@
 #[allow(dead_code)]
 pub mod payloads;
 pub mod error;
+pub mod transport;
+pub mod eeprom;
+pub mod health;
+pub mod registry;
+pub mod router;
+pub mod filter;
+pub mod predict;
+pub mod delay;
+pub mod resample;
+pub mod timecode;
+pub mod capture;
+pub mod dialect;
+pub mod transform;
+pub mod lens;
+pub mod export;
+pub mod osc;
+pub mod json;
+pub mod opentrackio;
+#[cfg(feature = "tokio")]
+pub mod asynctransport;
 
 pub mod common {
-    use std::{fmt::{self, Display}, alloc::System};
+    use std::fmt::{self, Display};
 
 
     use bitflags::bitflags;
@@ -13,6 +33,11 @@ pub mod common {
         ///Generate an arbitrary array of `u8`s.
         fn serialise(self) -> Vec<u8>;
 
+        ///The command a message carrying this payload is sent with. Defaults to `COMMAND`, but may be overridden
+        /// by types such as `Payloads` where the command depends on the value.
+        fn command(&self) -> Commands {
+            Self::COMMAND
+        }
     }
     //move this into the serialise trait -
     pub trait Deserialise {
@@ -27,8 +52,96 @@ pub mod common {
     }
 
     ///NewType wrapper for RMS error - each unit is 1/32768th of a pixel.
-    #[derive(Copy, Clone)]
+    #[derive(Copy, Clone, Default, Debug, PartialEq, Eq, PartialOrd, Ord)]
     pub struct Pixel32768th(pub ux::u24);
+
+    impl Pixel32768th {
+        pub const PER_PIXEL: f64 = 32768.0;
+        ///Largest value the protocol defines, with an integer part in bits 22 to 15 and bit 23 clear. Just under
+        /// 256 pixels.
+        pub const MAX_UNITS: u32 = 0x7FFFFF;
+
+        ///Converts a value in pixels to the nearest representable 1/32768th pixel. Fails if the value is negative
+        /// or has an integer part that doesn't fit in bits 22 to 15, i.e. 256 pixels or more.
+        pub fn from_pixels(pixels: f64) -> Result<Self, String> {
+            let units = (pixels * Self::PER_PIXEL).round();
+            if !(0.0..=Self::MAX_UNITS as f64).contains(&units) {
+                return Err(format!("{} pixels cannot be represented as an RMS error", pixels));
+            }
+            Ok(Self(ux::u24::new(units as u32)))
+        }
+
+        pub fn pixels(self) -> f64 {
+            u32::from(self.0) as f64 / Self::PER_PIXEL
+        }
+    }
+
+    impl Display for Pixel32768th {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.pixels())
+        }
+    }
+
+    ///Firmware version in Binary Coded Decimal, where there is an implied decimal point between the two digits.
+    /// e.g. `0x12 = 1.2`. Bytes with a nibble above 9 are not valid BCD and are rejected.
+    #[derive(Copy, Clone, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct FirmwareVersion(u8);
+
+    impl FirmwareVersion {
+        pub fn new(major: u8, minor: u8) -> Result<Self, String> {
+            if major > 9 || minor > 9 {
+                return Err(format!("{}.{} is not a valid BCD firmware version", major, minor));
+            }
+            Ok(Self(major << 4 | minor))
+        }
+
+        pub fn major(self) -> u8 {
+            self.0 >> 4
+        }
+
+        pub fn minor(self) -> u8 {
+            self.0 & 0x0F
+        }
+
+        ///The raw BCD byte, as sent on the wire.
+        pub fn bcd(self) -> u8 {
+            self.0
+        }
+    }
+
+    impl Display for FirmwareVersion {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}.{}", self.major(), self.minor())
+        }
+    }
+
+    impl TryFrom<u8> for FirmwareVersion {
+        type Error = String;
+        fn try_from(value: u8) -> Result<Self, Self::Error> {
+            match Self::new(value >> 4, value & 0x0F) {
+                Ok(x) => Ok(x),
+                Err(_) => Err(format!("Misformed firmware version {:#04x}, not valid BCD", value))
+            }
+        }
+    }
+
+    impl From<FirmwareVersion> for u8 {
+        fn from(value: FirmwareVersion) -> Self {
+            value.0
+        }
+    }
+
+    impl std::str::FromStr for FirmwareVersion {
+        type Err = String;
+        ///Parses a version written as `major.minor`, e.g. `"1.2"`.
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let digits: Option<(u8, u8)> = s.split_once('.').and_then(|(major, minor)| Some((major.parse().ok()?, minor.parse().ok()?)));
+            match digits {
+                Some((major, minor)) => Self::new(major, minor),
+                None => Err(format!("{} is not a firmware version, expected major.minor", s))
+            }
+        }
+    }
     ///Newtype wrapper for 1 unit in camera control structs. Each unit is 1/64th of a pixel.
     #[derive(Copy, Clone)]
     #[cfg_attr(test, derive(PartialEq, Debug))]
@@ -40,7 +153,7 @@ pub mod common {
 
     impl FromBytes<3> for ux::u24 {
         fn from_be_bytes(arr: [u8; 3]) -> Self {
-            ux::u24::new(((arr[2] as u32) | (arr[1] as u32) << 8 | (arr[0] as u32) << 8*2).into())
+            ux::u24::new((arr[2] as u32) | (arr[1] as u32) << 8 | (arr[0] as u32) << 16)
         }
     }
 
@@ -48,8 +161,8 @@ pub mod common {
         fn from_be_bytes(arr: [u8; 3]) -> Self {
             match (arr[0] & 0b10000000) != 0 {
                 //need to sign extend
-                true => ux::i24::new((0xff000000_u32 | (arr[2] as u32) | (arr[1] as u32) << 8 | (arr[0] as u32) << 8 * 2) as i32),
-                false => ux::i24::new(((arr[2] as u32) | (arr[1] as u32) << 8 | (arr[0] as u32) << 8 * 2).try_into().expect("Valid"))
+                true => ux::i24::new((0xff000000_u32 | (arr[2] as u32) | (arr[1] as u32) << 8 | (arr[0] as u32) << 16) as i32),
+                false => ux::i24::new(((arr[2] as u32) | (arr[1] as u32) << 8 | (arr[0] as u32) << 16).try_into().expect("Valid"))
             }
 
         }
@@ -71,6 +184,7 @@ pub mod common {
         STREAM_MODE_START = 0x01,
         FREEZE_MODE_STOP = 0x02,
         FREEZE_MODE_START = 0x03,
+        POLL = 0xD0,
         POSITION_POLL = 0xD1,
         SYSTEM_STATUS = 0xD2,
         SYSTEM_PARAMS = 0xD3,
@@ -92,6 +206,7 @@ pub mod common {
                 x if x == Self::STREAM_MODE_STOP as u8 => Ok(Self::STREAM_MODE_STOP),
                 x if x == Self::FREEZE_MODE_STOP as u8 => Ok(Self::FREEZE_MODE_STOP),
                 x if x == Self::FREEZE_MODE_START as u8 => Ok(Self::FREEZE_MODE_START),
+                x if x == Self::POLL as u8 => Ok(Self::POLL),
                 x if x == Self::POSITION_POLL as u8 => Ok(Self::POSITION_POLL),
                 x if x == Self::SYSTEM_STATUS as u8 => Ok(Self::SYSTEM_STATUS),
                 x if x == Self::SYSTEM_PARAMS as u8 => Ok(Self::SYSTEM_PARAMS),
@@ -108,6 +223,31 @@ pub mod common {
         }
     }
 
+    impl Commands {
+        ///Length of the payload carried by a message with this command, excluding the command, camera id and
+        /// checksum bytes. The stream and freeze mode commands are only sent inside a poll payload, so have no length.
+        pub fn payload_length(self) -> Option<usize> {
+            match self {
+                Self::POLL => Some(1),
+                Self::POSITION_POLL => Some(26),
+                Self::SYSTEM_STATUS => Some(13),
+                Self::SYSTEM_PARAMS => Some(10),
+                Self::FIRST_TARGET | Self::NEXT_TARGET => Some(15),
+                Self::FIRST_IMAGE | Self::NEXT_IMAGE => Some(15),
+                Self::EEPROM_DATA => Some(18),
+                Self::REQUEST_EEPROM => Some(2),
+                Self::CAMERA_CALIBRATION => Some(27),
+                Self::DIAGNOSTIC_MODE => Some(1),
+                Self::STREAM_MODE_STOP | Self::STREAM_MODE_START | Self::FREEZE_MODE_STOP | Self::FREEZE_MODE_START => None,
+            }
+        }
+
+        ///Length of a complete message with this command, including the command, camera id and checksum bytes.
+        pub fn message_length(self) -> Option<usize> {
+            self.payload_length().map(|x| x + 3)
+        }
+    }
+
     impl Display for Commands{
         fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", match self {
@@ -115,6 +255,7 @@ pub mod common {
             Self::STREAM_MODE_STOP => "Stream Mode Stop",
             Self::FREEZE_MODE_START => "Freeze Mode Start",
             Self::FREEZE_MODE_STOP => "Freeze Mode Stop",
+            Self::POLL => "Poll",
             Self::POSITION_POLL => "Position Poll",
             Self::SYSTEM_STATUS => "System Status",
             Self::SYSTEM_PARAMS => "System Params",
@@ -131,11 +272,35 @@ pub mod common {
     }
     }
 
+    impl std::str::FromStr for Commands {
+        type Err = String;
+        ///Parses a command byte given in hex, with or without a `0x` prefix, e.g. `D1` or `0xD1`.
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let byte = u8::from_str_radix(s.trim_start_matches("0x"), 16).map_err(|_| format!("{} is not a hex command", s))?;
+            Commands::try_from(byte).map_err(|_| format!("{} is not a free-d command", s))
+        }
+    }
+
+    ///Parses a camera id given in decimal, or in hex with a `0x` prefix.
+    pub fn parse_cameraid(text: &str) -> Result<u8, String> {
+        let parsed = match text.strip_prefix("0x") {
+            Some(hex) => u8::from_str_radix(hex, 16),
+            None => text.parse::<u8>(),
+        };
+        parsed.map_err(|_| format!("{} is not a camera id", text))
+    }
+
+    ///Formats bytes as space separated hex, e.g. `D1 01 FF`.
+    pub fn hex(data: &[u8]) -> String {
+        data.iter().map(|x| format!("{:02X}", x)).collect::<Vec<String>>().join(" ")
+    }
+
     #[allow(non_camel_case_types)]
-    #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    #[derive(Copy, Clone, Default, Debug, PartialEq, Eq, PartialOrd, Ord)]
     ///Set of possible status codes the freed unit may report as part of 
     /// a 'system status' payload.
     pub enum SystemStatus {
+        #[default]
         SYSTEM_NORMAL = 0,
         PROCESSOR_RESET = 1,
         SERIAL_ERROR = 2,
@@ -175,11 +340,6 @@ pub mod common {
             })
         }
     }
-    impl Default for SystemStatus {
-        fn default() -> Self {
-            Self::SYSTEM_NORMAL
-        }
-    }
     impl TryFrom<u8> for SystemStatus {
         type Error = String;
         fn try_from(value: u8) -> Result<Self, Self::Error> {
@@ -237,18 +397,25 @@ pub mod common {
 
     bitflags! {
         #[derive(Copy, Clone, Default, Debug, PartialEq, Eq, PartialOrd, Ord)]
+        ///Raw bits of the switch settings byte. Bits 0 to 3 hold the value of the S5 hex rotary switch,
+        /// bits 4 and 5 the position of the three way S2 switch (neither set is centre), bit 6 is S4 and bit 7 is S3.
+        /// See `SwitchSettings` for a typed view of the same byte.
         pub struct SwitchSettingFlags: u8 {
             const S5_HEX_00   = 0b00000001;
             const S5_HEX_01   = 0b00000010;
             const S5_HEX_02   = 0b00000100;
             const S5_HEX_03   = 0b00001000;
-            const S2_LEFT     = 0b00000000; //why does the protocol pattern change here? should I invert?
-            const S2_RIGHT    = 0b00000000;
-            const S4_CLOSED   = 0b00000000;
+            const S2_LEFT     = 0b00010000;
+            const S2_RIGHT    = 0b00100000;
+            const S4_CLOSED   = 0b01000000;
             const IS_S3_RIGHT = 0b10000000;
         }
     }
 
+    impl SwitchSettingFlags {
+        pub const S5_HEX_MASK: u8 = 0b00001111;
+    }
+
     impl Display for SwitchSettingFlags {
         fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             write!(f, "{:08b}", self.bits())
@@ -268,24 +435,131 @@ pub mod common {
             
         
         }
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    ///Position of the three way S2 switch on the free-d unit.
+    pub enum SwitchPosition {
+        Left,
+        Centre,
+        Right,
+    }
+
+    impl Display for SwitchPosition {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", match self {
+                Self::Left => "Left",
+                Self::Centre => "Centre",
+                Self::Right => "Right"
+            })
+        }
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    ///Position of the two way S3 switch on the free-d unit, which has no centre.
+    pub enum SwitchSide {
+        Left,
+        Right,
+    }
+
+    impl Display for SwitchSide {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", match self {
+                Self::Left => "Left",
+                Self::Right => "Right"
+            })
+        }
+    }
+
+    ///Typed settings of the switches on the free-d unit, as reported in the switch settings byte of a
+    /// `SystemStatusPayload`.
+    ///
+    /// `s2` - three way switch, `Left`, `Centre` or `Right`.
+    ///
+    /// `s3` - two way switch, `Left` or `Right`.
+    ///
+    /// `s4closed` - whether the S4 contact is closed.
+    ///
+    /// `s5` - value of the S5 hex rotary switch, `0x0` to `0xF`. Only the low four bits are sent.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct SwitchSettings {
+        pub s2: SwitchPosition,
+        pub s3: SwitchSide,
+        pub s4closed: bool,
+        pub s5: u8,
+    }
+
+    impl Default for SwitchSettings {
+        fn default() -> Self {
+            Self {
+                s2: SwitchPosition::Centre,
+                s3: SwitchSide::Left,
+                s4closed: false,
+                s5: 0,
+            }
+        }
+    }
+
+    impl Display for SwitchSettings {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "S2 {}, S3 {}, S4 {}, S5 {:X}", self.s2, self.s3, if self.s4closed {"Closed"} else {"Open"}, self.s5)
+        }
+    }
+
+    impl TryFrom<SwitchSettingFlags> for SwitchSettings {
+        type Error = String;
+        fn try_from(value: SwitchSettingFlags) -> Result<Self, Self::Error> {
+            let s2 = match (value.contains(SwitchSettingFlags::S2_LEFT), value.contains(SwitchSettingFlags::S2_RIGHT)) {
+                (false, false) => SwitchPosition::Centre,
+                (true, false) => SwitchPosition::Left,
+                (false, true) => SwitchPosition::Right,
+                (true, true) => return Err("Misformed SwitchSetting, S2 cannot be both left and right".to_string())
+            };
+
+            Ok(Self {
+                s2,
+                s3: if value.contains(SwitchSettingFlags::IS_S3_RIGHT) {SwitchSide::Right} else {SwitchSide::Left},
+                s4closed: value.contains(SwitchSettingFlags::S4_CLOSED),
+                s5: value.bits() & SwitchSettingFlags::S5_HEX_MASK,
+            })
+        }
+    }
+
+    impl TryFrom<u8> for SwitchSettings {
+        type Error = String;
+        fn try_from(value: u8) -> Result<Self, Self::Error> {
+            SwitchSettingFlags::from_bits_retain(value).try_into()
+        }
+    }
+
+    impl From<SwitchSettings> for SwitchSettingFlags {
+        fn from(value: SwitchSettings) -> Self {
+            let mut flags = SwitchSettingFlags::from_bits_retain(value.s5 & SwitchSettingFlags::S5_HEX_MASK);
+            flags.set(SwitchSettingFlags::S2_LEFT, value.s2 == SwitchPosition::Left);
+            flags.set(SwitchSettingFlags::S2_RIGHT, value.s2 == SwitchPosition::Right);
+            flags.set(SwitchSettingFlags::S4_CLOSED, value.s4closed);
+            flags.set(SwitchSettingFlags::IS_S3_RIGHT, value.s3 == SwitchSide::Right);
+            flags
+        }
+    }
+
+    impl From<SwitchSettings> for u8 {
+        fn from(value: SwitchSettings) -> Self {
+            SwitchSettingFlags::from(value).bits()
+        }
+    }
     
 
 
     #[allow(non_camel_case_types)]
-    #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    #[derive(Copy, Clone, Default, Debug, PartialEq, Eq, PartialOrd, Ord)]
     pub enum DSPError {
+        #[default]
         TOO_FEW_TARGETS = -1,
         ITERATION_CONVERGE_FAIL = -2,
         DSP_RESET = -3,
         INTERNAL_ERROR = -4,
     }
 
-    impl Default for DSPError {
-        fn default() -> Self {
-            Self::TOO_FEW_TARGETS
-        }
-    }
-
     impl Display for DSPError {
         fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             write!(f, "{}", match self {
@@ -317,8 +591,9 @@ pub mod common {
     }
 
     #[allow(non_camel_case_types)]
-    #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    #[derive(Copy, Clone, Default, Debug, PartialEq, Eq, PartialOrd, Ord)]
     pub enum DiagnosticModes {
+        #[default]
         NORMAL_OPERATION = 0x00,
         VIDEO_DATA_0x55 = 0x40,
         VIDEO_DATA_0xAA = 0x80,
@@ -336,12 +611,6 @@ pub mod common {
         }
     }
 
-    impl Default for DiagnosticModes {
-        fn default() -> Self {
-            Self::NORMAL_OPERATION
-        }
-    }
-
     impl TryFrom<u8> for DiagnosticModes {
         type Error = String;
         fn try_from(value: u8) -> Result<Self, Self::Error> {
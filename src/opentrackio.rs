@@ -0,0 +1,335 @@
+use std::fmt::{self, Display};
+
+use ux::u24;
+
+use crate::json::JsonValue;
+use crate::payloads::*;
+use crate::resample::FrameRate;
+use crate::timecode::Timecode;
+
+const TRANSLATION: [(&str, Axis); 3] = [("x", Axis::X), ("y", Axis::Y), ("z", Axis::Z)];
+const ROTATION: [(&str, Axis); 3] = [("pan", Axis::Yaw), ("tilt", Axis::Pitch), ("roll", Axis::Roll)];
+const ENCODERS: [(&str, Axis); 2] = [("zoom", Axis::Zoom), ("focus", Axis::Focus)];
+
+///Something in a sample that did not make it into the free-d message.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Loss {
+    ///The value has no free-d field. It is kept in `TrackingSample::metadata`.
+    Unrepresentable { path: String },
+    ///The value was outside the range free-d can hold, and was clamped to `to`.
+    Clamped { path: String, from: f64, to: f64 },
+    ///The sample has no value for a free-d field, which was left at zero.
+    Missing { path: String },
+    ///The value could not be read, and was dropped.
+    Invalid { path: String, description: String },
+}
+
+impl Display for Loss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unrepresentable { path } => write!(f, "{}: no free-d equivalent, kept as metadata", path),
+            Self::Clamped { path, from, to } => write!(f, "{}: {} is out of range, clamped to {}", path, from, to),
+            Self::Missing { path } => write!(f, "{}: missing, sent as zero", path),
+            Self::Invalid { path, description } => write!(f, "{}: {}, dropped", path, description),
+        }
+    }
+}
+
+///A free-d pose along with the parts of an OpenTrackIO-style sample it cannot hold, for converting between the two.
+///
+/// Samples are mapped as follows. Everything else in a sample is kept in `metadata` and written back out unchanged,
+/// so a sample converted to free-d and back only loses precision.
+///
+/// - `sourceNumber` is the camera id.
+/// - `transforms[0].translation` is the position in metres, with X right, Y forward and Z up as in free-d.
+/// - `transforms[0].rotation` is pan, tilt and roll in degrees. OpenTrackIO rotates anticlockwise about Z, so pan has
+///   the opposite sign to free-d.
+/// - `lens.rawEncoders` carries the zoom and focus values, and `lens.encoders` the same as a fraction of the 24 bit
+///   range. Raw values are used where a sample has both.
+/// - `timing.timecode` is the timecode, which free-d itself does not carry.
+/// - `custom.freed.userdefined` is the vendor specific `userdefined` field, written only when it is not zero.
+#[derive(Clone, Debug)]
+pub struct TrackingSample {
+    pub message: Message<PositionPollPayload>,
+    pub timecode: Option<Timecode>,
+    ///The members of the sample with no free-d equivalent, as a JSON object.
+    pub metadata: JsonValue,
+}
+
+fn degrees(axis: Axis) -> f64 {
+    if axis == Axis::Yaw { -32768.0 } else { 32768.0 }
+}
+
+fn encoder_range() -> f64 {
+    u32::from(u24::MAX) as f64
+}
+
+///Lists the path of every value left in `value`, for reporting what was carried over as metadata.
+fn leaves(value: &JsonValue, path: String, output: &mut Vec<Loss>) {
+    let join = |key: &str| if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) };
+    match value {
+        JsonValue::Object(members) if !members.is_empty() => {
+            for (key, value) in members {
+                leaves(value, join(key), output);
+            }
+        }
+        JsonValue::Array(values) if !values.is_empty() => {
+            for (index, value) in values.iter().enumerate() {
+                leaves(value, format!("{}[{}]", path, index), output);
+            }
+        }
+        _ => output.push(Loss::Unrepresentable { path }),
+    }
+}
+
+impl TrackingSample {
+    pub fn new(message: Message<PositionPollPayload>) -> TrackingSample {
+        TrackingSample { message, timecode: None, metadata: JsonValue::object() }
+    }
+
+    ///Writes the sample as JSON, merging the free-d fields into `metadata`. Nothing in the free-d message is lost.
+    pub fn to_json(&self) -> JsonValue {
+        let payload = &self.message.payload;
+        let mut sample = JsonValue::object();
+        sample.insert_path(&["protocol", "name"], JsonValue::from("OpenTrackIO"));
+        sample.insert_path(&["protocol", "version"], JsonValue::Array(vec![1.0.into(), 0.0.into(), 0.0.into()]));
+        if let JsonValue::Object(members) = &self.metadata {
+            for (key, value) in members {
+                sample.insert(key, value.clone());
+            }
+        }
+
+        sample.insert("sourceNumber", JsonValue::from(self.message.cameraid as f64));
+
+        if let Some(timecode) = self.timecode {
+            let mut value = JsonValue::object();
+            value.insert("hours", JsonValue::from(timecode.hours as f64));
+            value.insert("minutes", JsonValue::from(timecode.minutes as f64));
+            value.insert("seconds", JsonValue::from(timecode.seconds as f64));
+            value.insert("frames", JsonValue::from(timecode.frames as f64));
+            value.insert_path(&["format", "frameRate", "num"], JsonValue::from(timecode.rate.frames as f64));
+            value.insert_path(&["format", "frameRate", "denom"], JsonValue::from(timecode.rate.seconds as f64));
+            value.insert_path(&["format", "dropFrame"], JsonValue::from(timecode.dropframe));
+            sample.insert_path(&["timing", "timecode"], value);
+        }
+
+        if !matches!(sample.get("transforms"), Some(JsonValue::Array(x)) if !x.is_empty()) {
+            sample.insert("transforms", JsonValue::Array(vec![JsonValue::object()]));
+        }
+        if let Some(JsonValue::Array(transforms)) = sample.get_mut("transforms") {
+            for (name, axis) in TRANSLATION {
+                transforms[0].insert_path(&["translation", name], JsonValue::from(payload.get(axis) / 64000.0));
+            }
+            for (name, axis) in ROTATION {
+                transforms[0].insert_path(&["rotation", name], JsonValue::from(payload.get(axis) / degrees(axis)));
+            }
+        }
+
+        for (name, axis) in ENCODERS {
+            sample.insert_path(&["lens", "encoders", name], JsonValue::from(payload.get(axis) / encoder_range()));
+            sample.insert_path(&["lens", "rawEncoders", name], JsonValue::from(payload.get(axis)));
+        }
+
+        if payload.userdefined != 0 {
+            sample.insert_path(&["custom", "freed", "userdefined"], JsonValue::from(payload.userdefined as f64));
+        }
+        sample
+    }
+
+    ///Reads a sample, reporting everything that could not be carried in the free-d message. Fails only if the sample
+    /// is not a JSON object.
+    pub fn from_json(sample: &JsonValue) -> Result<(TrackingSample, Vec<Loss>), String> {
+        if !matches!(sample, JsonValue::Object(_)) {
+            return Err("a tracking sample must be a JSON object".to_string());
+        }
+        let mut metadata = sample.clone();
+        let mut losses = Vec::new();
+        let mut payload = PositionPollPayload::default();
+
+        let cameraid = take_number(&mut metadata, &["sourceNumber"], "sourceNumber", &mut losses).unwrap_or_default();
+        let clamped = cameraid.round().clamp(0.0, u8::MAX as f64);
+        if clamped != cameraid.round() {
+            losses.push(Loss::Clamped { path: "sourceNumber".to_string(), from: cameraid, to: clamped });
+        }
+
+        let mut placeholder = JsonValue::object();
+        let transform = match metadata.get_mut("transforms") {
+            Some(JsonValue::Array(x)) if !x.is_empty() => &mut x[0],
+            //anything else is carried over as it is, and reported with the rest of the metadata
+            _ => &mut placeholder,
+        };
+        for (name, axis) in TRANSLATION {
+            let path = format!("transforms[0].translation.{}", name);
+            if let Some(metres) = take_number(transform, &["translation", name], &path, &mut losses) {
+                set_clamped(&mut payload, axis, metres * 64000.0, 64000.0, &path, &mut losses);
+            }
+        }
+        for (name, axis) in ROTATION {
+            let path = format!("transforms[0].rotation.{}", name);
+            if let Some(angle) = take_number(transform, &["rotation", name], &path, &mut losses) {
+                payload.set(axis, wrap_angle(angle * degrees(axis)));
+            }
+        }
+        if matches!(metadata.get("transforms"), Some(JsonValue::Array(x)) if x.len() == 1 && x[0] == JsonValue::object()) {
+            metadata.remove("transforms");
+        }
+
+        for (name, axis) in ENCODERS {
+            let normalised = metadata.remove_path(&["lens", "encoders", name]);
+            let path = format!("lens.encoders.{}", name);
+            if metadata.pointer(&["lens", "rawEncoders", name]).is_some() {
+                let path = format!("lens.rawEncoders.{}", name);
+                if let Some(raw) = take_number(&mut metadata, &["lens", "rawEncoders", name], &path, &mut losses) {
+                    set_clamped(&mut payload, axis, raw, 1.0, &path, &mut losses);
+                }
+            } else if let Some(fraction) = number(normalised, &path, &mut losses) {
+                set_clamped(&mut payload, axis, fraction * encoder_range(), encoder_range(), &path, &mut losses);
+            }
+        }
+
+        if let Some(value) = metadata.remove_path(&["custom", "freed", "userdefined"]) {
+            match value.as_f64() {
+                Some(x) if (0.0..=u16::MAX as f64).contains(&x) => payload.userdefined = x as u16,
+                _ => losses.push(Loss::Invalid { path: "custom.freed.userdefined".to_string(), description: format!("{} is not a u16", value) }),
+            }
+        }
+
+        let timecode = match metadata.remove_path(&["timing", "timecode"]).map(|x| parse_timecode(&x)) {
+            None => None,
+            Some(Ok(x)) => Some(x),
+            Some(Err(description)) => {
+                losses.push(Loss::Invalid { path: "timing.timecode".to_string(), description });
+                None
+            }
+        };
+
+        //the protocol header describes the JSON rather than the tracking, so it isn't worth reporting
+        if let JsonValue::Object(members) = &metadata {
+            for (key, value) in members.iter().filter(|(x, _)| x != "protocol") {
+                leaves(value, key.clone(), &mut losses);
+            }
+        }
+
+        let message = Message::new(payload, clamped as u8);
+        Ok((TrackingSample { message, timecode, metadata }, losses))
+    }
+}
+
+///Reads a number, reporting it if it is missing or not a number.
+fn number(value: Option<JsonValue>, path: &str, losses: &mut Vec<Loss>) -> Option<f64> {
+    match value {
+        Some(JsonValue::Number(x)) => Some(x),
+        None => {
+            losses.push(Loss::Missing { path: path.to_string() });
+            None
+        }
+        Some(x) => {
+            losses.push(Loss::Invalid { path: path.to_string(), description: format!("{} is not a number", x) });
+            None
+        }
+    }
+}
+
+///Takes a number out of the metadata.
+fn take_number(metadata: &mut JsonValue, members: &[&str], path: &str, losses: &mut Vec<Loss>) -> Option<f64> {
+    number(metadata.remove_path(members), path, losses)
+}
+
+///Sets a raw value, reporting it if it had to be clamped. `scale` is raw units per unit of the sample.
+fn set_clamped(payload: &mut PositionPollPayload, axis: Axis, raw: f64, scale: f64, path: &str, losses: &mut Vec<Loss>) {
+    payload.set(axis, raw);
+    let stored = payload.get(axis);
+    if stored != raw.round() {
+        losses.push(Loss::Clamped { path: path.to_string(), from: raw / scale, to: stored / scale });
+    }
+}
+
+fn parse_timecode(value: &JsonValue) -> Result<Timecode, String> {
+    let field = |path: &[&str]| {
+        value.pointer(path).and_then(|x| x.as_f64()).ok_or_else(|| format!("{} is missing", path.join(".")))
+    };
+    let byte = |path: &[&str]| field(path).map(|x| x.clamp(0.0, u8::MAX as f64) as u8);
+
+    let rate = FrameRate::new(field(&["format", "frameRate", "num"])? as u32, field(&["format", "frameRate", "denom"])? as u32)?;
+    let dropframe = value.pointer(&["format", "dropFrame"]).and_then(|x| x.as_bool()).unwrap_or(false);
+    Timecode::new(byte(&["hours"])?, byte(&["minutes"])?, byte(&["seconds"])?, byte(&["frames"])?, rate, dropframe)
+}
+
+impl From<Message<PositionPollPayload>> for TrackingSample {
+    fn from(value: Message<PositionPollPayload>) -> Self {
+        TrackingSample::new(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ux::i24;
+
+    const SAMPLE: &str = r#"{
+        "protocol": {"name": "OpenTrackIO", "version": [1, 0, 0]},
+        "sourceId": "urn:uuid:2b1e9a64-4b5d-4f1e-9a1c-0d6b1d2f3e4a",
+        "sourceNumber": 3,
+        "timing": {
+            "mode": "internal",
+            "timecode": {"hours": 1, "minutes": 2, "seconds": 3, "frames": 4, "format": {"frameRate": {"num": 30000, "denom": 1001}, "dropFrame": true}}
+        },
+        "tracker": {"status": "Optical Good"},
+        "transforms": [{"translation": {"x": 1.5, "y": -0.25, "z": 200}, "rotation": {"pan": 90, "tilt": -10, "roll": 0.5}, "id": "Camera"}],
+        "lens": {"encoders": {"zoom": 0.5, "focus": 1.0, "iris": 0.3}, "rawEncoders": {"focus": 4000}}
+    }"#;
+
+    #[test]
+    fn from_json() {
+        let (sample, losses) = TrackingSample::from_json(&JsonValue::parse(SAMPLE).unwrap()).unwrap();
+        let payload = sample.message.payload;
+        assert_eq!(sample.message.cameraid, 3);
+        assert_eq!(payload.pos_x, i24::new(96000));
+        assert_eq!(payload.pos_y, i24::new(-16000));
+        assert_eq!(payload.yaw, i24::new(-90 * 32768));
+        assert_eq!(payload.roll, i24::new(16384));
+        assert_eq!(payload.zoom, u24::new(8388608));
+        assert_eq!(payload.focus, u24::new(4000));
+        assert_eq!(sample.timecode.unwrap().to_string(), "01:02:03;04");
+
+        assert!(losses.contains(&Loss::Clamped { path: "transforms[0].translation.z".to_string(), from: 200.0, to: 8388607.0 / 64000.0 }));
+        for path in ["sourceId", "timing.mode", "tracker.status", "transforms[0].id", "lens.encoders.iris"] {
+            assert!(losses.contains(&Loss::Unrepresentable { path: path.to_string() }), "{}", path);
+        }
+        assert_eq!(losses.len(), 6);
+        assert_eq!(sample.metadata.pointer(&["lens", "encoders", "iris"]), Some(&JsonValue::from(0.3)));
+        assert_eq!(sample.metadata.pointer(&["lens", "rawEncoders"]), None);
+
+        let (_, losses) = TrackingSample::from_json(&JsonValue::parse(r#"{"transforms": 5, "lens": {"encoders": {"zoom": "wide"}}}"#).unwrap()).unwrap();
+        assert!(losses.contains(&Loss::Missing { path: "sourceNumber".to_string() }));
+        assert!(losses.contains(&Loss::Missing { path: "transforms[0].rotation.pan".to_string() }));
+        assert!(losses.contains(&Loss::Missing { path: "lens.encoders.focus".to_string() }));
+        assert!(losses.contains(&Loss::Unrepresentable { path: "transforms".to_string() }));
+        assert!(losses.iter().any(|x| matches!(x, Loss::Invalid { path, .. } if path == "lens.encoders.zoom")));
+        assert!(TrackingSample::from_json(&JsonValue::Array(vec![])).is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let (sample, _) = TrackingSample::from_json(&JsonValue::parse(SAMPLE).unwrap()).unwrap();
+        let json = sample.to_json();
+        assert_eq!(json.get("sourceId"), JsonValue::parse(SAMPLE).unwrap().get("sourceId"));
+        assert_eq!(json.pointer(&["transforms"]).unwrap().as_array().unwrap()[0].get("id"), Some(&JsonValue::from("Camera")));
+        assert_eq!(json.pointer(&["lens", "rawEncoders", "focus"]), Some(&JsonValue::from(4000.0)));
+
+        let (again, losses) = TrackingSample::from_json(&json).unwrap();
+        assert_eq!(again.message.payload, sample.message.payload);
+        assert_eq!(again.timecode, sample.timecode);
+        assert_eq!(again.metadata, sample.metadata);
+        assert!(losses.iter().all(|x| matches!(x, Loss::Unrepresentable { .. })));
+
+        let mut payload = PositionPollPayload { userdefined: 77, ..PositionPollPayload::default() };
+        payload.set(Axis::Pitch, 12.5 * 32768.0);
+        let json = TrackingSample::from(Message::new(payload, 1)).to_json();
+        assert_eq!(json.get("protocol").unwrap().get("name"), Some(&JsonValue::from("OpenTrackIO")));
+        assert_eq!(json.pointer(&["custom", "freed", "userdefined"]), Some(&JsonValue::from(77.0)));
+        let (decoded, losses) = TrackingSample::from_json(&json).unwrap();
+        assert_eq!(decoded.message.payload, payload);
+        assert!(losses.is_empty(), "{:?}", losses);
+    }
+}
@@ -0,0 +1,489 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use ux::u24;
+
+use crate::common::*;
+use crate::error::OscError;
+use crate::payloads::*;
+
+///The OSC time tag meaning "immediately".
+pub const IMMEDIATELY: u64 = 1;
+
+const BUNDLE_TAG: &[u8] = b"#bundle\0";
+
+///A single OSC argument. Decoding also accepts doubles and the `T` and `F` booleans sent by some controllers.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OscArgument {
+    Int(i32),
+    Float(f32),
+    Double(f64),
+    String(String),
+    Bool(bool),
+}
+
+impl OscArgument {
+    fn tag(&self) -> char {
+        match self {
+            Self::Int(_) => 'i',
+            Self::Float(_) => 'f',
+            Self::Double(_) => 'd',
+            Self::String(_) => 's',
+            Self::Bool(true) => 'T',
+            Self::Bool(false) => 'F',
+        }
+    }
+
+    ///The argument as a number, if it is one.
+    pub fn number(&self) -> Option<f64> {
+        match self {
+            Self::Int(x) => Some(*x as f64),
+            Self::Float(x) => Some(*x as f64),
+            Self::Double(x) => Some(*x),
+            Self::Bool(x) => Some(*x as u8 as f64),
+            Self::String(_) => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub arguments: Vec<OscArgument>,
+}
+
+///An OSC 1.0 packet: a message, or a bundle of packets to be applied together at `timetag`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OscPacket {
+    Message(OscMessage),
+    Bundle { timetag: u64, packets: Vec<OscPacket> },
+}
+
+///Appends a string with its terminator, padded to a multiple of 4 bytes.
+fn write_string(output: &mut Vec<u8>, text: &str) {
+    output.extend(text.as_bytes());
+    output.extend(std::iter::repeat_n(0, 4 - text.len() % 4));
+}
+
+fn read_string<'a>(data: &mut &'a [u8]) -> Result<&'a str, OscError> {
+    let end = data.iter().position(|x| *x == 0).ok_or_else(|| OscError::InvalidPacket("unterminated string".to_string()))?;
+    let text = std::str::from_utf8(&data[..end]).map_err(|_| OscError::InvalidPacket("string is not UTF-8".to_string()))?;
+    let padded = (end / 4 + 1) * 4;
+    if padded > data.len() {
+        return Err(OscError::InvalidPacket("string runs past the end of the packet".to_string()));
+    }
+    *data = &data[padded..];
+    Ok(text)
+}
+
+fn read_bytes<const N: usize>(data: &mut &[u8]) -> Result<[u8; N], OscError> {
+    if data.len() < N {
+        return Err(OscError::InvalidPacket("argument runs past the end of the packet".to_string()));
+    }
+    let bytes = data[..N].try_into().unwrap();
+    *data = &data[N..];
+    Ok(bytes)
+}
+
+impl OscPacket {
+    pub fn message(address: &str, arguments: Vec<OscArgument>) -> OscPacket {
+        OscPacket::Message(OscMessage { address: address.to_string(), arguments })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        match self {
+            OscPacket::Message(message) => {
+                write_string(&mut output, &message.address);
+                let tags: String = std::iter::once(',').chain(message.arguments.iter().map(|x| x.tag())).collect();
+                write_string(&mut output, &tags);
+                for argument in &message.arguments {
+                    match argument {
+                        OscArgument::Int(x) => output.extend(x.to_be_bytes()),
+                        OscArgument::Float(x) => output.extend(x.to_be_bytes()),
+                        OscArgument::Double(x) => output.extend(x.to_be_bytes()),
+                        OscArgument::String(x) => write_string(&mut output, x),
+                        OscArgument::Bool(_) => {}
+                    }
+                }
+            }
+            OscPacket::Bundle { timetag, packets } => {
+                output.extend(BUNDLE_TAG);
+                output.extend(timetag.to_be_bytes());
+                for packet in packets {
+                    let data = packet.encode();
+                    output.extend((data.len() as u32).to_be_bytes());
+                    output.extend(data);
+                }
+            }
+        }
+        output
+    }
+
+    pub fn decode(data: &[u8]) -> Result<OscPacket, OscError> {
+        if !data.len().is_multiple_of(4) {
+            return Err(OscError::InvalidPacket(format!("{} bytes is not a multiple of 4", data.len())));
+        }
+
+        if let Some(mut rest) = data.strip_prefix(BUNDLE_TAG) {
+            let timetag = u64::from_be_bytes(read_bytes(&mut rest)?);
+            let mut packets = Vec::new();
+            while !rest.is_empty() {
+                let length = u32::from_be_bytes(read_bytes(&mut rest)?) as usize;
+                if length > rest.len() {
+                    return Err(OscError::InvalidPacket("bundle element runs past the end of the packet".to_string()));
+                }
+                packets.push(OscPacket::decode(&rest[..length])?);
+                rest = &rest[length..];
+            }
+            return Ok(OscPacket::Bundle { timetag, packets });
+        }
+
+        let mut rest = data;
+        let address = read_string(&mut rest)?.to_string();
+        if !address.starts_with('/') {
+            return Err(OscError::InvalidPacket(format!("{} is not an OSC address", address)));
+        }
+        //very old senders omit the type tags entirely, which we treat as no arguments
+        let tags = if rest.is_empty() { "," } else { read_string(&mut rest)? };
+        let tags = tags.strip_prefix(',').ok_or_else(|| OscError::InvalidPacket("missing type tags".to_string()))?;
+
+        let mut arguments = Vec::new();
+        for tag in tags.chars() {
+            arguments.push(match tag {
+                'i' => OscArgument::Int(i32::from_be_bytes(read_bytes(&mut rest)?)),
+                'f' => OscArgument::Float(f32::from_be_bytes(read_bytes(&mut rest)?)),
+                'd' => OscArgument::Double(f64::from_be_bytes(read_bytes(&mut rest)?)),
+                's' => OscArgument::String(read_string(&mut rest)?.to_string()),
+                'T' => OscArgument::Bool(true),
+                'F' => OscArgument::Bool(false),
+                x => return Err(OscError::InvalidPacket(format!("unsupported argument type {}", x))),
+            });
+        }
+        Ok(OscPacket::Message(OscMessage { address, arguments }))
+    }
+
+    ///Every message in the packet, in order, looking inside bundles.
+    pub fn messages(&self) -> Vec<&OscMessage> {
+        match self {
+            OscPacket::Message(message) => vec![message],
+            OscPacket::Bundle { packets, .. } => packets.iter().flat_map(|x| x.messages()).collect(),
+        }
+    }
+}
+
+///Where each value is sent, as a template containing `{camera}` and `{field}`, e.g. `/camera/{camera}/{field}`.
+/// Both must make up a whole segment of the address so incoming addresses can be matched against the schema.
+///
+/// Position fields are `pan`, `tilt`, `roll`, `x`, `y`, `z`, `zoom` and `focus`. Status fields are `status`,
+/// `targets_seen`, `targets_identified`, `targets_used` and `rms_error`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AddressSchema {
+    segments: Vec<String>,
+}
+
+impl Default for AddressSchema {
+    fn default() -> Self {
+        "/camera/{camera}/{field}".parse().expect("Valid schema")
+    }
+}
+
+impl FromStr for AddressSchema {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let segments: Vec<String> = s.strip_prefix('/').ok_or_else(|| format!("{} does not start with /", s))?
+            .split('/')
+            .map(|x| x.to_string())
+            .collect();
+        for placeholder in ["{camera}", "{field}"] {
+            if segments.iter().filter(|x| *x == placeholder).count() != 1 {
+                return Err(format!("{} must contain {} once, as a whole segment", s, placeholder));
+            }
+        }
+        if segments.iter().any(|x| x.is_empty() || (x.contains('{') && x != "{camera}" && x != "{field}")) {
+            return Err(format!("{} has an empty segment or unknown placeholder", s));
+        }
+        Ok(AddressSchema { segments })
+    }
+}
+
+impl Display for AddressSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "/{}", self.segments.join("/"))
+    }
+}
+
+impl AddressSchema {
+    pub fn address(&self, cameraid: u8, field: &str) -> String {
+        let segments: Vec<String> = self.segments.iter().map(|x| match x.as_str() {
+            "{camera}" => cameraid.to_string(),
+            "{field}" => field.to_string(),
+            x => x.to_string(),
+        }).collect();
+        format!("/{}", segments.join("/"))
+    }
+
+    ///Splits an address into its camera id and field, or returns `None` if it does not match the schema.
+    pub fn parse<'a>(&self, address: &'a str) -> Option<(u8, &'a str)> {
+        let parts: Vec<&str> = address.strip_prefix('/')?.split('/').collect();
+        if parts.len() != self.segments.len() {
+            return None;
+        }
+
+        let (mut cameraid, mut field) = (None, None);
+        for (part, segment) in parts.iter().zip(&self.segments) {
+            match segment.as_str() {
+                "{camera}" => cameraid = Some(part.parse::<u8>().ok()?),
+                "{field}" => field = Some(*part),
+                x if x != *part => return None,
+                _ => {}
+            }
+        }
+        Some((cameraid?, field?))
+    }
+}
+
+///Units values are sent in. Physical units are degrees for rotations, metres for positions, pixels for the RMS
+/// error and the fraction of the encoder range for zoom and focus, all as floats. Raw units are the integers carried
+/// by free-d.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum OscUnits {
+    #[default]
+    Physical,
+    Raw,
+}
+
+impl FromStr for OscUnits {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "physical" => Ok(OscUnits::Physical),
+            "raw" => Ok(OscUnits::Raw),
+            x => Err(format!("{} is not physical or raw", x)),
+        }
+    }
+}
+
+///The name of an axis in OSC addresses.
+pub fn field_name(axis: Axis) -> &'static str {
+    match axis {
+        Axis::Pitch => "tilt",
+        Axis::Yaw => "pan",
+        Axis::Roll => "roll",
+        Axis::Z => "z",
+        Axis::Y => "y",
+        Axis::X => "x",
+        Axis::Zoom => "zoom",
+        Axis::Focus => "focus",
+    }
+}
+
+///Physical units per raw unit of an axis.
+fn scale(axis: Axis) -> f64 {
+    match axis {
+        Axis::Pitch | Axis::Yaw | Axis::Roll => 1.0 / 32768.0,
+        Axis::X | Axis::Y | Axis::Z => 1.0 / 64000.0,
+        Axis::Zoom | Axis::Focus => 1.0 / u32::from(u24::MAX) as f64,
+    }
+}
+
+///Converts decoded free-d messages into OSC. With `bundle` set, the values of each message are sent together as one
+/// bundle so receivers see a whole frame at once, otherwise each value is a packet of its own.
+///
+/// ```rust,ignore
+/// let encoder = OscEncoder { schema: "/crane/{camera}/{field}".parse()?, ..OscEncoder::default() };
+/// for packet in encoder.encode(&deserialise_payloads(&data)?) {
+///     socket.send_to(&packet.encode(), lighting)?;
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OscEncoder {
+    pub schema: AddressSchema,
+    pub units: OscUnits,
+    pub bundle: bool,
+}
+
+impl OscEncoder {
+    fn value(&self, raw: f64, scale: f64) -> OscArgument {
+        match self.units {
+            OscUnits::Physical => OscArgument::Float((raw * scale) as f32),
+            OscUnits::Raw => OscArgument::Int(raw as i32),
+        }
+    }
+
+    ///The OSC messages for a free-d message. Only position and system status messages are converted.
+    pub fn messages(&self, message: &Message<Payloads>) -> Vec<OscMessage> {
+        let cameraid = message.cameraid;
+        let values: Vec<(&str, OscArgument)> = match &message.payload {
+            Payloads::PositionPollPayload(payload) => Axis::ALL.iter()
+                .map(|x| (field_name(*x), self.value(payload.get(*x), scale(*x))))
+                .collect(),
+            Payloads::SystemStatusPayload(payload) => vec![
+                ("status", OscArgument::Int(payload.systemstatus as i32)),
+                ("targets_seen", OscArgument::Int(payload.numtargetsseen as i32)),
+                ("targets_identified", OscArgument::Int(payload.numtargetsidentified as i32)),
+                ("targets_used", OscArgument::Int(payload.numtargetsused as i32)),
+                ("rms_error", self.value(u32::from(payload.rmserror.0) as f64, 1.0 / Pixel32768th::PER_PIXEL)),
+            ],
+            _ => Vec::new(),
+        };
+
+        values.into_iter()
+            .map(|(field, argument)| OscMessage { address: self.schema.address(cameraid, field), arguments: vec![argument] })
+            .collect()
+    }
+
+    pub fn encode(&self, message: &Message<Payloads>) -> Vec<OscPacket> {
+        let packets: Vec<OscPacket> = self.messages(message).into_iter().map(OscPacket::Message).collect();
+        if self.bundle && !packets.is_empty() {
+            return vec![OscPacket::Bundle { timetag: IMMEDIATELY, packets }];
+        }
+        packets
+    }
+}
+
+///A simulated unit driven by OSC, the reverse of `OscEncoder`. Position fields addressed by the schema set the pose
+/// of that camera, which is then sent as free-d.
+///
+/// Messages to addresses outside the schema are ignored, since controllers often share a port with other software.
+/// A bundle is applied in order, so messages before an invalid one still take effect.
+#[derive(Clone, Debug, Default)]
+pub struct OscController {
+    pub schema: AddressSchema,
+    pub units: OscUnits,
+    poses: BTreeMap<u8, PositionPollPayload>,
+}
+
+impl OscController {
+    pub fn new(schema: AddressSchema, units: OscUnits) -> OscController {
+        OscController { schema, units, poses: BTreeMap::new() }
+    }
+
+    ///Applies every message in a packet, returning the cameras whose pose changed.
+    pub fn apply(&mut self, packet: &OscPacket) -> Result<Vec<u8>, OscError> {
+        let mut changed = Vec::new();
+        for message in packet.messages() {
+            let Some((cameraid, field)) = self.schema.parse(&message.address) else {
+                continue;
+            };
+            let error = |description: &str| OscError::InvalidMessage { address: message.address.clone(), description: description.to_string() };
+
+            let axis = Axis::ALL.into_iter().find(|x| field_name(*x) == field).ok_or_else(|| error("not a position field"))?;
+            let value = match &message.arguments[..] {
+                [argument] => argument.number().ok_or_else(|| error("expected a number"))?,
+                _ => return Err(error("expected a single argument")),
+            };
+            let raw = match self.units {
+                OscUnits::Physical => value / scale(axis),
+                OscUnits::Raw => value,
+            };
+
+            self.poses.entry(cameraid).or_default().set(axis, raw);
+            if !changed.contains(&cameraid) {
+                changed.push(cameraid);
+            }
+        }
+        Ok(changed)
+    }
+
+    ///The current pose of a camera. Cameras that have not been addressed yet are at rest at the origin.
+    pub fn pose(&self, cameraid: u8) -> PositionPollPayload {
+        self.poses.get(&cameraid).copied().unwrap_or_default()
+    }
+
+    ///Position messages for every camera addressed so far, in camera id order.
+    pub fn messages(&self) -> Vec<Message<PositionPollPayload>> {
+        self.poses.iter().map(|(cameraid, pose)| Message::new(*pose, *cameraid)).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ux::i24;
+
+    #[test]
+    fn packet_round_trip() {
+        let message = OscPacket::message("/camera/1/pan", vec![
+            OscArgument::Float(1.5),
+            OscArgument::Int(-3),
+            OscArgument::String("abcd".to_string()),
+            OscArgument::Bool(true),
+            OscArgument::Double(0.25),
+        ]);
+        let data = message.encode();
+        assert_eq!(&data[..16], b"/camera/1/pan\0\0\0");
+        assert_eq!(&data[16..24], b",fisTd\0\0");
+        assert_eq!(OscPacket::decode(&data).unwrap(), message);
+
+        let bundle = OscPacket::Bundle { timetag: IMMEDIATELY, packets: vec![message.clone(), OscPacket::message("/x", vec![])] };
+        let decoded = OscPacket::decode(&bundle.encode()).unwrap();
+        assert_eq!(decoded, bundle);
+        assert_eq!(decoded.messages().len(), 2);
+
+        assert!(OscPacket::decode(&data[..data.len() - 4]).is_err());
+        assert!(OscPacket::decode(b"nope\0\0\0\0").is_err());
+    }
+
+    #[test]
+    fn schema() {
+        assert_eq!(AddressSchema::default().to_string(), "/camera/{camera}/{field}");
+        assert!("/stage/{field}/cam{camera}".parse::<AddressSchema>().is_err());
+
+        let schema: AddressSchema = "/stage/{field}/{camera}".parse().unwrap();
+        assert_eq!(schema.address(2, "zoom"), "/stage/zoom/2");
+        assert_eq!(schema.parse("/stage/zoom/2"), Some((2, "zoom")));
+        assert_eq!(schema.parse("/stage/zoom/two"), None);
+        assert_eq!(schema.parse("/lights/zoom/2"), None);
+        assert!("/camera/{camera}".parse::<AddressSchema>().is_err());
+        assert!("camera/{camera}/{field}".parse::<AddressSchema>().is_err());
+    }
+
+    #[test]
+    fn encode_position() {
+        let payload = PositionPollPayload { yaw: i24::new(90 * 32768), pos_x: i24::new(-32000), zoom: u24::MAX, ..PositionPollPayload::default() };
+        let message = Message::new(Payloads::PositionPollPayload(payload), 1);
+
+        let encoder = OscEncoder::default();
+        let packets = encoder.encode(&message);
+        assert_eq!(packets.len(), 8);
+        assert!(packets.contains(&OscPacket::message("/camera/1/pan", vec![OscArgument::Float(90.0)])));
+        assert!(packets.contains(&OscPacket::message("/camera/1/x", vec![OscArgument::Float(-0.5)])));
+        assert!(packets.contains(&OscPacket::message("/camera/1/zoom", vec![OscArgument::Float(1.0)])));
+
+        let encoder = OscEncoder { units: OscUnits::Raw, bundle: true, ..OscEncoder::default() };
+        let packets = encoder.encode(&message);
+        assert_eq!(packets.len(), 1);
+        assert!(packets[0].messages().contains(&&OscMessage { address: "/camera/1/pan".to_string(), arguments: vec![OscArgument::Int(90 * 32768)] }));
+
+        let status = SystemStatusPayload { numtargetsused: 7, ..SystemStatusPayload::default() };
+        let packets = OscEncoder::default().encode(&Message::new(Payloads::SystemStatusPayload(status), 2));
+        assert!(packets.contains(&OscPacket::message("/camera/2/targets_used", vec![OscArgument::Int(7)])));
+        assert!(OscEncoder::default().encode(&Message::new(Payloads::PollPayload(PollPayload::default()), 2)).is_empty());
+    }
+
+    #[test]
+    fn controller() {
+        let mut controller = OscController::default();
+        let bundle = OscPacket::Bundle { timetag: IMMEDIATELY, packets: vec![
+            OscPacket::message("/camera/3/pan", vec![OscArgument::Float(-45.0)]),
+            OscPacket::message("/camera/3/y", vec![OscArgument::Double(2.0)]),
+            OscPacket::message("/mixer/fader/1", vec![OscArgument::Float(0.5)]),
+        ] };
+        assert_eq!(controller.apply(&bundle).unwrap(), vec![3]);
+        assert_eq!(controller.pose(3).yaw, i24::new(-45 * 32768));
+        assert_eq!(controller.pose(3).pos_y, i24::new(128000));
+        assert_eq!(controller.messages().len(), 1);
+        assert_eq!(controller.messages()[0].cameraid, 3);
+
+        assert!(controller.apply(&OscPacket::message("/camera/3/status", vec![OscArgument::Int(0)])).is_err());
+        assert!(controller.apply(&OscPacket::message("/camera/3/pan", vec![OscArgument::String("left".to_string())])).is_err());
+
+        let mut controller = OscController::new(AddressSchema::default(), OscUnits::Raw);
+        controller.apply(&OscPacket::message("/camera/1/focus", vec![OscArgument::Int(1000)])).unwrap();
+        assert_eq!(controller.pose(1).focus, u24::new(1000));
+    }
+}
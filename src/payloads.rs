@@ -1,9 +1,6 @@
-#![allow(dead_code)]
-
 use crate::common::*;
 use crate::error::*;
 
-use std::alloc::System;
 use std::vec;
 
 use ux::i24;
@@ -45,14 +42,51 @@ impl std::fmt::Display for Payloads {
     }
 }
 
+impl Default for Payloads {
+    fn default() -> Self {
+        Payloads::PositionPollPayload(PositionPollPayload::default())
+    }
+}
+
+impl Serialise for Payloads {
+    fn serialise(self) -> Vec<u8> {
+        match self {
+            Payloads::PollPayload(x) => x.serialise(),
+            Payloads::PositionPollPayload(x) => x.serialise(),
+            Payloads::SystemStatusPayload(x) => x.serialise(),
+            Payloads::SystemControlPayload(x) => x.serialise(),
+            Payloads::TargetDataPayload(x) => x.serialise(),
+            Payloads::ImageDataPayload(x) => x.serialise(),
+            Payloads::EEPROMDataPayload(x) => x.serialise(),
+            Payloads::EEPROMDataRequestPayload(x) => x.serialise(),
+            Payloads::CameraCalibrationPayload(x) => x.serialise(),
+            Payloads::DiagnosticModePayload(x) => x.serialise(),
+        }
+    }
+
+    fn command(&self) -> Commands {
+        match self {
+            Payloads::PollPayload(x) => x.command(),
+            Payloads::PositionPollPayload(x) => x.command(),
+            Payloads::SystemStatusPayload(x) => x.command(),
+            Payloads::SystemControlPayload(x) => x.command(),
+            Payloads::TargetDataPayload(x) => x.command(),
+            Payloads::ImageDataPayload(x) => x.command(),
+            Payloads::EEPROMDataPayload(x) => x.command(),
+            Payloads::EEPROMDataRequestPayload(x) => x.command(),
+            Payloads::CameraCalibrationPayload(x) => x.command(),
+            Payloads::DiagnosticModePayload(x) => x.command(),
+        }
+    }
+}
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
-///Struct for a simple poll command, corresponding to free-d command `0xD1`
+///Struct for a simple poll command, corresponding to free-d command `0xD0`
 /// 
 ///This is used by the free-d protocol to send poll commands to a free-d unit, which
 /// should return back a payload message corresponding to the desired command.
 /// 
-/// ```rust
+/// ```rust,ignore
 /// let payload: PollPayload = PollPayload {command: Commands::POSITION_POLL}
 /// 
 /// //serialise the data to send it
@@ -64,16 +98,16 @@ pub struct PollPayload {
 
 impl Default for PollPayload {
     fn default() -> Self {
-        return PollPayload {
+        PollPayload {
             command: Commands::SYSTEM_STATUS,
-        };
+        }
     }
 }
 
 impl Serialise for PollPayload {
-    const COMMAND: Commands = Commands::POSITION_POLL; //unused?
+    const COMMAND: Commands = Commands::POLL;
     fn serialise(self) -> Vec<u8> {
-        return vec![self.command as u8];
+        vec![self.command as u8]
     }
 
 }
@@ -86,7 +120,7 @@ impl Deserialise for PollPayload {
             Err(x) => return Err(DeserialiseError { description: x }),
         };
 
-        Ok(PollPayload { command: command })
+        Ok(PollPayload { command })
     }
 }
 
@@ -95,14 +129,14 @@ impl Deserialise for PollPayload {
 ///System Status Payload. Typically reported by the free-d unit in response to a
 /// `0xD2` `SYSTEM_STATUS` poll. 
 /// 
-/// `switchsetting` - see `SwitchSettingFlags`. Describes the status of the free-d switch
+/// `switchsetting` - see `SwitchSettings`. Describes the positions of the free-d unit's switches
 /// 
 /// `ledindication` - see `LEDFlags`. Reports the status of the LED indicators on the free-d unit
 /// 
 /// `systemstatus` - see `SystemStatus`. Reports the internal state of the free-d unit.
 /// 
 /// `cpufirmwareversion`, `pldfirmwareversion`, `dspsoftwareversion`. Firmware version values of various hardware components of the
-/// free-d unit in Binary Coded Decimal, where there is an implied decimal point between the two digits. e.g. `0x12 = 1.2`. 
+/// free-d unit. See `FirmwareVersion`. 
 /// 
 /// `dspstatus` - Reports either the number of iterations required to compute the camera's position, or an error as defined in `DSPError`
 /// 
@@ -113,58 +147,59 @@ impl Deserialise for PollPayload {
 /// `numtargetsused` - The number of targets used (identified and in database)
 /// 
 /// `rmserror` - RMS error expressed in pixels, where 1 unit = 1/32768 pixels. Bits 22 to 15 decide the integer part of the value,
-/// while bits 14 to 0 decide the fractional part of the value. 
+/// while bits 14 to 0 decide the fractional part of the value. Use `Pixel32768th::pixels()` to get the value in pixels. 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct SystemStatusPayload {
-    pub switchsetting: SwitchSettingFlags,
+    pub switchsetting: SwitchSettings,
     pub ledindication: LEDFlags,
     pub systemstatus: SystemStatus,
-    pub cpufirmwareversion: u8,
-    pub pldfirmwareversion: u8,
-    pub dspsoftwareversion: u8,
+    pub cpufirmwareversion: FirmwareVersion,
+    pub pldfirmwareversion: FirmwareVersion,
+    pub dspsoftwareversion: FirmwareVersion,
     pub dspstatus: Result<i8, DSPError>,
     pub numtargetsseen: u8,
     pub numtargetsidentified: u8,
     pub numtargetsused: u8,
-    pub rmserror: u24,
+    pub rmserror: Pixel32768th,
 }
 
 impl Default for SystemStatusPayload {
     fn default() -> Self {
         Self {
-            switchsetting: SwitchSettingFlags::default(),
+            switchsetting: SwitchSettings::default(),
             ledindication: LEDFlags::default(),
             systemstatus: SystemStatus::default(),
-            cpufirmwareversion: u8::default(),
-            pldfirmwareversion: u8::default(),
-            dspsoftwareversion: u8::default(),
+            cpufirmwareversion: FirmwareVersion::default(),
+            pldfirmwareversion: FirmwareVersion::default(),
+            dspsoftwareversion: FirmwareVersion::default(),
             dspstatus: Ok(i8::default()),
             numtargetsseen: u8::default(),
             numtargetsidentified: u8::default(),
             numtargetsused: u8::default(),
-            rmserror: u24::default(),
+            rmserror: Pixel32768th::default(),
         }
     }
 }
 
 #[allow(non_snake_case)]
 impl Serialise for SystemStatusPayload {
+    const COMMAND: Commands = Commands::SYSTEM_STATUS;
     fn serialise(self) -> Vec<u8> {
         let dspstatusserial = match self.dspstatus {
             Ok(x) => x,
             Err(DSPError) => DSPError as i8,
         };
 
-        let rmserror32: u32 = self.rmserror.into();
+        let rmserror32: u32 = self.rmserror.0.into();
         let [__a, b, c, d] = rmserror32.to_be_bytes();
 
         vec![
-            SwitchSettingFlags::bits(&self.switchsetting),
+            self.switchsetting.into(),
             LEDFlags::bits(&self.ledindication),
             self.systemstatus as u8,
-            self.cpufirmwareversion,
-            self.pldfirmwareversion,
-            self.dspsoftwareversion,
+            self.cpufirmwareversion.bcd(),
+            self.pldfirmwareversion.bcd(),
+            self.dspsoftwareversion.bcd(),
             dspstatusserial as u8,
             self.numtargetsseen,
             self.numtargetsidentified,
@@ -185,7 +220,7 @@ impl Deserialise for SystemStatusPayload {
             SIZE, PAYLOADID)})
         }
 
-        let switchsetting: SwitchSettingFlags = match array[0].try_into() {
+        let switchsetting: SwitchSettings = match array[0].try_into() {
             Ok(x) => x,
             Err(x) => return Err(DeserialiseError { description: x })
         };
@@ -199,6 +234,14 @@ impl Deserialise for SystemStatusPayload {
             Ok(x) => x,
             Err(x) => return Err(DeserialiseError { description: x })
         };
+        let mut versions = [FirmwareVersion::default(); 3];
+        for (version, byte) in versions.iter_mut().zip(&array[3..6]) {
+            *version = match (*byte).try_into() {
+                Ok(x) => x,
+                Err(x) => return Err(DeserialiseError { description: x })
+            };
+        }
+
         let dspcode = array[6] as i8;
         
         let dspstatus: Result<i8, DSPError> = match dspcode {
@@ -209,19 +252,24 @@ impl Deserialise for SystemStatusPayload {
                 Err(x) => return Err(DeserialiseError { description: x })}),
             _ => unreachable!()};
 
+        let rmserror = u24::from_be_bytes(array[10..].try_into().expect("Three Bytes"));
+        if u32::from(rmserror) > Pixel32768th::MAX_UNITS {
+            return Err(DeserialiseError { description: format!("Misformed RMS error {:#08x}, bit 23 must be clear", rmserror) });
+        }
+
             
          Ok(SystemStatusPayload {
-            switchsetting: switchsetting,
-            ledindication: ledindication,
-            systemstatus: systemstatus,
-            cpufirmwareversion: array[3],
-            pldfirmwareversion: array[4],
-            dspsoftwareversion: array[5],
-            dspstatus: dspstatus,
+            switchsetting,
+            ledindication,
+            systemstatus,
+            cpufirmwareversion: versions[0],
+            pldfirmwareversion: versions[1],
+            dspsoftwareversion: versions[2],
+            dspstatus,
             numtargetsseen: array[7],
             numtargetsidentified: array[8],
             numtargetsused: array[9], 
-            rmserror: u24::from_be_bytes(array[10..].try_into().expect("Three Bytes"))
+            rmserror: Pixel32768th(rmserror)
         })
 
         
@@ -303,7 +351,7 @@ impl Serialise for TargetDataPayload {
         serial.extend(self.targetnumber.to_be_bytes().iter());
         serial.extend(serialisei24array(&targetarray));
 
-        return serial;
+        serial
     }
 }
 
@@ -316,7 +364,7 @@ impl Deserialise for TargetDataPayload {
         }
 
         Ok(TargetDataPayload { 
-            studioid: array[0] as u8, 
+            studioid: array[0], 
             targetnumber: u16::from_be_bytes(array[1..3].try_into().unwrap()), 
             targetx: i24::from_be_bytes(array[3..6].try_into().unwrap()), 
             targety: i24::from_be_bytes(array[6..9].try_into().unwrap()), 
@@ -342,7 +390,7 @@ impl Serialise for ImageDataPayload {
         let mut serial = vec![self.targetindex];
         serial.extend(self.targetnum.to_be_bytes().iter());
         serial.extend(serialisei24array(&targettuple));
-        return serial;
+        serial
     }
 }
 
@@ -378,7 +426,7 @@ impl Serialise for EEPROMDataPayload {
         let databytes = self.EEPROMdata;
         serial.extend(databytes.iter());
 
-        return serial;
+        serial
     }
 }
 
@@ -407,7 +455,7 @@ impl Serialise for EEPROMDataRequestPayload {
     const COMMAND: Commands = Commands::REQUEST_EEPROM;
     fn serialise(self) -> Vec<u8> {
         let bytes = self.EEPROMaddress.to_be_bytes();
-        return bytes.to_vec();
+        bytes.to_vec()
     }
 }
 
@@ -452,7 +500,7 @@ impl Serialise for CameraCalibrationPayload {
             self.zoffset,
         ];
 
-        return serialisei24array(&order);
+        serialisei24array(&order)
     }
 }
 
@@ -467,7 +515,7 @@ impl Deserialise for CameraCalibrationPayload {
 
         Ok(CameraCalibrationPayload { 
             lenscentrex: i24::from_be_bytes(array[0..3].try_into().unwrap()), 
-            lenscentrey: i24::from_be_bytes(array[3*1..3*2].try_into().unwrap()), 
+            lenscentrey: i24::from_be_bytes(array[3..3*2].try_into().unwrap()), 
             lensscalex: i24::from_be_bytes(array[3*2..3*3].try_into().unwrap()), 
             lensscaley: i24::from_be_bytes(array[3*3..3*4].try_into().unwrap()), 
             lensdistortiona: i24::from_be_bytes(array[3*4..3*5].try_into().unwrap()), 
@@ -486,7 +534,7 @@ pub struct DiagnosticModePayload {
 impl Serialise for DiagnosticModePayload {
     const COMMAND: Commands = Commands::DIAGNOSTIC_MODE;
     fn serialise(self) -> Vec<u8> {
-        return vec![self.diagnosticflag as u8];
+        vec![self.diagnosticflag as u8]
     }
 }
 
@@ -511,7 +559,6 @@ impl Deserialise for DiagnosticModePayload {
 /// Note that most fields are 24 bit (as required by the protocol spec) - this will panic if you
 /// attempt to place too large or small values into it. Use the `u24::new()` (or `i24::new()`) function to generate values
 /// from literals or primitive integer types.
-
 //TODO: Compile time checks?
 pub struct PositionPollPayload {
     pub pitch: i24,
@@ -522,7 +569,7 @@ pub struct PositionPollPayload {
     pub pos_x: i24,
     pub zoom: u24,
     pub focus: u24,
-    pub userdefined: u16, //arbitrary [u8; 2]?
+    pub userdefined: u16, //vendor specific, see timecode::UserDefinedLayout
 }
 
 impl Default for PositionPollPayload {
@@ -563,7 +610,7 @@ impl Serialise for PositionPollPayload {
         for (index, field) in i32fields.iter().enumerate() {
             let [__a, b, c, d] = field.to_be_bytes();
 
-            serial[(index * 3) + 0] = b;
+            serial[index * 3] = b;
             serial[(index * 3) + 1] = c;
             serial[(index * 3) + 2] = d;
         }
@@ -571,7 +618,7 @@ impl Serialise for PositionPollPayload {
         for (index, field) in u32fields.iter().enumerate() {
             let [__a, b, c, d] = field.to_be_bytes();
 
-            serial[(index * 3) + i32fields.len() * 3 + 0] = b;
+            serial[(index * 3) + i32fields.len() * 3] = b;
             serial[(index * 3) + i32fields.len() * 3 + 1] = c;
             serial[(index * 3) + i32fields.len() * 3 + 2] = d;
         }
@@ -580,9 +627,7 @@ impl Serialise for PositionPollPayload {
         serial[serial.len() - 2] = a;
         serial[serial.len() - 1] = b;
 
-        let constserial = serial.to_vec();
-
-        return constserial;
+        serial.to_vec()
     }
 }
 
@@ -615,7 +660,118 @@ impl Deserialise for PositionPollPayload {
             userdefined: u16::from_be_bytes(array[3 * 8..].try_into().unwrap()),
         };
 
-        return Ok(payload);
+        Ok(payload)
+    }
+}
+
+///The continuously varying fields of a `PositionPollPayload`, for code that processes each field as a separate signal.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Axis {
+    Pitch,
+    Yaw,
+    Roll,
+    Z,
+    Y,
+    X,
+    Zoom,
+    Focus,
+}
+
+impl Axis {
+    pub const ALL: [Axis; 8] = [Axis::Pitch, Axis::Yaw, Axis::Roll, Axis::Z, Axis::Y, Axis::X, Axis::Zoom, Axis::Focus];
+
+    ///Raw rotation units in one full turn. Each unit is 1/32768th of a degree.
+    pub const TURN: f64 = 360.0 * 32768.0;
+
+    pub fn is_rotation(self) -> bool {
+        matches!(self, Axis::Pitch | Axis::Yaw | Axis::Roll)
+    }
+
+    ///Index of the axis in `Axis::ALL`, for per-axis arrays.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl std::fmt::Display for Axis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Axis::Pitch => "pitch",
+            Axis::Yaw => "yaw",
+            Axis::Roll => "roll",
+            Axis::Z => "pos_z",
+            Axis::Y => "pos_y",
+            Axis::X => "pos_x",
+            Axis::Zoom => "zoom",
+            Axis::Focus => "focus",
+        })
+    }
+}
+
+impl std::str::FromStr for Axis {
+    type Err = String;
+
+    ///Parses the names used by `Display`, such as `pitch` or `pos_x`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Axis::ALL.into_iter().find(|x| x.to_string() == s.trim()).ok_or_else(|| format!("{} is not an axis", s))
+    }
+}
+
+///Wraps a raw rotation value into the range -180 to +180 degrees.
+pub fn wrap_angle(raw: f64) -> f64 {
+    (raw + Axis::TURN / 2.0).rem_euclid(Axis::TURN) - Axis::TURN / 2.0
+}
+
+///The signed rotation from `from` to `to` going the short way round, in raw units.
+pub fn angle_difference(from: f64, to: f64) -> f64 {
+    wrap_angle(to - from)
+}
+
+impl PositionPollPayload {
+    ///Gets the raw value of a field.
+    pub fn get(&self, axis: Axis) -> f64 {
+        match axis {
+            Axis::Pitch => i32::from(self.pitch) as f64,
+            Axis::Yaw => i32::from(self.yaw) as f64,
+            Axis::Roll => i32::from(self.roll) as f64,
+            Axis::Z => i32::from(self.pos_z) as f64,
+            Axis::Y => i32::from(self.pos_y) as f64,
+            Axis::X => i32::from(self.pos_x) as f64,
+            Axis::Zoom => u32::from(self.zoom) as f64,
+            Axis::Focus => u32::from(self.focus) as f64,
+        }
+    }
+
+    ///Sets the raw value of a field, rounding to the nearest unit and clamping to the range the field can hold.
+    pub fn set(&mut self, axis: Axis, value: f64) {
+        let signed = value.round().clamp(i32::from(i24::MIN) as f64, i32::from(i24::MAX) as f64) as i32;
+        let unsigned = value.round().clamp(0.0, u32::from(u24::MAX) as f64) as u32;
+
+        match axis {
+            Axis::Pitch => self.pitch = i24::new(signed),
+            Axis::Yaw => self.yaw = i24::new(signed),
+            Axis::Roll => self.roll = i24::new(signed),
+            Axis::Z => self.pos_z = i24::new(signed),
+            Axis::Y => self.pos_y = i24::new(signed),
+            Axis::X => self.pos_x = i24::new(signed),
+            Axis::Zoom => self.zoom = u24::new(unsigned),
+            Axis::Focus => self.focus = u24::new(unsigned),
+        }
+    }
+
+    ///Linearly interpolates between two poses, where a `fraction` of 0 gives `self` and 1 gives `other`. Rotations
+    /// take the short way round. Fields other than the axes are taken from `self`.
+    pub fn interpolate(&self, other: &PositionPollPayload, fraction: f64) -> PositionPollPayload {
+        let mut output = *self;
+        for axis in Axis::ALL {
+            let (from, to) = (self.get(axis), other.get(axis));
+            if axis.is_rotation() {
+                output.set(axis, wrap_angle(from + angle_difference(from, to) * fraction));
+            } else {
+                output.set(axis, from + (to - from) * fraction);
+            }
+        }
+        output
     }
 }
 
@@ -623,14 +779,16 @@ impl TryFrom<Payloads> for PositionPollPayload {
     type Error = DeserialiseError;
 
     fn try_from(value: Payloads) -> Result<Self, Self::Error> {
-        return match value {
+        match value {
             Payloads::PositionPollPayload(x) => Ok(x),
             _=> Err(DeserialiseError { description: "Not a position poll payload".to_string() })
         }
     }
 }
 
-fn deserialise<T: Serialise + Default + Deserialise>(data: &[u8]) -> Result<Message<T>, DeserialiseError> {
+///Deserialises a complete message (command, camera id, payload and checksum) into a `Message<T>`.
+/// The checksum is verified before the payload is decoded.
+pub fn deserialise<T: Serialise + Default + Deserialise>(data: &[u8]) -> Result<Message<T>, DeserialiseError> {
     if data.len() < 4 {
         return Err(DeserialiseError {
             description: "Misformed data - the protocol defines no messages smaller than 4 bytes"
@@ -640,7 +798,7 @@ fn deserialise<T: Serialise + Default + Deserialise>(data: &[u8]) -> Result<Mess
 
     let checksum = data[data.len()-1];
 
-    if generate_checksum(&data) != checksum {
+    if generate_checksum(&data[..data.len()-1]) != checksum {
         return Err(DeserialiseError {
             description: "Misformed data - checksum is incorrect.".to_string(),
         });
@@ -651,7 +809,7 @@ fn deserialise<T: Serialise + Default + Deserialise>(data: &[u8]) -> Result<Mess
         Err(x) => return Err(DeserialiseError { description: x }),
     };
 
-    let payload: T = T::deserialise(&data[3..data.len()-1]).unwrap();
+    let payload: T = T::deserialise(&data[2..data.len()-1])?;
 
     // let payload = match command {
     //     Commands::POSITION_POLL => Payloads::PositionPollPayload(PositionPollPayload::deserialise(
@@ -659,12 +817,36 @@ fn deserialise<T: Serialise + Default + Deserialise>(data: &[u8]) -> Result<Mess
     //     )?),
     //     _ => todo!(),
     // };
-    return Ok(Message::<T> {command: command, cameraid: cameraid, payload: payload, checksum: checksum})
+    Ok(Message::<T> {command, cameraid, payload, checksum})
+}
+
+///Deserialises a complete message of any type into a `Message<Payloads>`, choosing the payload type from the
+/// command byte. This saves matching on `command_type` when the type of the incoming message is not known up front.
+pub fn deserialise_payloads(data: &[u8]) -> Result<Message<Payloads>, DeserialiseError> {
+    let command = command_type(data)?;
+
+    fn wrap<T: Serialise + Default>(message: Message<T>, variant: fn(T) -> Payloads) -> Message<Payloads> {
+        Message { command: message.command, cameraid: message.cameraid, payload: variant(message.payload), checksum: message.checksum }
+    }
+
+    match command {
+        Commands::POLL => Ok(wrap(deserialise(data)?, Payloads::PollPayload)),
+        Commands::POSITION_POLL => Ok(wrap(deserialise(data)?, Payloads::PositionPollPayload)),
+        Commands::SYSTEM_STATUS => Ok(wrap(deserialise(data)?, Payloads::SystemStatusPayload)),
+        Commands::SYSTEM_PARAMS => Ok(wrap(deserialise(data)?, Payloads::SystemControlPayload)),
+        Commands::FIRST_TARGET | Commands::NEXT_TARGET => Ok(wrap(deserialise(data)?, Payloads::TargetDataPayload)),
+        Commands::FIRST_IMAGE | Commands::NEXT_IMAGE => Ok(wrap(deserialise(data)?, Payloads::ImageDataPayload)),
+        Commands::EEPROM_DATA => Ok(wrap(deserialise(data)?, Payloads::EEPROMDataPayload)),
+        Commands::REQUEST_EEPROM => Ok(wrap(deserialise(data)?, Payloads::EEPROMDataRequestPayload)),
+        Commands::CAMERA_CALIBRATION => Ok(wrap(deserialise(data)?, Payloads::CameraCalibrationPayload)),
+        Commands::DIAGNOSTIC_MODE => Ok(wrap(deserialise(data)?, Payloads::DiagnosticModePayload)),
+        x => Err(DeserialiseError { description: format!("{} is not a message type", x) }),
+    }
 }
 
 /// Queries the command type for an arbitrary message. Asserts that the message is at least well-formed (correct size, valid checksum)
 /// Match on the result of this in order to deserialise into a specific type. 
-fn command_type(data: &[u8]) -> Result<Commands, DeserialiseError> {
+pub fn command_type(data: &[u8]) -> Result<Commands, DeserialiseError> {
     if data.len() < 4 
     {
         return Err(DeserialiseError {
@@ -674,8 +856,8 @@ fn command_type(data: &[u8]) -> Result<Commands, DeserialiseError> {
     }
 
     let checksum = data[data.len()-1];
-    if generate_checksum(&data) != checksum {
-        return Err(DeserialiseError { description: "Misformed data - checksum is incorrect.".to_string() }),
+    if generate_checksum(&data[..data.len()-1]) != checksum {
+        return Err(DeserialiseError { description: "Misformed data - checksum is incorrect.".to_string() });
     };
 
     let command: Commands = match data[0].try_into()
@@ -684,7 +866,7 @@ fn command_type(data: &[u8]) -> Result<Commands, DeserialiseError> {
         Err(x) => return Err(DeserialiseError { description:x }),
     };
 
-    return Ok(command);
+    Ok(command)
 }
 
 ///Message type for serialising and deserialising protocol messages. 
@@ -712,27 +894,32 @@ impl<T: Serialise + Default + Copy + Clone> Serialise for Message<T> {
         output.extend(payloaddata);
         let checksum = generate_checksum(&output);
         output.push(checksum);
-        return output;
+        output
     }
 }
 
 impl<T: Serialise + Default> Message<T> {
     pub fn new(payload: T, cameraid: u8) -> Message<T> {
         let payload: T = payload;
-        let command: Commands = <T as Serialise>::COMMAND;
+        let command: Commands = payload.command();
 
         
         Message {
-            command: command,
-            cameraid: cameraid,
-            payload: payload,
+            command,
+            cameraid,
+            payload,
             checksum: 0,
         }
     }
+    ///Gets the command byte this message will be sent with.
+    pub fn command(&self) -> Commands {
+        self.command
+    }
+
     //reset this to return a mutable reference.
     ///Gets a copy of the payload struct
     pub fn get_payload(self) -> T {
-        return self.payload;
+        self.payload
     }
 
     //Sets the payload struct. Note that the new payload must be the same type as the one retrieved from the message.
@@ -741,15 +928,15 @@ impl<T: Serialise + Default> Message<T> {
     }
 }
 
-//pull this out to public function? Doesn't depend on type
-fn generate_checksum(serialised: &[u8]) -> u8 {
+///Generates the checksum for a serialised message, excluding the checksum byte itself.
+pub fn generate_checksum(serialised: &[u8]) -> u8 {
     let mut checksum: u16 = 0x40;
     for byte in serialised {
         let upcast = *byte as u16;
         checksum = (checksum.wrapping_sub(upcast)) % 256;
     }
 
-    return (checksum % 256).try_into().unwrap(); //spec says 256.. verify.
+    (checksum % 256).try_into().unwrap() //spec says 256.. verify.
 }
 
 impl Message<TargetDataPayload> {
@@ -775,6 +962,23 @@ impl Message<ImageDataPayload> {
     }
 }
 
+impl Message<Payloads> {
+    ///Creates a message sent with `command` rather than the one `Message::new` picks, for payloads such as
+    /// `TargetDataPayload` that may be sent as either the first or the next of a series. Fails if the payload can't
+    /// be sent with `command`.
+    pub fn with_command(payload: Payloads, command: Commands, cameraid: u8) -> Result<Message<Payloads>, InvalidCommand> {
+        let allowedcommands = match payload.command() {
+            Commands::FIRST_TARGET => (Commands::FIRST_TARGET, Commands::NEXT_TARGET),
+            Commands::FIRST_IMAGE => (Commands::FIRST_IMAGE, Commands::NEXT_IMAGE),
+            x => (x, x),
+        };
+        if command != allowedcommands.0 && command != allowedcommands.1 {
+            return Err(InvalidCommand { allowedcommands, payload });
+        }
+        Ok(Message { command, cameraid, payload, checksum: 0 })
+    }
+}
+
 fn is_valid_message<T: Serialise>(array: &[u8]) -> bool {
     let command: Result<Commands, String> = array[0].try_into();
 
@@ -784,9 +988,9 @@ fn is_valid_message<T: Serialise>(array: &[u8]) -> bool {
     };
 
     //when is it possible for different arrays to have the same checksum?
-    let checksum_correct = generate_checksum(&array) == array[array.len() - 1];
+    let checksum_correct = generate_checksum(&array[..array.len() - 1]) == array[array.len() - 1];
 
-    return command_correct && checksum_correct;
+    command_correct && checksum_correct
 }
 
 fn serialisei24array(array: &[ux::i24]) -> Vec<u8> {
@@ -797,7 +1001,7 @@ fn serialisei24array(array: &[ux::i24]) -> Vec<u8> {
 
         serial.extend_from_slice(&elementi32.to_be_bytes()[1..]);
     }
-    return serial;
+    serial
 }
 
 #[cfg(test)]
@@ -836,7 +1040,7 @@ mod test {
     #[test]
     fn i24_from_be_bytes() {
         let mut x: i24 = i24::new(0x001234);
-        let mut xarray: [u8; 3] = [0x00, 0x12, 0x34];
+        let xarray: [u8; 3] = [0x00, 0x12, 0x34];
         let mut y = ux::i24::from_be_bytes(xarray);
 
         assert_eq!(x, y);
@@ -888,42 +1092,57 @@ mod test {
             
         }
         else {
-            //failed to deserialise
-            assert!(false);
+            panic!("failed to deserialise");
         }
     }
 
     #[allow(non_snake_case)]
+    #[test]
+    fn pollpayload_message() {
+        let message = Message::new(PollPayload { command: Commands::SYSTEM_STATUS }, 1);
+        assert_eq!(message.command(), Commands::POLL);
+
+        let data = message.serialise();
+        assert_eq!(data[..3], [0xD0, 1, 0xD2]);
+        assert_eq!(data[3], generate_checksum(&data[..3]));
+        assert_eq!(deserialise::<PollPayload>(&data).unwrap().payload.command, Commands::SYSTEM_STATUS);
+
+        //and the unit answers with a 0xD2 status message
+        assert_eq!(Message::new(SystemStatusPayload::default(), 1).serialise()[0], 0xD2);
+    }
+
     #[test]
     fn systemstatuspayload_serialise() {
-        let switchset = SwitchSettingFlags::S5_HEX_00 | SwitchSettingFlags::IS_S3_RIGHT;
-        let LEDindicate = LEDFlags::VIDEO_PRESENT | LEDFlags::VIDEO_OK | LEDFlags::SERIAL_PRESENT;
+        let switchset: SwitchSettings = (SwitchSettingFlags::S5_HEX_00 | SwitchSettingFlags::IS_S3_RIGHT).try_into().unwrap();
+        let ledindicate = LEDFlags::VIDEO_PRESENT | LEDFlags::VIDEO_OK | LEDFlags::SERIAL_PRESENT;
 
         let payload = SystemStatusPayload {
             switchsetting: switchset,
-            ledindication: LEDindicate,
+            ledindication: ledindicate,
             systemstatus: SystemStatus::SYSTEM_NORMAL,
-            cpufirmwareversion: 1,
-            pldfirmwareversion: 1,
-            dspsoftwareversion: 1,
+            cpufirmwareversion: FirmwareVersion::new(1, 2).unwrap(),
+            pldfirmwareversion: FirmwareVersion::new(0, 1).unwrap(),
+            dspsoftwareversion: FirmwareVersion::new(3, 0).unwrap(),
             dspstatus: Ok(3),
             numtargetsseen: 8,
             numtargetsidentified: 8,
             numtargetsused: 4,
-            rmserror: u24::new(454555),
+            rmserror: Pixel32768th(u24::new(454555)),
         };
 
         let serial = payload.serialise();
         assert_eq!(serial.len(), 13);
 
-        assert_eq!(serial[0], SwitchSettingFlags::bits(&switchset));
-        assert_eq!(serial[1], LEDFlags::bits(&LEDindicate));
+        assert_eq!(serial[0], 0b10000001);
+        assert_eq!(serial[1], LEDFlags::bits(&ledindicate));
 
         assert_eq!(serial[2], SystemStatus::SYSTEM_NORMAL as u8);
 
         assert_eq!(serial[6], 3);
 
-        assert_eq!(u24::from_be_bytes(serial[10..].try_into().unwrap()), payload.rmserror);
+        assert_eq!(serial[3], 0x12);
+
+        assert_eq!(u24::from_be_bytes(serial[10..].try_into().unwrap()), payload.rmserror.0);
     }
 
 
@@ -931,21 +1150,21 @@ mod test {
     #[allow(non_snake_case)]
     #[test]
     fn systemstatuspayload_serialise_dsperror() {
-        let switchset = SwitchSettingFlags::S5_HEX_00 | SwitchSettingFlags::IS_S3_RIGHT;
-        let LEDindicate = LEDFlags::VIDEO_PRESENT | LEDFlags::VIDEO_OK | LEDFlags::SERIAL_PRESENT;
+        let switchset: SwitchSettings = (SwitchSettingFlags::S5_HEX_00 | SwitchSettingFlags::IS_S3_RIGHT).try_into().unwrap();
+        let ledindicate = LEDFlags::VIDEO_PRESENT | LEDFlags::VIDEO_OK | LEDFlags::SERIAL_PRESENT;
 
         let payload = SystemStatusPayload {
             switchsetting: switchset,
-            ledindication: LEDindicate,
+            ledindication: ledindicate,
             systemstatus: SystemStatus::SYSTEM_NORMAL,
-            cpufirmwareversion: 1,
-            pldfirmwareversion: 1,
-            dspsoftwareversion: 1,
+            cpufirmwareversion: FirmwareVersion::new(1, 2).unwrap(),
+            pldfirmwareversion: FirmwareVersion::new(0, 1).unwrap(),
+            dspsoftwareversion: FirmwareVersion::new(3, 0).unwrap(),
             dspstatus: Err(DSPError::INTERNAL_ERROR),
             numtargetsseen: 8,
             numtargetsidentified: 8,
             numtargetsused: 4,
-            rmserror: u24::new(0),
+            rmserror: Pixel32768th(u24::new(0)),
         };
 
         let serial = payload.serialise();
@@ -955,21 +1174,21 @@ mod test {
 
     #[test]
     fn systemstatuspayload_deserialise() {
-        let switchset = SwitchSettingFlags::S5_HEX_00 | SwitchSettingFlags::IS_S3_RIGHT;
-        let LEDindicate = LEDFlags::VIDEO_PRESENT | LEDFlags::VIDEO_OK | LEDFlags::SERIAL_PRESENT;
+        let switchset: SwitchSettings = (SwitchSettingFlags::S5_HEX_00 | SwitchSettingFlags::IS_S3_RIGHT).try_into().unwrap();
+        let ledindicate = LEDFlags::VIDEO_PRESENT | LEDFlags::VIDEO_OK | LEDFlags::SERIAL_PRESENT;
 
         let payload = SystemStatusPayload {
             switchsetting: switchset,
-            ledindication: LEDindicate,
+            ledindication: ledindicate,
             systemstatus: SystemStatus::SYSTEM_NORMAL,
-            cpufirmwareversion: 1,
-            pldfirmwareversion: 1,
-            dspsoftwareversion: 1,
+            cpufirmwareversion: FirmwareVersion::new(1, 2).unwrap(),
+            pldfirmwareversion: FirmwareVersion::new(0, 1).unwrap(),
+            dspsoftwareversion: FirmwareVersion::new(3, 0).unwrap(),
             dspstatus: Ok(3),
             numtargetsseen: 8,
             numtargetsidentified: 8,
             numtargetsused: 4,
-            rmserror: u24::new(0),
+            rmserror: Pixel32768th(u24::new(0)),
         };
 
         let serial = payload.serialise();
@@ -978,10 +1197,122 @@ mod test {
             assert_eq!(deserialised, payload);
         }
         else {
-            assert!(false);
+            panic!("failed to deserialise");
         }
     }
 
+    #[test]
+    fn systemstatuspayload_deserialise_invalid_firmware() {
+        let mut serial = SystemStatusPayload::default().serialise();
+        serial[4] = 0x1A;
+
+        assert!(SystemStatusPayload::deserialise(&serial).is_err());
+    }
+
+    #[test]
+    fn systemstatuspayload_deserialise_invalid_rmserror() {
+        let mut serial = SystemStatusPayload::default().serialise();
+        serial[10..13].copy_from_slice(&[0x7F, 0xFF, 0xFF]);
+        let payload = SystemStatusPayload::deserialise(&serial).unwrap();
+        assert_eq!(u32::from(payload.rmserror.0), Pixel32768th::MAX_UNITS);
+
+        serial[10] = 0x80;
+        let error = SystemStatusPayload::deserialise(&serial).unwrap_err();
+        assert_eq!(error.description, "Misformed RMS error 0x80ffff, bit 23 must be clear");
+    }
+
+    #[test]
+    fn switchsettings_round_trip() {
+        for byte in 0..=u8::MAX {
+            let flags = SwitchSettingFlags::from_bits_retain(byte);
+            let both = flags.contains(SwitchSettingFlags::S2_LEFT | SwitchSettingFlags::S2_RIGHT);
+
+            match SwitchSettings::try_from(byte) {
+                Ok(settings) => assert_eq!(u8::from(settings), byte),
+                Err(_) => assert!(both),
+            }
+        }
+    }
+
+    #[test]
+    fn message_with_command() {
+        let target = Payloads::TargetDataPayload(TargetDataPayload { studioid: 3, ..TargetDataPayload::default() });
+        let data = Message::with_command(target, Commands::NEXT_TARGET, 2).unwrap().serialise();
+        assert_eq!(data[0], Commands::NEXT_TARGET as u8);
+        let decoded = deserialise_payloads(&data).unwrap();
+        assert_eq!(decoded.command(), Commands::NEXT_TARGET);
+        assert_eq!(decoded.payload, target);
+
+        let image = Payloads::ImageDataPayload(ImageDataPayload::default());
+        assert_eq!(Message::with_command(image, Commands::FIRST_IMAGE, 1).unwrap().serialise()[0], Commands::FIRST_IMAGE as u8);
+        let error = Message::with_command(image, Commands::NEXT_TARGET, 1).unwrap_err();
+        assert_eq!(error.to_string(), "Only First Image or Next Image commands may be used with the Image Data Payload payload");
+
+        let position = Payloads::PositionPollPayload(PositionPollPayload::default());
+        assert!(Message::with_command(position, Commands::POSITION_POLL, 1).is_ok());
+        assert!(Message::with_command(position, Commands::SYSTEM_STATUS, 1).is_err());
+    }
+
+    #[test]
+    fn command_and_cameraid_text() {
+        assert_eq!("D1".parse::<Commands>().unwrap(), Commands::POSITION_POLL);
+        assert_eq!("0xdb".parse::<Commands>().unwrap(), Commands::DIAGNOSTIC_MODE);
+        assert!("D1D1".parse::<Commands>().is_err());
+        assert!("E0".parse::<Commands>().is_err());
+
+        assert_eq!(parse_cameraid("12").unwrap(), 12);
+        assert_eq!(parse_cameraid("0xFF").unwrap(), ALL_CAMERAS);
+        assert!(parse_cameraid("256").is_err());
+
+        assert_eq!(hex(&[0xD1, 0x01, 0x0F]), "D1 01 0F");
+        assert_eq!(hex(&[]), "");
+    }
+
+    #[test]
+    fn switchsettings_decode() {
+        let settings = SwitchSettings::try_from(0b11011010).unwrap();
+
+        assert_eq!(settings.s5, 0xA);
+        assert_eq!(settings.s2, SwitchPosition::Left);
+        assert_eq!(settings.s3, SwitchSide::Right);
+        assert!(settings.s4closed);
+
+        let settings = SwitchSettings::try_from(0b00100000).unwrap();
+        assert_eq!(settings.s2, SwitchPosition::Right);
+        assert_eq!(settings.s3, SwitchSide::Left);
+        assert!(!settings.s4closed);
+
+        assert_eq!(SwitchSettings::try_from(0).unwrap(), SwitchSettings::default());
+    }
+
+    #[test]
+    fn firmwareversion_bcd() {
+        let version = FirmwareVersion::try_from(0x12).unwrap();
+        assert_eq!(version.to_string(), "1.2");
+        assert_eq!(u8::from(version), 0x12);
+        assert_eq!("1.2".parse::<FirmwareVersion>().unwrap(), version);
+
+        assert!(FirmwareVersion::try_from(0x0A).is_err());
+        assert!(FirmwareVersion::try_from(0xF1).is_err());
+        assert!("1.10".parse::<FirmwareVersion>().is_err());
+        assert!("12".parse::<FirmwareVersion>().is_err());
+    }
+
+    #[test]
+    fn rmserror_pixels() {
+        //bits 22 to 15 are the integer part, bits 14 to 0 the fraction
+        let rmserror = Pixel32768th(u24::new(3 << 15 | 1 << 14));
+        assert_eq!(rmserror.pixels(), 3.5);
+
+        assert_eq!(Pixel32768th::from_pixels(3.5).unwrap(), rmserror);
+        assert_eq!(Pixel32768th::from_pixels(0.0).unwrap(), Pixel32768th::default());
+        assert!(Pixel32768th::from_pixels(-0.5).is_err());
+        assert!(Pixel32768th::from_pixels(1024.0).is_err());
+        assert_eq!(Pixel32768th::from_pixels(255.99).unwrap().pixels(), (255.99 * 32768.0_f64).round() / 32768.0);
+        assert!(Pixel32768th::from_pixels(256.0).is_err());
+        assert!(Pixel32768th::from_pixels(300.0).is_err());
+    }
+
     #[test]
     fn systemcontrolpayload_serialise() {
         let payload = SystemControlPayload {
@@ -1023,7 +1354,7 @@ mod test {
             assert_eq!(deserialised, payload);
         }
         else {
-            assert!(false);
+            panic!("failed to deserialise");
         }
     }
 
@@ -1066,7 +1397,7 @@ mod test {
             assert_eq!(payload, deserialised);
         }
         else {
-            assert!(false);
+            panic!("failed to deserialise");
         }
     }
 
@@ -1101,7 +1432,7 @@ mod test {
             assert_eq!(deserialised, payload);
         }
         else {
-            assert!(false);
+            panic!("failed to deserialise");
         }
 
     }
@@ -1134,7 +1465,7 @@ mod test {
             assert_eq!(deserialised, payload);
         }    
         else {
-            assert!(false)
+            panic!("failed to deserialise");
         }
     }
 
@@ -1163,7 +1494,7 @@ mod test {
             assert_eq!(deserialise, payload);
         }
         else {
-            assert!(false);
+            panic!("failed to deserialise");
         }
     }
 
@@ -1206,7 +1537,7 @@ mod test {
             assert_eq!(deserialise, payload);
         }
         else {
-            assert!(false);
+            panic!("failed to deserialise");
         }
     }
 
@@ -1237,7 +1568,7 @@ mod test {
             assert_eq!(payload, deserialise);
         }
         else {
-            assert!(false);
+            panic!("failed to deserialise");
         }
     }
 
@@ -1317,11 +1648,49 @@ mod test {
             assert_eq!(deserialised, payload);
         }
         else {
-            assert!(false);
+            panic!("failed to deserialise");
         }
 
     }
 
+    #[test]
+    fn positionpollpayload_axes() {
+        let mut payload = PositionPollPayload::default();
+        payload.set(Axis::Yaw, -1000.4);
+        payload.set(Axis::Zoom, -5.0);
+        payload.set(Axis::X, 1.0e9);
+
+        assert_eq!(payload.yaw, i24::new(-1000));
+        assert_eq!(payload.zoom, u24::new(0));
+        assert_eq!(payload.pos_x, i24::MAX);
+        assert_eq!(payload.get(Axis::Yaw), -1000.0);
+    }
+
+    #[test]
+    fn angle_wrapping() {
+        let degree = 32768.0;
+
+        assert_eq!(wrap_angle(190.0 * degree), -170.0 * degree);
+        assert_eq!(wrap_angle(-190.0 * degree), 170.0 * degree);
+        assert_eq!(angle_difference(170.0 * degree, -170.0 * degree), 20.0 * degree);
+        assert_eq!(angle_difference(-170.0 * degree, 170.0 * degree), -20.0 * degree);
+    }
+
+    #[test]
+    fn pose_interpolation() {
+        let degree = 32768.0;
+        let mut from = PositionPollPayload::default();
+        let mut to = PositionPollPayload::default();
+        from.set(Axis::Yaw, 170.0 * degree);
+        to.set(Axis::Yaw, -170.0 * degree);
+        to.set(Axis::Zoom, 1000.0);
+
+        let halfway = from.interpolate(&to, 0.5);
+        assert_eq!(halfway.get(Axis::Yaw).abs(), 180.0 * degree);
+        assert_eq!(halfway.get(Axis::Zoom), 500.0);
+        assert_eq!(from.interpolate(&to, 0.25).get(Axis::Yaw), 175.0 * degree);
+    }
+
     #[test]
     fn message_new() {
         let inpayload = PositionPollPayload::default();
@@ -1359,6 +1728,21 @@ mod test {
         assert_eq!(newpayload.zoom, testzoom);
     }
 
+    #[test]
+    fn message_deserialise_payloads() {
+        let status = SystemStatusPayload { numtargetsused: 6, ..SystemStatusPayload::default() };
+        let serial = Message::new(status, 3).serialise();
+
+        let deserialised = deserialise_payloads(&serial).unwrap();
+        assert_eq!(deserialised.command(), Commands::SYSTEM_STATUS);
+        assert_eq!(deserialised.cameraid, 3);
+        assert_eq!(deserialised.payload, Payloads::SystemStatusPayload(status));
+
+        //re-serialising the wrapped payload gives back the same bytes
+        assert_eq!(Message::new(deserialised.payload, 3).serialise(), serial);
+        assert_eq!(serial.len(), Commands::SYSTEM_STATUS.message_length().unwrap());
+    }
+
     #[test]
     fn checksum_simple() {
         let array: [u8; 5] = [1, 2, 3, 4, 5];
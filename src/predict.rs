@@ -0,0 +1,269 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::payloads::*;
+use crate::router::Stage;
+
+///Recent samples from one camera. Rotations are stored unwrapped so a fit never sees a jump of a full turn.
+#[derive(Clone, Debug, Default)]
+struct History {
+    samples: VecDeque<(Instant, [f64; 8])>,
+}
+
+impl History {
+    fn push(&mut self, payload: &PositionPollPayload, received: Instant, window: usize, max_gap: Duration) {
+        if let Some((last, _)) = self.samples.back() {
+            match received.checked_duration_since(*last) {
+                Some(gap) if gap <= max_gap => {}
+                //a gap or samples arriving out of order make the history meaningless
+                _ => self.samples.clear(),
+            }
+        }
+
+        let mut values = [0.0; 8];
+        for axis in Axis::ALL {
+            let index = axis.index();
+            let value = payload.get(axis);
+            values[index] = match self.samples.back() {
+                Some((_, previous)) if axis.is_rotation() => previous[index] + angle_difference(previous[index], value),
+                _ => value,
+            };
+        }
+
+        self.samples.push_back((received, values));
+        while self.samples.len() > window.max(2) {
+            self.samples.pop_front();
+        }
+    }
+}
+
+///Least squares fit of `values` against `times`, returning the velocity and acceleration at time zero. A quadratic
+/// is fitted when there are at least three samples, otherwise a straight line.
+fn fit(times: &[f64], values: &[f64]) -> (f64, f64) {
+    if times.len() < 2 {
+        return (0.0, 0.0);
+    }
+
+    //sums of t^k for k = 0..4, and of x t^k for k = 0..2
+    let mut t = [0.0; 5];
+    let mut xt = [0.0; 3];
+    for (time, value) in times.iter().zip(values) {
+        let mut power = 1.0;
+        for k in 0..5 {
+            t[k] += power;
+            if k < 3 {
+                xt[k] += value * power;
+            }
+            power *= time;
+        }
+    }
+
+    if times.len() >= 3 {
+        let determinant = |m: [[f64; 3]; 3]| {
+            m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1]) - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+                + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+        };
+        let normal = [[t[0], t[1], t[2]], [t[1], t[2], t[3]], [t[2], t[3], t[4]]];
+        let d = determinant(normal);
+
+        if d.abs() > f64::EPSILON * t[4].abs().max(1.0) {
+            let mut c1 = normal;
+            let mut c2 = normal;
+            for row in 0..3 {
+                c1[row][1] = xt[row];
+                c2[row][2] = xt[row];
+            }
+            return (determinant(c1) / d, 2.0 * determinant(c2) / d);
+        }
+    }
+
+    let d = t[0] * t[2] - t[1] * t[1];
+    if d.abs() <= f64::EPSILON {
+        return (0.0, 0.0);
+    }
+    ((t[0] * xt[1] - t[1] * xt[0]) / d, 0.0)
+}
+
+///Compensates for latency downstream of the tracker by forward-predicting each camera's pose.
+///
+/// The velocity and acceleration of every axis are fitted to the last `window` `PositionPollPayload`s from a camera,
+/// using their arrival times, and each pose is extrapolated `lead` into the future. Prediction is clamped so the
+/// camera is never predicted to overshoot a stop: when the latest movement is much slower than the fit
+/// (`stop_ratio`) or in the opposite direction the latest pose is held, and deceleration is only extrapolated up to
+/// the point the axis would come to rest. History is discarded after a gap longer than `max_gap`.
+///
+/// Each camera id is predicted independently. Messages other than `PositionPollPayload` pass through unchanged.
+///
+/// ```rust,ignore
+/// let mut router = Router::new(routes);
+/// router.add_stage(Box::new(PosePredictor::new(Duration::from_millis(40))));
+/// ```
+#[derive(Clone, Debug)]
+pub struct PosePredictor {
+    pub lead: Duration,
+    pub window: usize,
+    pub stop_ratio: f64,
+    pub max_gap: Duration,
+    pub axes: Vec<Axis>,
+    cameras: BTreeMap<u8, History>,
+}
+
+impl PosePredictor {
+    ///A predictor extrapolating every axis by `lead`, fitted to the last 5 samples.
+    pub fn new(lead: Duration) -> PosePredictor {
+        PosePredictor {
+            lead,
+            window: 5,
+            stop_ratio: 0.25,
+            max_gap: Duration::from_millis(250),
+            axes: Axis::ALL.to_vec(),
+            cameras: BTreeMap::new(),
+        }
+    }
+
+    ///Forgets the history of every camera.
+    pub fn reset(&mut self) {
+        self.cameras.clear();
+    }
+
+    ///Records a pose from `cameraid` received at `received`, and returns it extrapolated by `lead`.
+    pub fn predict(&mut self, cameraid: u8, payload: &PositionPollPayload, received: Instant) -> PositionPollPayload {
+        let history = self.cameras.entry(cameraid).or_default();
+        history.push(payload, received, self.window, self.max_gap);
+
+        let (latest, _) = *history.samples.back().expect("Just pushed");
+        let times: Vec<f64> = history.samples.iter()
+            .map(|(time, _)| -latest.duration_since(*time).as_secs_f64())
+            .collect();
+        let lead = self.lead.as_secs_f64();
+
+        let mut output = *payload;
+        for axis in &self.axes {
+            let index = axis.index();
+            let values: Vec<f64> = history.samples.iter().map(|(_, x)| x[index]).collect();
+            let current = values[values.len() - 1];
+            let (velocity, acceleration) = fit(&times, &values);
+
+            let displacement = match values.len() {
+                0 | 1 => 0.0,
+                n => {
+                    let recent = (current - values[n - 2]) / (times[n - 1] - times[n - 2]).max(f64::EPSILON);
+                    if recent * velocity <= 0.0 || recent.abs() < self.stop_ratio * velocity.abs() {
+                        0.0
+                    } else {
+                        let mut lead = lead;
+                        if acceleration * velocity < 0.0 {
+                            lead = lead.min(-velocity / acceleration);
+                        }
+                        velocity * lead + 0.5 * acceleration * lead * lead
+                    }
+                }
+            };
+
+            let predicted = current + displacement;
+            output.set(*axis, if axis.is_rotation() { wrap_angle(predicted) } else { predicted });
+        }
+
+        output
+    }
+}
+
+impl Stage for PosePredictor {
+    fn process(&mut self, message: Message<Payloads>, received: Instant) -> Vec<Message<Payloads>> {
+        let mut message = message;
+        if let Payloads::PositionPollPayload(payload) = message.payload {
+            message.payload = Payloads::PositionPollPayload(self.predict(message.cameraid, &payload, received));
+        }
+        vec![message]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::*;
+
+    const DEGREE: f64 = 32768.0;
+    const FRAME: Duration = Duration::from_millis(20);
+
+    fn pose(axis: Axis, value: f64) -> PositionPollPayload {
+        let mut payload = PositionPollPayload::default();
+        payload.set(axis, value);
+        payload
+    }
+
+    #[test]
+    fn constant_velocity() {
+        let mut predictor = PosePredictor::new(2 * FRAME);
+        let start = Instant::now();
+        let mut output = PositionPollPayload::default();
+
+        for frame in 0..10 {
+            output = predictor.predict(1, &pose(Axis::Yaw, frame as f64 * DEGREE), start + FRAME * frame);
+        }
+        assert!((output.get(Axis::Yaw) - 11.0 * DEGREE).abs() <= 2.0, "{}", output.get(Axis::Yaw) / DEGREE);
+        assert_eq!(output.get(Axis::Pitch), 0.0);
+    }
+
+    #[test]
+    fn constant_acceleration() {
+        let mut predictor = PosePredictor::new(2 * FRAME);
+        let start = Instant::now();
+        let mut output = PositionPollPayload::default();
+
+        let position = |frame: u32| 0.5 * 64000.0 * (frame as f64 * 0.02).powi(2);
+        for frame in 0..10 {
+            output = predictor.predict(1, &pose(Axis::X, position(frame)), start + FRAME * frame);
+        }
+        assert!((output.get(Axis::X) - position(11)).abs() <= 2.0, "{} {}", output.get(Axis::X), position(11));
+    }
+
+    #[test]
+    fn sudden_stop() {
+        let mut predictor = PosePredictor::new(5 * FRAME);
+        let start = Instant::now();
+
+        for frame in 0..10 {
+            predictor.predict(1, &pose(Axis::Z, frame as f64 * 640.0), start + FRAME * frame);
+        }
+        let output = predictor.predict(1, &pose(Axis::Z, 9.0 * 640.0), start + FRAME * 10);
+        assert_eq!(output.get(Axis::Z), 9.0 * 640.0);
+
+        //braking hard is not extrapolated past the point the camera would stop
+        let mut predictor = PosePredictor::new(10 * FRAME);
+        let mut output = PositionPollPayload::default();
+        for frame in 0..8 {
+            let t = frame as f64 * 0.02;
+            output = predictor.predict(1, &pose(Axis::Z, 6400.0 * t - 20000.0 * t * t), start + FRAME * frame);
+        }
+        assert!(output.get(Axis::Z) <= 6400.0 * 0.16 - 20000.0 * 0.16 * 0.16 + 2.0, "{}", output.get(Axis::Z));
+    }
+
+    #[test]
+    fn yaw_wraparound() {
+        let mut predictor = PosePredictor::new(2 * FRAME);
+        let start = Instant::now();
+        let mut output = PositionPollPayload::default();
+
+        for frame in 0..5 {
+            output = predictor.predict(1, &pose(Axis::Yaw, (176.0 + frame as f64) * DEGREE), start + FRAME * frame);
+        }
+        //182 degrees is -178
+        assert!((output.get(Axis::Yaw) + 178.0 * DEGREE).abs() <= 2.0, "{}", output.get(Axis::Yaw) / DEGREE);
+    }
+
+    #[test]
+    fn stage_passthrough_and_cameras() {
+        let mut predictor = PosePredictor::new(2 * FRAME);
+        let start = Instant::now();
+
+        for frame in 0..5 {
+            predictor.process(Message::new(Payloads::PositionPollPayload(pose(Axis::X, frame as f64 * 100.0)), 1), start + FRAME * frame);
+        }
+        let other = predictor.process(Message::new(Payloads::PositionPollPayload(pose(Axis::X, 1000.0)), 2), start + FRAME * 5);
+        assert_eq!(other[0].serialise(), Message::new(pose(Axis::X, 1000.0), 2).serialise());
+
+        let status = Message::new(Payloads::SystemStatusPayload(SystemStatusPayload::default()), 1);
+        assert_eq!(predictor.process(status, start + FRAME * 6)[0].serialise(), status.serialise());
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+use crate::error::DeserialiseError;
+use crate::payloads::*;
+
+///Which cameras a subscriber to a `CameraRegistry` is interested in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Subscription {
+    Camera(u8),
+    All,
+}
+
+impl Subscription {
+    pub fn matches(self, cameraid: u8) -> bool {
+        match self {
+            Self::Camera(x) => x == cameraid,
+            Self::All => true,
+        }
+    }
+}
+
+///A message received from a camera, as delivered to subscribers.
+#[derive(Copy, Clone, Debug)]
+pub struct CameraUpdate {
+    pub cameraid: u8,
+    pub received: Instant,
+    pub message: Message<Payloads>,
+}
+
+///Everything the registry knows about a single camera.
+///
+/// `pose` and `status` are the most recent position and system status payloads from the camera, `packet_rate` is
+/// the number of messages per second received over the registry's `rate_window`.
+#[derive(Clone, Debug)]
+pub struct CameraState {
+    pub cameraid: u8,
+    pub pose: Option<PositionPollPayload>,
+    pub status: Option<SystemStatusPayload>,
+    pub last_seen: Instant,
+    pub packets: u64,
+    pub packet_rate: f64,
+    arrivals: VecDeque<Instant>,
+}
+
+impl CameraState {
+    fn new(cameraid: u8, received: Instant) -> CameraState {
+        CameraState { cameraid, pose: None, status: None, last_seen: received, packets: 0, packet_rate: 0.0, arrivals: VecDeque::new() }
+    }
+}
+
+///Demultiplexes messages from several cameras sharing one stream by their `cameraid`, keeping the latest state of
+/// each camera and forwarding messages to subscribers of individual cameras or of all of them.
+///
+/// ```rust,ignore
+/// let mut registry = CameraRegistry::new();
+/// let camera3 = registry.subscribe(Subscription::Camera(3));
+///
+/// loop {
+///     let (length, _) = socket.recv_from(&mut buffer)?;
+///     let _ = registry.ingest_bytes(&buffer[..length], Instant::now());
+/// }
+/// ```
+pub struct CameraRegistry {
+    pub rate_window: Duration,
+    cameras: BTreeMap<u8, CameraState>,
+    subscribers: Vec<(Subscription, Sender<CameraUpdate>)>,
+}
+
+impl Default for CameraRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CameraRegistry {
+    pub fn new() -> CameraRegistry {
+        CameraRegistry { rate_window: Duration::from_secs(1), cameras: BTreeMap::new(), subscribers: Vec::new() }
+    }
+
+    ///Records a message received from a camera at `received`, and forwards it to matching subscribers.
+    pub fn ingest(&mut self, message: Message<Payloads>, received: Instant) {
+        let cameraid = message.cameraid;
+        let window = self.rate_window;
+        let state = self.cameras.entry(cameraid).or_insert_with(|| CameraState::new(cameraid, received));
+
+        match message.payload {
+            Payloads::PositionPollPayload(x) => state.pose = Some(x),
+            Payloads::SystemStatusPayload(x) => state.status = Some(x),
+            _ => {}
+        }
+
+        state.last_seen = received;
+        state.packets += 1;
+        state.arrivals.push_back(received);
+        while let Some(oldest) = state.arrivals.front() {
+            match received.checked_duration_since(*oldest) {
+                Some(age) if age > window => { state.arrivals.pop_front(); }
+                _ => break,
+            }
+        }
+        state.packet_rate = state.arrivals.len() as f64 / window.as_secs_f64();
+
+        let update = CameraUpdate { cameraid, received, message };
+        //subscribers that have dropped their receiver are forgotten
+        self.subscribers.retain(|(subscription, sender)| !subscription.matches(cameraid) || sender.send(update).is_ok());
+    }
+
+    ///Deserialises a raw message and records it. Malformed messages are returned as errors and otherwise ignored.
+    pub fn ingest_bytes(&mut self, data: &[u8], received: Instant) -> Result<u8, DeserialiseError> {
+        let message = deserialise_payloads(data)?;
+        self.ingest(message, received);
+        Ok(message.cameraid)
+    }
+
+    ///Returns a channel receiving every message from the cameras matched by `subscription`.
+    pub fn subscribe(&mut self, subscription: Subscription) -> Receiver<CameraUpdate> {
+        let (sender, receiver) = channel();
+        self.subscribers.push((subscription, sender));
+        receiver
+    }
+
+    pub fn camera(&self, cameraid: u8) -> Option<&CameraState> {
+        self.cameras.get(&cameraid)
+    }
+
+    ///All cameras seen so far, in order of camera id.
+    pub fn cameras(&self) -> impl Iterator<Item = &CameraState> {
+        self.cameras.values()
+    }
+
+    ///Forgets cameras that have not been heard from for longer than `max_age`, returning their ids.
+    pub fn remove_stale(&mut self, now: Instant, max_age: Duration) -> Vec<u8> {
+        let stale: Vec<u8> = self.cameras.values()
+            .filter(|x| now.saturating_duration_since(x.last_seen) > max_age)
+            .map(|x| x.cameraid)
+            .collect();
+
+        for cameraid in &stale {
+            self.cameras.remove(cameraid);
+        }
+        stale
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::*;
+    use ux::i24;
+
+    fn pose(cameraid: u8, pan: i32) -> Message<Payloads> {
+        let payload = PositionPollPayload { yaw: i24::new(pan), ..PositionPollPayload::default() };
+        Message::new(Payloads::PositionPollPayload(payload), cameraid)
+    }
+
+    #[test]
+    fn demultiplexes_by_camera() {
+        let mut registry = CameraRegistry::new();
+        let now = Instant::now();
+
+        registry.ingest(pose(1, 100), now);
+        registry.ingest(pose(2, 200), now);
+        registry.ingest(pose(1, 150), now);
+
+        assert_eq!(registry.cameras().count(), 2);
+        assert_eq!(registry.camera(1).unwrap().pose.unwrap().yaw, i24::new(150));
+        assert_eq!(registry.camera(1).unwrap().packets, 2);
+        assert_eq!(registry.camera(2).unwrap().pose.unwrap().yaw, i24::new(200));
+        assert!(registry.camera(3).is_none());
+    }
+
+    #[test]
+    fn subscriptions() {
+        let mut registry = CameraRegistry::new();
+        let camera2 = registry.subscribe(Subscription::Camera(2));
+        let all = registry.subscribe(Subscription::All);
+        let now = Instant::now();
+
+        registry.ingest(pose(1, 100), now);
+        registry.ingest(pose(2, 200), now);
+
+        assert_eq!(camera2.try_iter().map(|x| x.cameraid).collect::<Vec<u8>>(), vec![2]);
+        assert_eq!(all.try_iter().map(|x| x.cameraid).collect::<Vec<u8>>(), vec![1, 2]);
+
+        drop(camera2);
+        registry.ingest(pose(2, 250), now);
+        assert_eq!(registry.subscribers.len(), 1);
+    }
+
+    #[test]
+    fn packet_rate_and_staleness() {
+        let mut registry = CameraRegistry::new();
+        let start = Instant::now();
+
+        for frame in 0..100 {
+            registry.ingest(pose(4, frame), start + Duration::from_millis(frame as u64 * 20));
+        }
+        let rate = registry.camera(4).unwrap().packet_rate;
+        assert!((rate - 50.0).abs() <= 1.0, "{}", rate);
+
+        let serial = Message::new(SystemStatusPayload::default(), 5).serialise();
+        assert_eq!(registry.ingest_bytes(&serial, start).unwrap(), 5);
+        assert!(registry.camera(5).unwrap().status.is_some());
+        assert!(registry.ingest_bytes(&serial[1..], start).is_err());
+
+        assert_eq!(registry.remove_stale(start + Duration::from_secs(3), Duration::from_secs(2)), vec![5]);
+        assert_eq!(registry.cameras().count(), 1);
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::VecDeque;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::payloads::*;
+
+///An exact video frame rate, as a ratio of frames to seconds, so that NTSC rates such as 24000/1001 do not drift.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FrameRate {
+    pub frames: u32,
+    pub seconds: u32,
+}
+
+impl FrameRate {
+    pub const FPS_23_976: FrameRate = FrameRate { frames: 24000, seconds: 1001 };
+    pub const FPS_24: FrameRate = FrameRate { frames: 24, seconds: 1 };
+    pub const FPS_25: FrameRate = FrameRate { frames: 25, seconds: 1 };
+    pub const FPS_29_97: FrameRate = FrameRate { frames: 30000, seconds: 1001 };
+    pub const FPS_30: FrameRate = FrameRate { frames: 30, seconds: 1 };
+    pub const FPS_50: FrameRate = FrameRate { frames: 50, seconds: 1 };
+    pub const FPS_59_94: FrameRate = FrameRate { frames: 60000, seconds: 1001 };
+    pub const FPS_60: FrameRate = FrameRate { frames: 60, seconds: 1 };
+
+    pub fn new(frames: u32, seconds: u32) -> Result<FrameRate, String> {
+        if frames == 0 || seconds == 0 {
+            return Err(format!("{}/{} is not a frame rate", frames, seconds));
+        }
+        Ok(FrameRate { frames, seconds })
+    }
+
+    pub fn fps(self) -> f64 {
+        self.frames as f64 / self.seconds as f64
+    }
+
+    ///The time from the start of frame 0 to the start of frame `frame`, exact to the nanosecond.
+    pub fn frame_time(self, frame: u64) -> Duration {
+        let nanoseconds = frame as u128 * self.seconds as u128 * 1_000_000_000 / self.frames as u128;
+        Duration::new((nanoseconds / 1_000_000_000) as u64, (nanoseconds % 1_000_000_000) as u32)
+    }
+
+    pub fn frame_duration(self) -> Duration {
+        self.frame_time(1)
+    }
+}
+
+impl Display for FrameRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.seconds {
+            1 => write!(f, "{}", self.frames),
+            1001 => write!(f, "{:.3}", self.fps()),
+            _ => write!(f, "{}/{}", self.frames, self.seconds),
+        }
+    }
+}
+
+///Parses `25`, `24000/1001`, or the usual shorthand for NTSC rates such as `23.976`, `29.97` and `59.94`.
+impl FromStr for FrameRate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("{} is not a frame rate", s);
+
+        if let Some((frames, seconds)) = s.split_once('/') {
+            return FrameRate::new(frames.trim().parse().map_err(|_| invalid())?, seconds.trim().parse().map_err(|_| invalid())?);
+        }
+        if let Ok(frames) = s.trim().parse::<u32>() {
+            return FrameRate::new(frames, 1);
+        }
+
+        let fps = s.trim().parse::<f64>().map_err(|_| invalid())?;
+        if fps.fract() == 0.0 && fps >= 1.0 && fps <= u32::MAX as f64 {
+            return FrameRate::new(fps as u32, 1);
+        }
+        let ntsc = (fps * 1.001).round();
+        if ntsc > 0.0 && (ntsc / 1.001 - fps).abs() < 0.005 {
+            return FrameRate::new((ntsc as u32).checked_mul(1000).ok_or_else(invalid)?, 1001);
+        }
+        Err(invalid())
+    }
+}
+
+///What a resampled frame was made from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FrameSource {
+    ///Interpolated between the samples either side of the frame.
+    Interpolated,
+    ///No sample arrived after the frame in time, so the last pose was repeated.
+    Held,
+    ///No sample arrived after the frame in time, so the last movement was continued.
+    Extrapolated,
+}
+
+impl FrameSource {
+    ///Whether the frame was made up to cover a gap in the input.
+    pub fn is_synthesised(self) -> bool {
+        self != FrameSource::Interpolated
+    }
+}
+
+impl Display for FrameSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            FrameSource::Interpolated => "interpolated",
+            FrameSource::Held => "held",
+            FrameSource::Extrapolated => "extrapolated",
+        })
+    }
+}
+
+///How a `Resampler` covers gaps in its input.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GapPolicy {
+    Hold,
+    Extrapolate,
+}
+
+///One output frame from a `Resampler`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub number: u64,
+    pub time: Instant,
+    pub pose: PositionPollPayload,
+    pub source: FrameSource,
+}
+
+///Resamples `PositionPollPayload`s arriving at the tracker's own, possibly irregular, rate to exactly one pose per
+/// video frame.
+///
+/// Frame `n` falls at `start` plus `n` frames at `rate`. Each frame is interpolated between the samples received
+/// either side of it, with rotations taking the short way round, and is returned by `frames` once a later sample has
+/// arrived. If none has arrived `wait` after the frame was due the frame is synthesised instead, by holding the last
+/// pose or extrapolating its movement according to `gap_policy`. Extrapolation is limited to `limit` past the last
+/// sample, after which the pose is held. Frames before the first sample are skipped.
+///
+/// ```rust,ignore
+/// let mut resampler = Resampler::new(FrameRate::FPS_59_94, Instant::now());
+///
+/// resampler.push(&pose, received);
+/// for frame in resampler.frames(Instant::now()) {
+///     send(frame.pose, frame.source.is_synthesised());
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Resampler {
+    pub rate: FrameRate,
+    pub wait: Duration,
+    pub gap_policy: GapPolicy,
+    pub limit: Duration,
+    start: Instant,
+    next: u64,
+    samples: VecDeque<(Instant, PositionPollPayload)>,
+}
+
+impl Resampler {
+    ///A resampler holding over gaps, waiting up to one frame for late samples and extrapolating at most 100ms.
+    pub fn new(rate: FrameRate, start: Instant) -> Resampler {
+        Resampler {
+            rate,
+            wait: rate.frame_duration(),
+            gap_policy: GapPolicy::Hold,
+            limit: Duration::from_millis(100),
+            start,
+            next: 0,
+            samples: VecDeque::new(),
+        }
+    }
+
+    ///The number of the next frame to be output.
+    pub fn next_frame(&self) -> u64 {
+        self.next
+    }
+
+    pub fn frame_time(&self, frame: u64) -> Instant {
+        self.start + self.rate.frame_time(frame)
+    }
+
+    ///Adds a sample received at `received`. Samples older than the newest one already added are ignored.
+    pub fn push(&mut self, payload: &PositionPollPayload, received: Instant) {
+        if self.samples.back().is_none_or(|(last, _)| received >= *last) {
+            self.samples.push_back((received, *payload));
+        }
+    }
+
+    ///Returns every frame that can be output by `now`, in order.
+    pub fn frames(&mut self, now: Instant) -> Vec<Frame> {
+        let mut frames = Vec::<Frame>::new();
+
+        loop {
+            let time = self.frame_time(self.next);
+            if time > now {
+                break;
+            }
+
+            if self.samples.is_empty() {
+                self.next += 1;
+                continue;
+            }
+
+            //keep the last sample at or before the frame, and the one before that for extrapolation
+            while self.samples.len() > 2 && self.samples[2].0 <= time {
+                self.samples.pop_front();
+            }
+
+            let before = self.samples.iter().rposition(|(sampled, _)| *sampled <= time);
+            let frame = match before {
+                //skip frames from before the first sample
+                None => Some(None),
+                Some(index) if index + 1 < self.samples.len() => {
+                    let (t0, from) = self.samples[index];
+                    let (t1, to) = self.samples[index + 1];
+                    let fraction = time.duration_since(t0).as_secs_f64() / t1.duration_since(t0).as_secs_f64();
+                    Some(Some((from.interpolate(&to, fraction), FrameSource::Interpolated)))
+                }
+                Some(index) if self.samples[index].0 == time => Some(Some((self.samples[index].1, FrameSource::Interpolated))),
+                Some(_) if now < time + self.wait => None,
+                Some(index) => Some(Some(self.synthesise(index, time))),
+            };
+
+            match frame {
+                //wait for a later sample
+                None => break,
+                Some(None) => {}
+                Some(Some((pose, source))) => frames.push(Frame { number: self.next, time, pose, source }),
+            }
+            self.next += 1;
+        }
+
+        frames
+    }
+
+    fn synthesise(&self, index: usize, time: Instant) -> (PositionPollPayload, FrameSource) {
+        let (t1, last) = self.samples[index];
+        if self.gap_policy == GapPolicy::Hold || index == 0 {
+            return (last, FrameSource::Held);
+        }
+
+        let (t0, previous) = self.samples[index - 1];
+        let interval = t1.duration_since(t0).as_secs_f64();
+        let gap = time.duration_since(t1);
+        if interval <= 0.0 {
+            return (last, FrameSource::Held);
+        }
+        if gap > self.limit {
+            let limit = t1 + self.limit;
+            return (previous.interpolate(&last, 1.0 + limit.duration_since(t1).as_secs_f64() / interval), FrameSource::Held);
+        }
+
+        (previous.interpolate(&last, 1.0 + gap.as_secs_f64() / interval), FrameSource::Extrapolated)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DEGREE: f64 = 32768.0;
+
+    fn pose(axis: Axis, value: f64) -> PositionPollPayload {
+        let mut payload = PositionPollPayload::default();
+        payload.set(axis, value);
+        payload
+    }
+
+    #[test]
+    fn frame_rates() {
+        assert_eq!(FrameRate::FPS_23_976.frame_time(24000), Duration::from_secs(1001));
+        assert_eq!(FrameRate::FPS_25.frame_duration(), Duration::from_millis(40));
+        assert_eq!("23.976".parse::<FrameRate>().unwrap(), FrameRate::FPS_23_976);
+        assert_eq!("59.94".parse::<FrameRate>().unwrap(), FrameRate::FPS_59_94);
+        assert_eq!("50".parse::<FrameRate>().unwrap(), FrameRate::FPS_50);
+        assert_eq!("30000/1001".parse::<FrameRate>().unwrap(), FrameRate::FPS_29_97);
+        assert_eq!(FrameRate::FPS_29_97.to_string(), "29.970");
+        assert_eq!("25.0".parse::<FrameRate>().unwrap(), FrameRate::FPS_25);
+        assert!("0".parse::<FrameRate>().is_err());
+        assert!("24.5".parse::<FrameRate>().is_err());
+        assert!("4290677.3227".parse::<FrameRate>().is_err());
+    }
+
+    #[test]
+    fn irregular_input() {
+        let start = Instant::now();
+        let mut resampler = Resampler::new(FrameRate::FPS_25, start);
+
+        //a ramp of 1000 units per second, sampled at uneven intervals
+        let mut frames = Vec::new();
+        let mut time = 0;
+        for step in [7, 13, 17, 9, 21, 11, 16, 19, 8, 14].iter().cycle().take(30) {
+            resampler.push(&pose(Axis::X, time as f64), start + Duration::from_millis(time));
+            frames.extend(resampler.frames(start + Duration::from_millis(time)));
+            time += step;
+        }
+
+        assert_eq!(frames.len(), 10);
+        for (number, frame) in frames.iter().enumerate() {
+            assert_eq!(frame.number, number as u64);
+            assert_eq!(frame.source, FrameSource::Interpolated);
+            assert_eq!(frame.pose.get(Axis::X), number as f64 * 40.0);
+        }
+    }
+
+    #[test]
+    fn shortest_path() {
+        let start = Instant::now();
+        let mut resampler = Resampler::new(FrameRate::FPS_50, start + Duration::from_millis(10));
+        resampler.push(&pose(Axis::Yaw, 179.0 * DEGREE), start);
+        resampler.push(&pose(Axis::Yaw, -179.0 * DEGREE), start + Duration::from_millis(20));
+
+        let frames = resampler.frames(start + Duration::from_millis(20));
+        assert_eq!(frames[0].pose.get(Axis::Yaw).abs(), 180.0 * DEGREE);
+    }
+
+    #[test]
+    fn gaps() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+
+        let mut resampler = Resampler::new(FrameRate::FPS_50, start);
+        resampler.push(&pose(Axis::X, 0.0), start);
+        resampler.push(&pose(Axis::X, 100.0), start + ms(20));
+
+        //frame 2 waits a frame for a later sample before being held
+        assert_eq!(resampler.frames(start + ms(50)).len(), 2);
+        let frames = resampler.frames(start + ms(60));
+        assert_eq!(frames.len(), 1);
+        assert_eq!((frames[0].number, frames[0].source), (2, FrameSource::Held));
+        assert_eq!(frames[0].pose.get(Axis::X), 100.0);
+
+        let mut resampler = Resampler::new(FrameRate::FPS_50, start);
+        resampler.gap_policy = GapPolicy::Extrapolate;
+        resampler.limit = ms(60);
+        resampler.push(&pose(Axis::X, 0.0), start);
+        resampler.push(&pose(Axis::X, 100.0), start + ms(20));
+
+        let frames = resampler.frames(start + ms(200));
+        let sources: Vec<FrameSource> = frames.iter().map(|x| x.source).collect();
+        let values: Vec<f64> = frames.iter().map(|x| x.pose.get(Axis::X)).collect();
+        assert_eq!(values, vec![0.0, 100.0, 200.0, 300.0, 400.0, 400.0, 400.0, 400.0, 400.0, 400.0]);
+        assert!(!sources[1].is_synthesised());
+        assert_eq!(sources[4], FrameSource::Extrapolated);
+        assert_eq!(sources[5], FrameSource::Held);
+
+        //input resuming is interpolated again
+        resampler.push(&pose(Axis::X, 1000.0), start + ms(200));
+        let frames = resampler.frames(start + ms(220));
+        assert_eq!(frames.iter().map(|x| x.source).collect::<Vec<FrameSource>>(), vec![FrameSource::Interpolated]);
+    }
+}
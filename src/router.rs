@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::common::*;
+use crate::payloads::*;
+
+///Counters kept for each `Route`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RouteCounters {
+    pub forwarded: u64,
+    pub filtered: u64,
+    pub bytes: u64,
+    pub send_errors: u64,
+}
+
+///A destination that messages are forwarded to, along with the filters and camera id remapping applied on the way.
+///
+/// `commands` and `cameras` limit the messages forwarded to those with one of the listed commands or camera ids.
+/// An empty list lets everything through. `remap` replaces camera ids before forwarding; ids not listed are kept.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Route {
+    pub destination: SocketAddr,
+    pub commands: Vec<Commands>,
+    pub cameras: Vec<u8>,
+    pub remap: BTreeMap<u8, u8>,
+    pub counters: RouteCounters,
+}
+
+impl Route {
+    ///A route forwarding every message to `destination` unchanged.
+    pub fn new(destination: SocketAddr) -> Route {
+        Route { destination, commands: Vec::new(), cameras: Vec::new(), remap: BTreeMap::new(), counters: RouteCounters::default() }
+    }
+
+    pub fn accepts(&self, message: &Message<Payloads>) -> bool {
+        (self.commands.is_empty() || self.commands.contains(&message.command()))
+            && (self.cameras.is_empty() || self.cameras.contains(&message.cameraid))
+    }
+}
+
+impl Display for Route {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} forwarded, {} filtered, {} bytes, {} send errors", self.destination,
+            self.counters.forwarded, self.counters.filtered, self.counters.bytes, self.counters.send_errors)
+    }
+}
+
+///A serialised message ready to be sent to the destination of route number `route`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Forward {
+    pub route: usize,
+    pub destination: SocketAddr,
+    pub data: Vec<u8>,
+}
+
+///A processing step applied to every message passing through a `Router` before it is fanned out, such as
+/// prediction or delay. Stages may change, drop or hold back messages.
+pub trait Stage {
+    ///Accepts a message received at `received`, returning the messages that should continue down the path now.
+    fn process(&mut self, message: Message<Payloads>, received: Instant) -> Vec<Message<Payloads>>;
+
+    ///Returns messages held back by the stage that have become ready by `now`. Stages that never hold messages
+    /// back can use the default, which returns nothing.
+    fn poll(&mut self, _now: Instant) -> Vec<Message<Payloads>> {
+        Vec::new()
+    }
+}
+
+///Lets a stage be adjusted, for example to change a delay, while a `Router` owns it.
+impl<S: Stage + ?Sized> Stage for Arc<Mutex<S>> {
+    fn process(&mut self, message: Message<Payloads>, received: Instant) -> Vec<Message<Payloads>> {
+        self.lock().expect("Stage lock poisoned").process(message, received)
+    }
+
+    fn poll(&mut self, now: Instant) -> Vec<Message<Payloads>> {
+        self.lock().expect("Stage lock poisoned").poll(now)
+    }
+}
+
+///Fans incoming free-d messages out to a set of `Route`s. Malformed messages, including those with a bad checksum,
+/// are counted and dropped. Messages pass through each `Stage` in turn before being fanned out. The router only
+/// decides what to send; sending is left to the caller.
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+    stages: Vec<Box<dyn Stage + Send>>,
+    pub received: u64,
+    pub malformed: u64,
+}
+
+impl Router {
+    pub fn new(routes: Vec<Route>) -> Router {
+        Router { routes, stages: Vec::new(), received: 0, malformed: 0 }
+    }
+
+    pub fn routes(&self) -> &[Route] {
+        &self.routes
+    }
+
+    ///Appends a stage to the end of the processing path.
+    pub fn add_stage(&mut self, stage: Box<dyn Stage + Send>) {
+        self.stages.push(stage);
+    }
+
+    ///Decodes a message received at `received` and returns what should be sent to each route that accepts it.
+    pub fn route(&mut self, data: &[u8], received: Instant) -> Vec<Forward> {
+        self.received += 1;
+
+        let message = match deserialise_payloads(data) {
+            Ok(x) => x,
+            Err(_) => {
+                self.malformed += 1;
+                return Vec::new();
+            }
+        };
+
+        self.route_message(message, received)
+    }
+
+    ///Forwards an already decoded message.
+    pub fn route_message(&mut self, message: Message<Payloads>, received: Instant) -> Vec<Forward> {
+        let messages = self.run_stages(0, vec![message], received);
+        messages.into_iter().flat_map(|x| self.fan_out(x)).collect()
+    }
+
+    ///Collects messages that stages have released since the last call. Should be called regularly when any stage
+    /// holds messages back.
+    pub fn poll(&mut self, now: Instant) -> Vec<Forward> {
+        let mut forwards = Vec::<Forward>::new();
+
+        for index in 0..self.stages.len() {
+            let ready = self.stages[index].poll(now);
+            let messages = self.run_stages(index + 1, ready, now);
+            forwards.extend(messages.into_iter().flat_map(|x| self.fan_out(x)));
+        }
+
+        forwards
+    }
+
+    fn run_stages(&mut self, from: usize, mut messages: Vec<Message<Payloads>>, now: Instant) -> Vec<Message<Payloads>> {
+        for stage in self.stages[from..].iter_mut() {
+            messages = messages.into_iter().flat_map(|x| stage.process(x, now)).collect();
+        }
+        messages
+    }
+
+    fn fan_out(&mut self, message: Message<Payloads>) -> Vec<Forward> {
+        let mut forwards = Vec::<Forward>::new();
+
+        for (index, route) in self.routes.iter_mut().enumerate() {
+            if !route.accepts(&message) {
+                route.counters.filtered += 1;
+                continue;
+            }
+
+            let mut outgoing = message;
+            if let Some(cameraid) = route.remap.get(&message.cameraid) {
+                outgoing.cameraid = *cameraid;
+            }
+
+            let data = outgoing.serialise();
+            route.counters.forwarded += 1;
+            route.counters.bytes += data.len() as u64;
+            forwards.push(Forward { route: index, destination: route.destination, data });
+        }
+
+        forwards
+    }
+
+    ///Records that sending a forwarded message to route number `route` failed.
+    pub fn send_failed(&mut self, route: usize) {
+        self.routes[route].counters.send_errors += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ux::i24;
+
+    fn position(cameraid: u8) -> Vec<u8> {
+        let payload = PositionPollPayload { pitch: i24::new(-1000), ..PositionPollPayload::default() };
+        Message::new(payload, cameraid).serialise()
+    }
+
+    #[test]
+    fn fan_out_and_filter() {
+        let mut everything = Route::new("127.0.0.1:5000".parse().unwrap());
+        let mut statusonly = Route::new("127.0.0.1:5001".parse().unwrap());
+        statusonly.commands = vec![Commands::SYSTEM_STATUS];
+        everything.cameras = vec![1, 2];
+        let mut router = Router::new(vec![everything, statusonly]);
+
+        let forwards = router.route(&position(1), Instant::now());
+        assert_eq!(forwards.len(), 1);
+        assert_eq!(forwards[0].route, 0);
+        assert_eq!(forwards[0].data, position(1));
+
+        assert!(router.route(&position(3), Instant::now()).is_empty());
+        assert_eq!(router.route(&Message::new(SystemStatusPayload::default(), 2).serialise(), Instant::now()).len(), 2);
+
+        assert_eq!(router.routes()[0].counters.forwarded, 2);
+        assert_eq!(router.routes()[0].counters.filtered, 1);
+        assert_eq!(router.routes()[1].counters.filtered, 2);
+    }
+
+    #[test]
+    fn remap_camera_id() {
+        let mut route = Route::new("127.0.0.1:5000".parse().unwrap());
+        route.remap.insert(1, 5);
+        let mut router = Router::new(vec![route]);
+
+        let forwards = router.route(&position(1), Instant::now());
+        let remapped: Message<PositionPollPayload> = deserialise(&forwards[0].data).unwrap();
+        assert_eq!(remapped.cameraid, 5);
+        assert_eq!(forwards[0].data, position(5));
+
+        //cameras without a mapping are passed through
+        assert_eq!(router.route(&position(2), Instant::now())[0].data, position(2));
+    }
+
+    #[test]
+    fn drop_malformed() {
+        let mut router = Router::new(vec![Route::new("127.0.0.1:5000".parse().unwrap())]);
+
+        let mut corrupt = position(1);
+        corrupt[4] ^= 0xFF;
+
+        assert!(router.route(&corrupt, Instant::now()).is_empty());
+        assert!(router.route(&[0xD1, 0x01], Instant::now()).is_empty());
+        assert_eq!(router.malformed, 2);
+        assert_eq!(router.received, 2);
+    }
+}
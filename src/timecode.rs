@@ -0,0 +1,277 @@
+use std::fmt::{self, Display};
+use std::time::Duration;
+
+use crate::resample::FrameRate;
+
+///A SMPTE timecode at a given frame rate. NTSC rates may count in drop-frame, where frame numbers 0 and 1 (0 to 3
+/// at 59.94) are skipped at the start of every minute except each tenth, keeping the timecode in step with the clock.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Timecode {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u8,
+    pub rate: FrameRate,
+    pub dropframe: bool,
+}
+
+///The number of frames counted per second, e.g. 30 for 29.97.
+fn nominal(rate: FrameRate) -> u64 {
+    (rate.frames as u64 + rate.seconds as u64 / 2) / rate.seconds as u64
+}
+
+///The number of frame numbers skipped each minute in drop-frame.
+fn dropped(rate: FrameRate) -> u64 {
+    nominal(rate) / 15
+}
+
+impl Timecode {
+    pub fn new(hours: u8, minutes: u8, seconds: u8, frames: u8, rate: FrameRate, dropframe: bool) -> Result<Timecode, String> {
+        let timecode = Timecode { hours, minutes, seconds, frames, rate, dropframe };
+
+        if dropframe && (rate.seconds != 1001 || !nominal(rate).is_multiple_of(30)) {
+            return Err(format!("drop-frame timecode is not possible at {} fps", rate));
+        }
+        if hours >= 24 || minutes >= 60 || seconds >= 60 || frames as u64 >= nominal(rate) {
+            return Err(format!("{} is not a valid timecode at {} fps", timecode, rate));
+        }
+        if dropframe && seconds == 0 && !minutes.is_multiple_of(10) && (frames as u64) < dropped(rate) {
+            return Err(format!("{} is skipped in drop-frame timecode", timecode));
+        }
+        Ok(timecode)
+    }
+
+    ///Parses `HH:MM:SS:FF`. A `;` or `.` before the frames marks drop-frame timecode.
+    pub fn parse(text: &str, rate: FrameRate) -> Result<Timecode, String> {
+        let invalid = || format!("{} is not a timecode", text);
+        let dropframe = text.contains([';', '.']);
+
+        let fields: Vec<u8> = text.split([':', ';', '.'])
+            .map(|x| x.parse::<u8>().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+        match fields[..] {
+            [hours, minutes, seconds, frames] => Timecode::new(hours, minutes, seconds, frames, rate, dropframe),
+            _ => Err(invalid()),
+        }
+    }
+
+    ///The number of frames in a day, after which timecode wraps back to midnight.
+    pub fn frames_per_day(rate: FrameRate, dropframe: bool) -> u64 {
+        let frames = nominal(rate) * 86400;
+        if dropframe { frames - dropped(rate) * 24 * 54 } else { frames }
+    }
+
+    ///The timecode of frame `frame` counted from midnight, wrapping after 24 hours.
+    pub fn from_frame_number(frame: u64, rate: FrameRate, dropframe: bool) -> Timecode {
+        let nominal = nominal(rate);
+        let mut frame = frame % Timecode::frames_per_day(rate, dropframe);
+
+        if dropframe {
+            let drop = dropped(rate);
+            let perminute = nominal * 60 - drop;
+            let pertenminutes = perminute * 10 + drop;
+            let tens = frame / pertenminutes;
+            let remainder = frame % pertenminutes;
+
+            frame += drop * 9 * tens;
+            if remainder > drop {
+                frame += drop * ((remainder - drop) / perminute);
+            }
+        }
+
+        Timecode {
+            hours: (frame / (nominal * 3600)) as u8,
+            minutes: (frame / (nominal * 60) % 60) as u8,
+            seconds: (frame / nominal % 60) as u8,
+            frames: (frame % nominal) as u8,
+            rate,
+            dropframe,
+        }
+    }
+
+    ///The number of frames since midnight.
+    pub fn frame_number(&self) -> u64 {
+        let nominal = nominal(self.rate);
+        let minutes = self.hours as u64 * 60 + self.minutes as u64;
+        let frame = (minutes * 60 + self.seconds as u64) * nominal + self.frames as u64;
+
+        if self.dropframe {
+            frame - dropped(self.rate) * (minutes - minutes / 10)
+        } else {
+            frame
+        }
+    }
+
+    ///The timecode `frames` frames later, wrapping after 24 hours.
+    pub fn add_frames(&self, frames: u64) -> Timecode {
+        Timecode::from_frame_number(self.frame_number() + frames, self.rate, self.dropframe)
+    }
+
+    ///The timecode of the frame showing `elapsed` after this one.
+    pub fn after(&self, elapsed: Duration) -> Timecode {
+        let frames = elapsed.as_nanos() * self.rate.frames as u128 / (self.rate.seconds as u128 * 1_000_000_000);
+        self.add_frames(frames as u64)
+    }
+}
+
+impl Display for Timecode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = if self.dropframe { ';' } else { ':' };
+        write!(f, "{:02}:{:02}:{:02}{}{:02}", self.hours, self.minutes, self.seconds, separator, self.frames)
+    }
+}
+
+///A decoded `PositionPollPayload::userdefined` field.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UserDefined {
+    Raw(u16),
+    FrameCounter(u16),
+    ///The seconds and frames of the timecode the pose was captured at.
+    PartialTimecode { seconds: u8, frames: u8 },
+}
+
+///An interpretation of the two `userdefined` bytes of a `PositionPollPayload`, which vendors fill in different ways.
+/// Implement this for layouts not covered here.
+///
+/// ```rust,ignore
+/// match PackedTimecodeLayout { bcd: true }.decode(payload.userdefined) {
+///     UserDefined::PartialTimecode { seconds, frames } => ...,
+///     _ => ...,
+/// }
+/// ```
+pub trait UserDefinedLayout {
+    fn decode(&self, value: u16) -> UserDefined;
+
+    ///Encodes a value, or returns `None` if it cannot be represented in this layout.
+    fn encode(&self, value: UserDefined) -> Option<u16>;
+}
+
+///Leaves the field as a bare number.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RawLayout;
+
+impl UserDefinedLayout for RawLayout {
+    fn decode(&self, value: u16) -> UserDefined {
+        UserDefined::Raw(value)
+    }
+
+    fn encode(&self, value: UserDefined) -> Option<u16> {
+        match value {
+            UserDefined::Raw(x) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+///A frame counter, incrementing by one every frame and wrapping at 65536.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FrameCounterLayout;
+
+impl UserDefinedLayout for FrameCounterLayout {
+    fn decode(&self, value: u16) -> UserDefined {
+        UserDefined::FrameCounter(value)
+    }
+
+    fn encode(&self, value: UserDefined) -> Option<u16> {
+        match value {
+            UserDefined::FrameCounter(x) | UserDefined::Raw(x) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+///Seconds in the high byte and frames in the low byte, either in binary or as BCD. Values outside 0-59 seconds or
+/// 0-99 frames decode as `Raw`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PackedTimecodeLayout {
+    pub bcd: bool,
+}
+
+impl PackedTimecodeLayout {
+    fn decode_byte(&self, byte: u8) -> Option<u8> {
+        match self.bcd {
+            true if byte >> 4 <= 9 && byte & 0x0F <= 9 => Some((byte >> 4) * 10 + (byte & 0x0F)),
+            true => None,
+            false => Some(byte),
+        }
+    }
+
+    fn encode_byte(&self, value: u8) -> u8 {
+        if self.bcd { ((value / 10) << 4) | (value % 10) } else { value }
+    }
+}
+
+impl UserDefinedLayout for PackedTimecodeLayout {
+    fn decode(&self, value: u16) -> UserDefined {
+        let [high, low] = value.to_be_bytes();
+        match (self.decode_byte(high), self.decode_byte(low)) {
+            (Some(seconds), Some(frames)) if seconds < 60 && frames < 100 => UserDefined::PartialTimecode { seconds, frames },
+            _ => UserDefined::Raw(value),
+        }
+    }
+
+    fn encode(&self, value: UserDefined) -> Option<u16> {
+        match value {
+            UserDefined::PartialTimecode { seconds, frames } if seconds < 60 && frames < 100 => {
+                Some(u16::from_be_bytes([self.encode_byte(seconds), self.encode_byte(frames)]))
+            }
+            UserDefined::Raw(x) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn non_drop_frame() {
+        let rate = FrameRate::FPS_25;
+        let timecode = Timecode::parse("01:00:00:00", rate).unwrap();
+        assert_eq!(timecode.frame_number(), 90000);
+        assert_eq!(timecode.add_frames(26).to_string(), "01:00:01:01");
+        assert_eq!(timecode.after(Duration::from_millis(1000)).to_string(), "01:00:01:00");
+        assert_eq!(Timecode::from_frame_number(Timecode::frames_per_day(rate, false), rate, false).to_string(), "00:00:00:00");
+
+        assert_eq!(Timecode::from_frame_number(24, FrameRate::FPS_23_976, false).to_string(), "00:00:01:00");
+        assert!(Timecode::parse("00:00:00:25", rate).is_err());
+        assert!(Timecode::parse("00:00:00;00", rate).is_err());
+        assert!(Timecode::parse("00:00:00", rate).is_err());
+    }
+
+    #[test]
+    fn drop_frame() {
+        let rate = FrameRate::FPS_29_97;
+        assert_eq!(Timecode::from_frame_number(1799, rate, true).to_string(), "00:00:59;29");
+        assert_eq!(Timecode::from_frame_number(1800, rate, true).to_string(), "00:01:00;02");
+        assert_eq!(Timecode::from_frame_number(17982, rate, true).to_string(), "00:10:00;00");
+        assert_eq!(Timecode::frames_per_day(rate, true), 2589408);
+        assert!(Timecode::parse("00:01:00;00", rate).is_err());
+        assert!(Timecode::parse("00:10:00;00", rate).is_ok());
+
+        assert_eq!(Timecode::from_frame_number(3600, FrameRate::FPS_59_94, true).to_string(), "00:01:00;04");
+
+        for frame in (0..Timecode::frames_per_day(rate, true)).step_by(997) {
+            let timecode = Timecode::from_frame_number(frame, rate, true);
+            assert_eq!(timecode.frame_number(), frame);
+            assert_eq!(Timecode::parse(&timecode.to_string(), rate).unwrap(), timecode);
+        }
+    }
+
+    #[test]
+    fn userdefined_layouts() {
+        assert_eq!(RawLayout.decode(0x1234), UserDefined::Raw(0x1234));
+        assert_eq!(FrameCounterLayout.decode(500), UserDefined::FrameCounter(500));
+        assert_eq!(RawLayout.encode(UserDefined::FrameCounter(1)), None);
+
+        let bcd = PackedTimecodeLayout { bcd: true };
+        assert_eq!(bcd.decode(0x5923), UserDefined::PartialTimecode { seconds: 59, frames: 23 });
+        assert_eq!(bcd.decode(0x5A23), UserDefined::Raw(0x5A23));
+        assert_eq!(bcd.encode(UserDefined::PartialTimecode { seconds: 12, frames: 7 }), Some(0x1207));
+
+        let binary = PackedTimecodeLayout { bcd: false };
+        assert_eq!(binary.decode(0x3B17), UserDefined::PartialTimecode { seconds: 59, frames: 23 });
+        assert_eq!(binary.encode(UserDefined::PartialTimecode { seconds: 60, frames: 0 }), None);
+    }
+}
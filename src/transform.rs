@@ -0,0 +1,442 @@
+use crate::payloads::*;
+
+pub type Vector3 = [f64; 3];
+///Row major, multiplying column vectors.
+pub type Matrix3 = [[f64; 3]; 3];
+///Row major, multiplying column vectors, with the translation in the last column.
+pub type Matrix4 = [[f64; 4]; 4];
+
+///Raw position units per metre. Each unit is 1/64th of a millimetre.
+pub const UNITS_PER_METRE: f64 = 64000.0;
+
+///Raw rotation units per degree.
+pub const UNITS_PER_DEGREE: f64 = 32768.0;
+
+fn multiply(a: &Matrix3, b: &Matrix3) -> Matrix3 {
+    let mut output = [[0.0; 3]; 3];
+    for (row, outputrow) in output.iter_mut().enumerate() {
+        for (column, value) in outputrow.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[row][k] * b[k][column]).sum();
+        }
+    }
+    output
+}
+
+fn apply(m: &Matrix3, v: Vector3) -> Vector3 {
+    [0, 1, 2].map(|row| m[row][0] * v[0] + m[row][1] * v[1] + m[row][2] * v[2])
+}
+
+fn transpose(m: &Matrix3) -> Matrix3 {
+    [0, 1, 2].map(|row| [0, 1, 2].map(|column| m[column][row]))
+}
+
+///The order Euler angle rotations are applied in, about fixed axes. `ZXY` rotates about Z first and Y last.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EulerOrder {
+    XYZ,
+    XZY,
+    YXZ,
+    YZX,
+    ZXY,
+    ZYX,
+}
+
+impl EulerOrder {
+    fn axes(self) -> [usize; 3] {
+        match self {
+            EulerOrder::XYZ => [0, 1, 2],
+            EulerOrder::XZY => [0, 2, 1],
+            EulerOrder::YXZ => [1, 0, 2],
+            EulerOrder::YZX => [1, 2, 0],
+            EulerOrder::ZXY => [2, 0, 1],
+            EulerOrder::ZYX => [2, 1, 0],
+        }
+    }
+}
+
+///Decomposes a rotation matrix into X, Y and Z Euler angles in degrees, applied in `order`.
+pub fn euler_angles(m: &Matrix3, order: EulerOrder) -> Vector3 {
+    let [i, j, k] = order.axes();
+    let parity = if (j + 3 - i) % 3 == 1 { 1.0 } else { -1.0 };
+
+    let mut angles = [0.0; 3];
+    angles[j] = (-parity * m[k][i]).clamp(-1.0, 1.0).asin();
+    angles[i] = (parity * m[k][j]).atan2(m[k][k]);
+    angles[k] = (parity * m[j][i]).atan2(m[i][i]);
+    angles.map(|x| x.to_degrees())
+}
+
+///A unit quaternion representing a rotation.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Quaternion {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Quaternion {
+    pub const IDENTITY: Quaternion = Quaternion { w: 1.0, x: 0.0, y: 0.0, z: 0.0 };
+
+    ///A right handed rotation of `degrees` about `axis`, which need not be normalised.
+    pub fn from_axis_angle(axis: Vector3, degrees: f64) -> Quaternion {
+        let length = (axis[0] * axis[0] + axis[1] * axis[1] + axis[2] * axis[2]).sqrt();
+        let (sin, cos) = (degrees.to_radians() / 2.0).sin_cos();
+        Quaternion { w: cos, x: axis[0] / length * sin, y: axis[1] / length * sin, z: axis[2] / length * sin }
+    }
+
+    ///The rotation applying `other` first, then `self`.
+    pub fn multiply(&self, other: &Quaternion) -> Quaternion {
+        Quaternion {
+            w: self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
+            x: self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            y: self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            z: self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w,
+        }
+    }
+
+    pub fn conjugate(&self) -> Quaternion {
+        Quaternion { w: self.w, x: -self.x, y: -self.y, z: -self.z }
+    }
+
+    pub fn rotate(&self, v: Vector3) -> Vector3 {
+        apply(&self.matrix(), v)
+    }
+
+    pub fn matrix(&self) -> Matrix3 {
+        let Quaternion { w, x, y, z } = *self;
+        [
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y)],
+            [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x)],
+            [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y)],
+        ]
+    }
+
+    ///The quaternion of a rotation matrix, with a non-negative `w`.
+    pub fn from_matrix(m: &Matrix3) -> Quaternion {
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        let q = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Quaternion { w: s / 4.0, x: (m[2][1] - m[1][2]) / s, y: (m[0][2] - m[2][0]) / s, z: (m[1][0] - m[0][1]) / s }
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+            Quaternion { w: (m[2][1] - m[1][2]) / s, x: s / 4.0, y: (m[0][1] + m[1][0]) / s, z: (m[0][2] + m[2][0]) / s }
+        } else if m[1][1] > m[2][2] {
+            let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+            Quaternion { w: (m[0][2] - m[2][0]) / s, x: (m[0][1] + m[1][0]) / s, y: s / 4.0, z: (m[1][2] + m[2][1]) / s }
+        } else {
+            let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+            Quaternion { w: (m[1][0] - m[0][1]) / s, x: (m[0][2] + m[2][0]) / s, y: (m[1][2] + m[2][1]) / s, z: s / 4.0 }
+        };
+
+        if q.w < 0.0 { Quaternion { w: -q.w, x: -q.x, y: -q.y, z: -q.z } } else { q }
+    }
+}
+
+///A position and rotation, as a 4x4 transform when needed.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3,
+    pub rotation: Quaternion,
+}
+
+impl Transform {
+    pub fn matrix(&self) -> Matrix3 {
+        self.rotation.matrix()
+    }
+
+    pub fn transform(&self) -> Matrix4 {
+        let m = self.matrix();
+        let t = self.translation;
+        [
+            [m[0][0], m[0][1], m[0][2], t[0]],
+            [m[1][0], m[1][1], m[1][2], t[1]],
+            [m[2][0], m[2][1], m[2][2], t[2]],
+            [0.0, 0.0, 0.0, 1.0],
+        ]
+    }
+}
+
+///A camera pose in free-d's frame: X to the right, Y forward into the studio and Z up, in metres. At zero rotation
+/// the camera looks along +Y with +Z up. Pan turns the camera to the right, tilt raises it and roll turns it
+/// clockwise as seen from behind, applied in that order. Zoom and focus are kept as the raw encoder values.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Pose {
+    pub position: Vector3,
+    pub rotation: Quaternion,
+    pub zoom: u32,
+    pub focus: u32,
+}
+
+impl Pose {
+    ///The rotation of a camera panned, tilted and rolled by the given number of degrees.
+    pub fn rotation_from_euler(pan: f64, tilt: f64, roll: f64) -> Quaternion {
+        Quaternion::from_axis_angle([0.0, 0.0, 1.0], -pan)
+            .multiply(&Quaternion::from_axis_angle([1.0, 0.0, 0.0], tilt))
+            .multiply(&Quaternion::from_axis_angle([0.0, 1.0, 0.0], roll))
+    }
+
+    pub fn from_payload(payload: &PositionPollPayload) -> Pose {
+        Pose {
+            position: [Axis::X, Axis::Y, Axis::Z].map(|x| payload.get(x) / UNITS_PER_METRE),
+            rotation: Pose::rotation_from_euler(
+                payload.get(Axis::Yaw) / UNITS_PER_DEGREE,
+                payload.get(Axis::Pitch) / UNITS_PER_DEGREE,
+                payload.get(Axis::Roll) / UNITS_PER_DEGREE,
+            ),
+            zoom: payload.get(Axis::Zoom) as u32,
+            focus: payload.get(Axis::Focus) as u32,
+        }
+    }
+
+    ///The pan, tilt and roll of the pose in degrees. Tilts of exactly +/-90 degrees are reported with no roll.
+    pub fn euler(&self) -> (f64, f64, f64) {
+        let m = self.rotation.matrix();
+        let tilt = m[2][1].clamp(-1.0, 1.0).asin();
+        if m[2][1].abs() > 1.0 - 1e-12 {
+            return (-m[1][0].atan2(m[0][0]).to_degrees(), tilt.to_degrees(), 0.0);
+        }
+        let pan = -(-m[0][1]).atan2(m[1][1]);
+        let roll = (-m[2][0]).atan2(m[2][2]);
+        (pan.to_degrees(), tilt.to_degrees(), roll.to_degrees())
+    }
+
+    ///Writes the pose back into a payload, keeping the other fields of `template`.
+    pub fn to_payload(&self, template: &PositionPollPayload) -> PositionPollPayload {
+        let (pan, tilt, roll) = self.euler();
+        let mut payload = *template;
+        payload.set(Axis::Yaw, pan * UNITS_PER_DEGREE);
+        payload.set(Axis::Pitch, tilt * UNITS_PER_DEGREE);
+        payload.set(Axis::Roll, roll * UNITS_PER_DEGREE);
+        payload.set(Axis::X, self.position[0] * UNITS_PER_METRE);
+        payload.set(Axis::Y, self.position[1] * UNITS_PER_METRE);
+        payload.set(Axis::Z, self.position[2] * UNITS_PER_METRE);
+        payload.set(Axis::Zoom, self.zoom as f64);
+        payload.set(Axis::Focus, self.focus as f64);
+        payload
+    }
+
+    ///The pose measured from a studio origin instead of the tracking system's own.
+    pub fn relative_to(&self, origin: &Origin) -> Pose {
+        let inverse = origin.rotation.conjugate();
+        let offset = [0, 1, 2].map(|x| self.position[x] - origin.position[x]);
+        Pose { position: inverse.rotate(offset), rotation: inverse.multiply(&self.rotation), ..*self }
+    }
+
+    ///The camera's transform in another coordinate system.
+    pub fn convert(&self, convention: &Convention) -> Transform {
+        let world = convention.world.matrix();
+        let camera = convention.camera.matrix();
+        let rotation = multiply(&multiply(&world, &self.rotation.matrix()), &transpose(&camera));
+        let position = apply(&world, self.position).map(|x| x * convention.units_per_metre);
+        Transform { translation: position, rotation: Quaternion::from_matrix(&rotation) }
+    }
+}
+
+///Where free-d's right, forward and up directions point in another coordinate system, as unit vectors.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Basis {
+    pub right: Vector3,
+    pub forward: Vector3,
+    pub up: Vector3,
+}
+
+impl Basis {
+    pub const FREED: Basis = Basis { right: [1.0, 0.0, 0.0], forward: [0.0, 1.0, 0.0], up: [0.0, 0.0, 1.0] };
+
+    ///The matrix taking free-d vectors into this basis.
+    pub fn matrix(&self) -> Matrix3 {
+        [0, 1, 2].map(|row| [self.right[row], self.forward[row], self.up[row]])
+    }
+
+    pub fn is_right_handed(&self) -> bool {
+        let m = self.matrix();
+        let determinant = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1]) - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+        determinant > 0.0
+    }
+}
+
+///A target coordinate system. `world` maps the studio's directions, and `camera` maps the camera's own right,
+/// forward and up directions onto its local axes, since many engines have cameras look down an axis other than
+/// forward. Positions are scaled by `units_per_metre`. Both bases must have the same handedness.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Convention {
+    pub world: Basis,
+    pub camera: Basis,
+    pub units_per_metre: f64,
+}
+
+impl Convention {
+    ///Free-d's own frame in metres.
+    pub const FREED: Convention = Convention { world: Basis::FREED, camera: Basis::FREED, units_per_metre: 1.0 };
+
+    ///Z up, left handed, X forward and Y right, in centimetres. Cameras look along +X.
+    pub const UNREAL: Convention = Convention {
+        world: Basis { right: [0.0, 1.0, 0.0], forward: [1.0, 0.0, 0.0], up: [0.0, 0.0, 1.0] },
+        camera: Basis { right: [0.0, 1.0, 0.0], forward: [1.0, 0.0, 0.0], up: [0.0, 0.0, 1.0] },
+        units_per_metre: 100.0,
+    };
+
+    ///Y up, left handed, Z forward, in metres. Cameras look along +Z.
+    pub const UNITY: Convention = Convention {
+        world: Basis { right: [1.0, 0.0, 0.0], forward: [0.0, 0.0, 1.0], up: [0.0, 1.0, 0.0] },
+        camera: Basis { right: [1.0, 0.0, 0.0], forward: [0.0, 0.0, 1.0], up: [0.0, 1.0, 0.0] },
+        units_per_metre: 1.0,
+    };
+
+    ///Z up, right handed, Y forward, in metres. Cameras look along -Z with +Y up.
+    pub const BLENDER: Convention = Convention {
+        world: Basis::FREED,
+        camera: Basis { right: [1.0, 0.0, 0.0], forward: [0.0, 0.0, -1.0], up: [0.0, 1.0, 0.0] },
+        units_per_metre: 1.0,
+    };
+
+    ///Y up, right handed, -Z forward, in metres, as used by glTF, USD and OpenGL. Cameras look along -Z.
+    pub const Y_UP: Convention = Convention {
+        world: Basis { right: [1.0, 0.0, 0.0], forward: [0.0, 0.0, -1.0], up: [0.0, 1.0, 0.0] },
+        camera: Basis { right: [1.0, 0.0, 0.0], forward: [0.0, 0.0, -1.0], up: [0.0, 1.0, 0.0] },
+        units_per_metre: 1.0,
+    };
+
+    ///As `Y_UP`, in centimetres.
+    pub const MAYA: Convention = Convention { units_per_metre: 100.0, ..Convention::Y_UP };
+}
+
+///A studio origin, used to re-zero a stage in software. `position` is where the origin lies in the tracking
+/// system's frame, in metres, and `rotation` how the studio's axes are turned relative to the tracking system's.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Origin {
+    pub position: Vector3,
+    pub rotation: Quaternion,
+}
+
+impl Origin {
+    ///An origin at `position` with the studio turned `pan` degrees to the right, keeping the floor level.
+    pub fn new(position: Vector3, pan: f64) -> Origin {
+        Origin { position, rotation: Pose::rotation_from_euler(pan, 0.0, 0.0) }
+    }
+
+    ///An origin under the camera's current position, facing the way the camera is panned.
+    pub fn from_pose(pose: &Pose) -> Origin {
+        let (pan, _, _) = pose.euler();
+        Origin::new([pose.position[0], pose.position[1], 0.0], pan)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn close(a: Vector3, b: Vector3) -> bool {
+        (0..3).all(|x| (a[x] - b[x]).abs() < 1e-9)
+    }
+
+    fn payload(pan: f64, tilt: f64, roll: f64, position: Vector3) -> PositionPollPayload {
+        let mut payload = PositionPollPayload::default();
+        payload.set(Axis::Yaw, pan * UNITS_PER_DEGREE);
+        payload.set(Axis::Pitch, tilt * UNITS_PER_DEGREE);
+        payload.set(Axis::Roll, roll * UNITS_PER_DEGREE);
+        for (axis, value) in [Axis::X, Axis::Y, Axis::Z].iter().zip(position) {
+            payload.set(*axis, value * UNITS_PER_METRE);
+        }
+        payload
+    }
+
+    #[test]
+    fn freed_conventions() {
+        let forward = [0.0, 1.0, 0.0];
+        let up = [0.0, 0.0, 1.0];
+
+        let pose = Pose::from_payload(&payload(90.0, 0.0, 0.0, [0.0; 3]));
+        assert!(close(pose.rotation.rotate(forward), [1.0, 0.0, 0.0]));
+
+        let pose = Pose::from_payload(&payload(0.0, 90.0, 0.0, [0.0; 3]));
+        assert!(close(pose.rotation.rotate(forward), up));
+
+        let pose = Pose::from_payload(&payload(0.0, 0.0, 90.0, [0.0; 3]));
+        assert!(close(pose.rotation.rotate(up), [1.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn euler_round_trip() {
+        let original = payload(-120.5, 30.25, -10.0, [1.5, -2.0, 1.75]);
+        let pose = Pose::from_payload(&original);
+        let (pan, tilt, roll) = pose.euler();
+        assert!(close([pan, tilt, roll], [-120.5, 30.25, -10.0]));
+        assert_eq!(pose.to_payload(&original), original);
+
+        let rotation = Quaternion::from_matrix(&pose.rotation.matrix());
+        assert!(close([rotation.x, rotation.y, rotation.z], [pose.rotation.x, pose.rotation.y, pose.rotation.z]));
+    }
+
+    #[test]
+    fn engine_presets() {
+        let pose = Pose::from_payload(&payload(90.0, 0.0, 0.0, [1.0, 2.0, 3.0]));
+
+        let unreal = pose.convert(&Convention::UNREAL);
+        assert!(close(unreal.translation, [200.0, 100.0, 300.0]));
+        //panned right to look along free-d's +X, which is Unreal's +Y
+        assert!(close(unreal.rotation.rotate([1.0, 0.0, 0.0]), [0.0, 1.0, 0.0]));
+
+        let unity = pose.convert(&Convention::UNITY);
+        assert!(close(unity.translation, [1.0, 3.0, 2.0]));
+        assert!(close(unity.rotation.rotate([0.0, 0.0, 1.0]), [1.0, 0.0, 0.0]));
+
+        let blender = pose.convert(&Convention::BLENDER);
+        assert!(close(blender.translation, [1.0, 2.0, 3.0]));
+        assert!(close(blender.rotation.rotate([0.0, 0.0, -1.0]), [1.0, 0.0, 0.0]));
+        assert!(close(blender.rotation.rotate([0.0, 1.0, 0.0]), [0.0, 0.0, 1.0]));
+
+        let gltf = pose.convert(&Convention::Y_UP);
+        assert!(close(gltf.translation, [1.0, 3.0, -2.0]));
+        assert!(close(gltf.rotation.rotate([0.0, 0.0, -1.0]), [1.0, 0.0, 0.0]));
+
+        assert!(Convention::MAYA.world.is_right_handed());
+        assert!(!Convention::UNREAL.world.is_right_handed());
+        let matrix = pose.convert(&Convention::FREED).transform();
+        assert_eq!(matrix[3], [0.0, 0.0, 0.0, 1.0]);
+        assert!(close([matrix[0][3], matrix[1][3], matrix[2][3]], [1.0, 2.0, 3.0]));
+    }
+
+    #[test]
+    fn euler_orders() {
+        let orders = [EulerOrder::XYZ, EulerOrder::XZY, EulerOrder::YXZ, EulerOrder::YZX, EulerOrder::ZXY, EulerOrder::ZYX];
+        let angles = [20.0, -35.0, 60.0];
+
+        for order in orders {
+            let rotation = order.axes().iter().fold(Quaternion::IDENTITY, |rotation, axis| {
+                let mut direction = [0.0; 3];
+                direction[*axis] = 1.0;
+                Quaternion::from_axis_angle(direction, angles[*axis]).multiply(&rotation)
+            });
+            assert!(close(euler_angles(&rotation.matrix(), order), angles), "{:?}", order);
+        }
+    }
+
+    #[test]
+    fn studio_origin() {
+        let pose = Pose::from_payload(&payload(45.0, 10.0, 0.0, [1.0, 1.0, 1.5]));
+        let origin = Origin::from_pose(&pose);
+        let rezeroed = pose.relative_to(&origin);
+
+        assert!(close(rezeroed.position, [0.0, 0.0, 1.5]));
+        let (pan, tilt, roll) = rezeroed.euler();
+        assert!(close([pan, tilt, roll], [0.0, 10.0, 0.0]));
+
+        let straightup = Pose::from_payload(&payload(30.0, 90.0, 0.0, [0.0; 3]));
+        let (pan, tilt, roll) = straightup.euler();
+        assert!(close([pan, tilt, roll], [30.0, 90.0, 0.0]));
+
+        //a point 1m in front of a studio turned right by 90 degrees is 1m along the tracker's +X
+        let origin = Origin::new([0.0; 3], 90.0);
+        let pose = Pose::from_payload(&payload(0.0, 0.0, 0.0, [1.0, 0.0, 0.0]));
+        assert!(close(pose.relative_to(&origin).position, [0.0, 1.0, 0.0]));
+    }
+}
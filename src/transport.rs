@@ -0,0 +1,265 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+
+///The largest message defined by the protocol (`CAMERA_CALIBRATION`, 30 bytes). Receive buffers of this size
+/// will always hold a complete message.
+pub const MAX_MESSAGE_LENGTH: usize = 30;
+
+///A bidirectional link to a free-d unit. Each call to `send` transmits one serialised message, and each call to
+/// `receive` returns at most one message.
+pub trait Transport {
+    ///Sends a serialised message to the unit.
+    fn send(&mut self, data: &[u8]) -> io::Result<()>;
+
+    ///Receives a single message into `buffer`, returning the number of bytes read. If nothing arrives within `timeout`
+    /// this returns an error of kind `WouldBlock` or `TimedOut`. A timeout of `None` blocks indefinitely.
+    fn receive(&mut self, buffer: &mut [u8], timeout: Option<Duration>) -> io::Result<usize>;
+}
+
+///Returns true if the error was produced by a receive timing out, rather than a failure of the link.
+pub fn is_timeout(error: &io::Error) -> bool {
+    matches!(error.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+}
+
+///`Transport` over a UDP socket, sending to a single remote address.
+pub struct UdpTransport {
+    socket: UdpSocket,
+    remote: SocketAddr,
+}
+
+impl UdpTransport {
+    pub fn new(socket: UdpSocket, remote: SocketAddr) -> UdpTransport {
+        UdpTransport { socket, remote }
+    }
+
+    ///Binds a new socket to `local` and sends to `remote`.
+    pub fn bind(local: SocketAddr, remote: SocketAddr) -> io::Result<UdpTransport> {
+        Ok(UdpTransport::new(UdpSocket::bind(local)?, remote))
+    }
+
+    pub fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+
+    pub fn remote(&self) -> SocketAddr {
+        self.remote
+    }
+}
+
+///The interface multicast traffic is received and sent on. IPv4 interfaces are picked by address and IPv6 interfaces
+/// by index, so `Address` only applies to IPv4 groups and `Index` only to IPv6 groups.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum MulticastInterface {
+    ///Let the operating system choose, usually the interface of the default route.
+    #[default]
+    Default,
+    Address(Ipv4Addr),
+    Index(u32),
+}
+
+impl std::str::FromStr for MulticastInterface {
+    type Err = String;
+
+    ///Parses an IPv4 address, or a decimal IPv6 interface index.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(address) = s.parse::<Ipv4Addr>() {
+            return Ok(MulticastInterface::Address(address));
+        }
+        s.parse::<u32>().map(MulticastInterface::Index).map_err(|_| format!("{} is not an IPv4 address or interface index", s))
+    }
+}
+
+///Settings for sending and receiving on a multicast group. `ttl` is the number of routers multicast traffic may cross,
+/// where the default of 1 keeps it on the local network. `loopback` delivers traffic sent by this host back to it, so
+/// several processes on one machine can share a group.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MulticastOptions {
+    pub interface: MulticastInterface,
+    pub ttl: u32,
+    pub loopback: bool,
+}
+
+impl Default for MulticastOptions {
+    fn default() -> Self {
+        Self { interface: MulticastInterface::Default, ttl: 1, loopback: true }
+    }
+}
+
+fn interface_v4(interface: MulticastInterface) -> io::Result<Ipv4Addr> {
+    match interface {
+        MulticastInterface::Default => Ok(Ipv4Addr::UNSPECIFIED),
+        MulticastInterface::Address(x) => Ok(x),
+        MulticastInterface::Index(_) => Err(io::Error::new(io::ErrorKind::InvalidInput, "IPv4 multicast interfaces are picked by address")),
+    }
+}
+
+fn interface_v6(interface: MulticastInterface) -> io::Result<u32> {
+    match interface {
+        MulticastInterface::Default => Ok(0),
+        MulticastInterface::Index(x) => Ok(x),
+        MulticastInterface::Address(_) => Err(io::Error::new(io::ErrorKind::InvalidInput, "IPv6 multicast interfaces are picked by index")),
+    }
+}
+
+///Sets the outbound interface, TTL and loopback used when sending to multicast groups of the family of `group`.
+pub(crate) fn configure_multicast(socket: SockRef<'_>, group: IpAddr, options: &MulticastOptions) -> io::Result<()> {
+    match group {
+        IpAddr::V4(_) => {
+            socket.set_multicast_if_v4(&interface_v4(options.interface)?)?;
+            socket.set_multicast_ttl_v4(options.ttl)?;
+            socket.set_multicast_loop_v4(options.loopback)
+        }
+        IpAddr::V6(_) => {
+            socket.set_multicast_if_v6(interface_v6(options.interface)?)?;
+            socket.set_multicast_hops_v6(options.ttl)?;
+            socket.set_multicast_loop_v6(options.loopback)
+        }
+    }
+}
+
+pub(crate) fn join_multicast(socket: SockRef<'_>, group: IpAddr, interface: MulticastInterface) -> io::Result<()> {
+    match group {
+        IpAddr::V4(x) => socket.join_multicast_v4(&x, &interface_v4(interface)?),
+        IpAddr::V6(x) => socket.join_multicast_v6(&x, interface_v6(interface)?),
+    }
+}
+
+pub(crate) fn leave_multicast(socket: SockRef<'_>, group: IpAddr, interface: MulticastInterface) -> io::Result<()> {
+    match group {
+        IpAddr::V4(x) => socket.leave_multicast_v4(&x, &interface_v4(interface)?),
+        IpAddr::V6(x) => socket.leave_multicast_v6(&x, interface_v6(interface)?),
+    }
+}
+
+///Sets the interface, TTL and loopback a plain socket uses when sending to multicast groups of the family of `group`.
+pub fn set_multicast_options(socket: &UdpSocket, group: IpAddr, options: &MulticastOptions) -> io::Result<()> {
+    configure_multicast(SockRef::from(socket), group, options)
+}
+
+///Binds a socket to the port of `group` and joins the group on it.
+///
+/// The port can be shared, so a process can hold a socket for each group it follows, and several processes can
+/// follow the same group. Except on Windows, the socket is bound to the group address itself so it only receives that
+/// group's traffic rather than everything arriving on the port.
+pub fn bind_multicast(group: SocketAddr, options: &MulticastOptions) -> io::Result<UdpSocket> {
+    if !group.ip().is_multicast() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not a multicast group", group.ip())));
+    }
+
+    let socket = Socket::new(Domain::for_address(group), Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+    socket.set_reuse_port(true)?;
+    if group.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+
+    let local = match (cfg!(windows), group.ip()) {
+        (false, _) => group,
+        (true, IpAddr::V4(_)) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), group.port()),
+        (true, IpAddr::V6(_)) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), group.port()),
+    };
+    socket.bind(&local.into())?;
+    join_multicast(SockRef::from(&socket), group.ip(), options.interface)?;
+    configure_multicast(SockRef::from(&socket), group.ip(), options)?;
+    Ok(socket.into())
+}
+
+impl UdpTransport {
+    ///Joins a multicast group, receiving on it and sending to it. Units answering on the group are heard by every
+    /// member, so this is mostly useful for listening to tracking shared between render nodes.
+    pub fn multicast(group: SocketAddr, options: &MulticastOptions) -> io::Result<UdpTransport> {
+        Ok(UdpTransport::new(bind_multicast(group, options)?, group))
+    }
+
+    ///Sets the interface, TTL and loopback used when `remote` is a multicast group.
+    pub fn set_multicast_options(&self, options: &MulticastOptions) -> io::Result<()> {
+        set_multicast_options(&self.socket, self.remote.ip(), options)
+    }
+
+    ///Joins another multicast group on the same socket. Outside Windows, sockets from `bind_multicast` only receive
+    /// the group they were bound to, so a socket following several groups should be bound to the unspecified address.
+    pub fn join(&self, group: IpAddr, interface: MulticastInterface) -> io::Result<()> {
+        join_multicast(SockRef::from(&self.socket), group, interface)
+    }
+
+    pub fn leave(&self, group: IpAddr, interface: MulticastInterface) -> io::Result<()> {
+        leave_multicast(SockRef::from(&self.socket), group, interface)
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        self.socket.send_to(data, self.remote)?;
+        Ok(())
+    }
+
+    fn receive(&mut self, buffer: &mut [u8], timeout: Option<Duration>) -> io::Result<usize> {
+        //a zero timeout is rejected by the socket, so round it up to the shortest one it accepts
+        self.socket.set_read_timeout(timeout.map(|x| x.max(Duration::from_millis(1))))?;
+        let (length, _) = self.socket.recv_from(buffer)?;
+        Ok(length)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn multicast_groups() {
+        let options = MulticastOptions { interface: MulticastInterface::Address(Ipv4Addr::LOCALHOST), ..MulticastOptions::default() };
+        let first = "239.255.40.1:45123".parse().unwrap();
+        let second = "239.255.40.2:45123".parse().unwrap();
+
+        //two groups on the same port, each with its own socket
+        let mut receiver = UdpTransport::multicast(first, &options).unwrap();
+        let mut other = UdpTransport::multicast(second, &options).unwrap();
+        let mut sender = UdpTransport::bind("127.0.0.1:0".parse().unwrap(), first).unwrap();
+        sender.set_multicast_options(&options).unwrap();
+
+        sender.send(&[0xD1, 0x01]).unwrap();
+        let mut buffer = [0_u8; MAX_MESSAGE_LENGTH];
+        assert_eq!(receiver.receive(&mut buffer, Some(Duration::from_secs(1))).unwrap(), 2);
+        if !cfg!(windows) {
+            assert!(other.receive(&mut buffer, Some(Duration::from_millis(50))).is_err());
+        }
+
+        receiver.leave(first.ip(), options.interface).unwrap();
+        assert!(UdpTransport::multicast("127.0.0.1:45123".parse().unwrap(), &options).is_err());
+        assert!(receiver.join("ff02::1".parse().unwrap(), options.interface).is_err());
+        assert_eq!("2".parse::<MulticastInterface>(), Ok(MulticastInterface::Index(2)));
+    }
+
+    #[test]
+    fn ipv6_multicast_group() {
+        let options = MulticastOptions::default();
+        let group = "[ff15::4001]:45124".parse().unwrap();
+
+        //not every host has an IPv6 interface that carries multicast, so skip rather than fail there
+        let setup = || -> io::Result<(UdpTransport, UdpTransport)> {
+            let receiver = UdpTransport::multicast(group, &options)?;
+            let mut sender = UdpTransport::bind("[::]:0".parse().unwrap(), group)?;
+            sender.set_multicast_options(&options)?;
+            sender.send(&[0xD1, 0x02])?;
+            Ok((receiver, sender))
+        };
+        let (mut receiver, _sender) = match setup() {
+            Ok(x) => x,
+            Err(x) => {
+                eprintln!("skipping IPv6 multicast test: {}", x);
+                return;
+            }
+        };
+
+        let mut buffer = [0_u8; MAX_MESSAGE_LENGTH];
+        assert_eq!(receiver.receive(&mut buffer, Some(Duration::from_secs(1))).unwrap(), 2);
+        assert_eq!(buffer[..2], [0xD1, 0x02]);
+
+        receiver.leave(group.ip(), options.interface).unwrap();
+        assert!(UdpTransport::multicast(group, &MulticastOptions { interface: MulticastInterface::Address(Ipv4Addr::LOCALHOST), ..options }).is_err());
+    }
+}
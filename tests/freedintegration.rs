@@ -1,31 +1,56 @@
-use std::task::Poll;
-
 use freed::common::*;
 use freed::payloads::*;
-use rand::{distributions::{Distribution, Standard}, prelude::*, seq::SliceRandom};
+use rand::{prelude::*, seq::SliceRandom};
+use ux::{i24, u24};
 
 #[test]
 fn arbitrarydeserialisation() {
-    
-    let inpayload = PositionPollPayload::default();
-    let msg = Message::new(inpayload, ALL_CAMERAS);
-    let payload = msg.get_payload();
+
+    for _ in 0..100 {
+        let inpayload = arbitrary_position();
+        let serial = Message::new(inpayload, rand::random()).serialise();
+
+        assert_eq!(command_type(&serial).unwrap(), Commands::POSITION_POLL);
+
+        let msg: Message<PositionPollPayload> = deserialise(&serial).unwrap();
+        assert_eq!(msg.get_payload(), inpayload);
+    }
 }
 
+#[test]
+fn arbitraryserialisation() {
+    for _ in 0..100 {
+        let serial = arbitrary_serialisation();
+        let msg: Message<PollPayload> = deserialise(&serial).unwrap();
 
-impl Distribution<PollPayload> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> PollPayload {
-        PollPayload { command: rand::random() }
+        assert_eq!(serial[2], msg.get_payload().command as u8);
     }
 }
 
-impl Distribution<Commands> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Commands {
-        [0x00, 0x01, 0x02, 0x03, 0xD1, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, 0xDA, 0xDB].choose(&mut rand::thread_rng())
+fn arbitrary_command<R: Rng + ?Sized>(rng: &mut R) -> Commands {
+    let byte = *[0x00, 0x01, 0x02, 0x03, 0xD1, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, 0xDA, 0xDB].choose(rng).unwrap();
+    Commands::try_from(byte).unwrap()
+}
+
+fn arbitrary_position() -> PositionPollPayload {
+    let mut rng = rand::thread_rng();
+    let mut angle = || i24::new(rng.gen_range(-0x800000..0x800000));
+
+    PositionPollPayload {
+        pitch: angle(),
+        yaw: angle(),
+        roll: angle(),
+        pos_z: angle(),
+        pos_y: angle(),
+        pos_x: angle(),
+        zoom: u24::new(rand::thread_rng().gen_range(0..0x1000000)),
+        focus: u24::new(rand::thread_rng().gen_range(0..0x1000000)),
+        userdefined: rand::random(),
     }
 }
 
-///fuzzy serialisation - picks a payload and parameters at random to serialise.
+///fuzzy serialisation - picks a poll command and camera at random to serialise.
 fn arbitrary_serialisation() -> Vec<u8> {
-    
-}
\ No newline at end of file
+    let payload = PollPayload { command: arbitrary_command(&mut rand::thread_rng()) };
+    Message::new(payload, rand::random()).serialise()
+}